@@ -0,0 +1,96 @@
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+use serde::Serialize;
+use crate::http;
+use crate::report::PingReport;
+use crate::thresholds::Thresholds;
+
+/// The payload a webhook expects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// The alert itself, as a JSON object.
+    Generic,
+    Slack,
+    Discord,
+}
+
+impl FromStr for WebhookFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "generic" => Ok(WebhookFormat::Generic),
+            "slack" => Ok(WebhookFormat::Slack),
+            "discord" => Ok(WebhookFormat::Discord),
+            _ => Err(format!("unknown webhook format '{}' (expected generic, slack, or discord)", s)),
+        }
+    }
+}
+
+/// A target that went unreachable or crossed a threshold.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub target: String,
+    /// What is wrong with the target.
+    pub reason: String,
+    pub loss_percent: Option<f32>,
+    /// Average round trip time, in milliseconds.
+    pub avg: Option<f32>,
+}
+
+impl Alert {
+    /// The alert for `result`, if the target failed, answered no probe, or
+    /// exceeded `thresholds`.
+    pub fn check(target: &str, result: &io::Result<PingReport>, thresholds: &Thresholds) -> Option<Alert> {
+        let report = match result {
+            Ok(report) => report,
+            Err(err) => {
+                return Some(Alert { target: target.to_string(), reason: err.to_string(), loss_percent: None, avg: None });
+            },
+        };
+        let loss_percent = report.packets.as_ref().map(|packets| packets.loss_percent);
+        let avg = report.trips.as_ref().map(|trips| trips.avg);
+        let unreachable = match &report.packets {
+            Some(packets) => packets.received == 0,
+            None => report.pings.is_empty(),
+        };
+        let reason = if unreachable {
+            "unreachable".to_string()
+        } else {
+            let violations = thresholds.violations(report);
+            if violations.is_empty() {
+                return None;
+            }
+            violations.join(", ")
+        };
+        Some(Alert { target: target.to_string(), reason, loss_percent, avg })
+    }
+
+    fn message(&self) -> String {
+        format!("multiping alert for {}: {}", self.target, self.reason)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub url: String,
+    pub format: WebhookFormat,
+}
+
+impl Webhook {
+    /// POSTs `alert` to the webhook; any status but 2xx is an error.
+    pub async fn send(&self, alert: &Alert) -> io::Result<()> {
+        let payload = match self.format {
+            WebhookFormat::Generic => serde_json::to_value(alert)?,
+            WebhookFormat::Slack => serde_json::json!({ "text": alert.message() }),
+            WebhookFormat::Discord => serde_json::json!({ "content": alert.message() }),
+        };
+        let headers = [("Content-Type", "application/json")];
+        let status = http::post(&self.url, &headers, &payload.to_string(), Duration::from_secs(10)).await?;
+        if !(200..300).contains(&status) {
+            return Err(io::Error::other(format!("webhook answered with status {}", status)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use crate::{PingEvent, PingOptions};
+use crate::db::{self, Database};
+use crate::monitor::StateTracker;
+use crate::output::{self, Format};
+use crate::prometheus::Metrics;
+use crate::report::{rfc3339, PingReport};
+use crate::stream::Event;
+use crate::targets::{self, Target};
+
+// The page served at `/`, following the targets through the status and
+// the event stream.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+const MAX_HEADERS: usize = 8192;
+const MAX_BODY: usize = 65536;
+// How often an idle event stream gets a comment, so that proxies keep it
+// open and clients that left are noticed.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// A request to the server, read off its connection.
+pub(crate) struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Reads a request: its line, the headers that matter, and the body the
+/// `Content-Length` announces.
+pub(crate) async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Request> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 1024];
+    let end = loop {
+        if let Some(index) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break index + 4;
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || data.len() > MAX_HEADERS {
+            break data.len();
+        }
+        data.extend_from_slice(&buf[..read]);
+    };
+    let head = String::from_utf8_lossy(&data[..end]).into_owned();
+    let mut lines = head.lines();
+    let mut words = lines.next().unwrap_or("").split_whitespace();
+    let method = words.next().unwrap_or("").to_string();
+    let target = words.next().unwrap_or("");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (decode(&key.replace('+', " ")), decode(&value.replace('+', " "))))
+        .collect();
+    let mut length = 0;
+    let mut authorization = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => length = value.trim().parse::<usize>().unwrap_or(0).min(MAX_BODY),
+            "authorization" => authorization = Some(value.trim().to_string()),
+            _ => (),
+        }
+    }
+    let mut body = data[end..].to_vec();
+    while body.len() < length {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&buf[..read]);
+    }
+    body.truncate(length);
+    Ok(Request { method, path: path.to_string(), query, authorization, body })
+}
+
+impl Request {
+    /// Whether this asks for the event stream, which outlives the request.
+    pub fn wants_events(&self) -> bool {
+        self.method == "GET" && self.path.trim_end_matches('/') == "/events"
+    }
+}
+
+// Undoes the percent-encoding of a path segment or query value.
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| value.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            },
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Compares tokens in a time that does not depend on where they differ, so
+// that timing the answers does not give the token away a byte at a time.
+pub(crate) fn same_token(given: &[u8], token: &[u8]) -> bool {
+    let difference = given.iter().zip(token).fold(0, |difference, (a, b)| difference | (a ^ b));
+    std::hint::black_box(difference) == 0 && given.len() == token.len()
+}
+
+/// What the server answers with.
+pub(crate) struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: &'static str, value: &impl Serialize) -> Response {
+        let body = serde_json::to_string(value).unwrap_or_default();
+        Response { status, content_type: "application/json", body }
+    }
+
+    fn error(status: &'static str, message: &str) -> Response {
+        Response::json(status, &serde_json::json!({ "error": message }))
+    }
+}
+
+/// How a target fared in the last round it was in.
+#[derive(Debug, Clone, Default, Serialize)]
+struct Status {
+    target: String,
+    /// `up`, `degraded`, or `down`; `None` before the target's first round.
+    state: Option<String>,
+    loss_percent: Option<f32>,
+    avg: Option<f32>,
+    flaps: Option<u32>,
+    error: Option<String>,
+    /// When the round finished.
+    updated: Option<String>,
+}
+
+/// What the server shares with the monitor: the targets it pings, how they
+/// fared, and their metrics. Targets added or removed through it are taken
+/// in from the next round on.
+pub(crate) struct Api {
+    pub targets: Arc<Mutex<Vec<Target>>>,
+    metrics: Mutex<Metrics>,
+    status: Mutex<BTreeMap<String, Status>>,
+    opts: PingOptions,
+    /// The database the rounds are stored in, for their history.
+    db: Option<PathBuf>,
+    /// The token requests that change the targets have to bear.
+    token: Option<String>,
+    /// Whether the targets cannot be changed at all, as when there is no
+    /// token to guard them beyond the loopback interface.
+    read_only: bool,
+    events: broadcast::Sender<PingEvent>,
+}
+
+impl Api {
+    pub fn new(targets: Vec<Target>, metrics: Metrics, opts: PingOptions, db: Option<PathBuf>, token: Option<String>, read_only: bool, events: broadcast::Sender<PingEvent>) -> Api {
+        Api {
+            targets: Arc::new(Mutex::new(targets)),
+            metrics: Mutex::new(metrics),
+            status: Mutex::new(BTreeMap::new()),
+            opts,
+            db,
+            token,
+            read_only,
+            events,
+        }
+    }
+
+    /// Streams the events of the rounds to `stream` as Server-Sent Events,
+    /// one JSON object each as in `--stream ndjson`, until the client goes
+    /// away. `target` parameters keep only the events of those targets.
+    pub async fn stream_events<S: AsyncWrite + Unpin>(&self, request: &Request, stream: &mut S) -> io::Result<()> {
+        let mut events = self.events.subscribe();
+        let wanted: Vec<&str> = request.query.iter().filter(|(key, _)| key == "target").map(|(_, value)| value.as_str()).collect();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n")
+            .await?;
+        let mut keepalive = tokio::time::interval(KEEPALIVE);
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = keepalive.tick() => {
+                    stream.write_all(b": keepalive\n\n").await?;
+                    continue;
+                },
+            };
+            let event = match event {
+                Ok(event) => event,
+                // A client too slow to keep up misses some events.
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return Ok(()),
+            };
+            if !wanted.is_empty() && !wanted.contains(&event.target.as_str()) {
+                continue;
+            }
+            if let Some(event) = Event::from_event(&event) {
+                stream.write_all(format!("data: {}\n\n", event.to_json()).as_bytes()).await?;
+            }
+        }
+    }
+
+    /// Takes in a round, leaving out the targets removed while it ran, and
+    /// forgetting those a reload of the config file removed.
+    pub fn record(&self, targets: &[Target], results: &[io::Result<PingReport>], tracker: &StateTracker) {
+        let current: HashSet<String> = self.targets.lock().unwrap().iter().map(Target::label).collect();
+        let mut metrics = self.metrics.lock().unwrap();
+        let mut status = self.status.lock().unwrap();
+        metrics.retain(|target| current.contains(target));
+        status.retain(|target, _| current.contains(target));
+        let updated = rfc3339(SystemTime::now());
+        for (target, result) in targets.iter().zip(results) {
+            let label = target.label();
+            if !current.contains(&label) {
+                continue;
+            }
+            metrics.record(&label, &target.labels, result);
+            let report = result.as_ref().ok();
+            status.insert(label.clone(), Status {
+                state: tracker.state(&label).map(|state| state.to_string()),
+                loss_percent: report.and_then(|report| report.packets.as_ref()).map(|packets| packets.loss_percent),
+                avg: report.and_then(|report| report.trips.as_ref()).map(|trips| trips.avg),
+                flaps: report.and_then(|report| report.flaps),
+                error: result.as_ref().err().map(|err| err.to_string()),
+                updated: Some(updated.clone()),
+                target: label,
+            });
+        }
+    }
+
+    pub fn handle(&self, request: &Request) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        let changes = request.method == "POST" || request.method == "DELETE";
+        if changes && self.read_only {
+            return Response::error("403 Forbidden", "the targets can only be changed with an --api-token when serving beyond the loopback interface");
+        }
+        if let (true, Some(token)) = (changes, &self.token) {
+            let given = request.authorization.as_deref().and_then(|authorization| authorization.strip_prefix("Bearer "));
+            if !given.is_some_and(|given| same_token(given.as_bytes(), token.as_bytes())) {
+                return Response::error("401 Unauthorized", "missing or wrong API token");
+            }
+        }
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", [""]) => Response { status: "200 OK", content_type: "text/html; charset=utf-8", body: DASHBOARD.to_string() },
+            ("GET", ["metrics"]) => Response {
+                status: "200 OK",
+                content_type: "text/plain; version=0.0.4",
+                body: self.metrics.lock().unwrap().render(),
+            },
+            ("GET", ["targets"]) => {
+                let targets = self.targets.lock().unwrap();
+                let described: Vec<_> = targets.iter().map(|target| self.describe(target)).collect();
+                Response::json("200 OK", &described)
+            },
+            ("POST", ["targets"]) => self.add(&request.body),
+            ("DELETE", ["targets", label]) => self.remove(&decode(label)),
+            ("GET", ["status"]) => {
+                let targets = self.targets.lock().unwrap();
+                let status = self.status.lock().unwrap();
+                let statuses: Vec<Status> = targets
+                    .iter()
+                    .map(|target| {
+                        let label = target.label();
+                        status.get(&label).cloned().unwrap_or(Status { target: label, ..Default::default() })
+                    })
+                    .collect();
+                Response::json("200 OK", &statuses)
+            },
+            ("GET", ["history", label]) => self.history(&decode(label), &request.query),
+            (_, [""] | ["metrics"] | ["events"] | ["targets"] | ["targets", _] | ["status"] | ["history", _]) => {
+                Response::error("405 Method Not Allowed", &format!("{} is not allowed on {}", request.method, request.path))
+            },
+            _ => Response::error("404 Not Found", &format!("nothing at {}", request.path)),
+        }
+    }
+
+    fn describe(&self, target: &Target) -> serde_json::Value {
+        serde_json::json!({
+            "target": target.label(),
+            "host": target.host,
+            "name": target.name,
+            "group": target.group,
+            "labels": target.labels,
+            "probe": target.options(&self.opts).probe_name(),
+        })
+    }
+
+    // The body is a target as the command line takes it, such as
+    // "gw=10.0.0.1;probe=tcp;port=22", or as the config file does.
+    fn add(&self, body: &[u8]) -> Response {
+        let target = match serde_json::from_slice::<serde_json::Value>(body) {
+            Ok(serde_json::Value::String(target)) => target.parse::<Target>(),
+            Ok(target) => serde_json::from_value::<Target>(target).map_err(|err| err.to_string()),
+            Err(err) => Err(format!("invalid JSON: {}", err)),
+        };
+        let target = match target {
+            Ok(target) => target,
+            Err(err) => return Response::error("400 Bad Request", &err),
+        };
+        let mut targets = self.targets.lock().unwrap();
+        if let Err((status, message)) = admit(&target, &self.opts, &targets) {
+            return Response::error(status, &message);
+        }
+        let described = self.describe(&target);
+        targets.push(target);
+        Response::json("201 Created", &described)
+    }
+
+    fn remove(&self, label: &str) -> Response {
+        let mut targets = self.targets.lock().unwrap();
+        let Some(index) = targets.iter().position(|target| target.label() == label) else {
+            return Response::error("404 Not Found", &format!("{} is not pinged", label));
+        };
+        let target = targets.remove(index);
+        self.metrics.lock().unwrap().remove(label);
+        self.status.lock().unwrap().remove(label);
+        Response::json("200 OK", &self.describe(&target))
+    }
+
+    fn history(&self, label: &str, query: &[(String, String)]) -> Response {
+        let Some(path) = &self.db else {
+            return Response::error("404 Not Found", "there is no history without --db");
+        };
+        let bound = |key: &str| query.iter().find(|(name, _)| name == key).map(|(_, value)| db::parse_time(value)).transpose();
+        let (since, until) = match (bound("since"), bound("until")) {
+            (Ok(since), Ok(until)) => (since, until),
+            (Err(err), _) | (_, Err(err)) => return Response::error("400 Bad Request", &err),
+        };
+        let histories = match Database::open(path).and_then(|db| db.history(Some(label), since.as_deref(), until.as_deref())) {
+            Ok(histories) => histories,
+            Err(err) => return Response::error("500 Internal Server Error", &err.to_string()),
+        };
+        if histories.is_empty() {
+            return Response::error("404 Not Found", &format!("no history for {}", label));
+        }
+        let mut body = Vec::new();
+        if let Err(err) = output::write_history(Format::Json, &histories, &mut body) {
+            return Response::error("500 Internal Server Error", &err.to_string());
+        }
+        Response { status: "200 OK", content_type: "application/json", body: String::from_utf8_lossy(&body).into_owned() }
+    }
+}
+
+// Whether `target` can join those already pinged in rounds, or the status
+// and reason it cannot.
+pub(crate) fn admit(target: &Target, opts: &PingOptions, targets: &[Target]) -> Result<(), (&'static str, String)> {
+    let (_, invalid) = targets::validate_targets(vec![target.clone()], opts);
+    if let Some((target, reason)) = invalid.first() {
+        return Err(("400 Bad Request", format!("invalid target {}: {}", target.host, reason)));
+    }
+    if target.options(opts).count == 0 {
+        return Err(("400 Bad Request", "a count of 0 only works with ping, not in rounds".to_string()));
+    }
+    let label = target.label();
+    if targets.iter().any(|target| target.label() == label) {
+        return Err(("409 Conflict", format!("{} is already pinged", label)));
+    }
+    Ok(())
+}
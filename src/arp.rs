@@ -0,0 +1,240 @@
+use std::io;
+use std::io::Read;
+use std::ffi::CStr;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::unix::io::AsRawFd;
+use std::time::Instant;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::sync::mpsc;
+use crate::PingOptions;
+use crate::error::MultipingError;
+use crate::event::{EventKind, PingEvent};
+use crate::report::{PingInfo, PingReport, RunClock};
+use crate::stats::{keep, packet_statistics, round_ms, round_trip_statistics};
+
+const ETH_P_ARP: u16 = 0x0806;
+const ETH_P_IP: u16 = 0x0800;
+const ARPHRD_ETHER: u16 = 1;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+// An ARP packet for Ethernet and IPv4 addresses.
+const ARP_PACKET_SIZE: usize = 28;
+const BROADCAST: [u8; 6] = [0xff; 6];
+
+// A local interface, with what ARP requests are sent from.
+struct Interface {
+    name: String,
+    index: i32,
+    mac: [u8; 6],
+    address: Ipv4Addr,
+}
+
+// The interfaces with an IPv4 address, each with its netmask.
+fn interfaces() -> io::Result<Vec<(Interface, Ipv4Addr)>> {
+    let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut list) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut addresses = Vec::new();
+    let mut links = Vec::new();
+    let mut entry = list;
+    while let Some(ifaddr) = unsafe { entry.as_ref() } {
+        entry = ifaddr.ifa_next;
+        let Some(addr) = (unsafe { ifaddr.ifa_addr.as_ref() }) else {
+            continue;
+        };
+        let name = unsafe { CStr::from_ptr(ifaddr.ifa_name) }.to_string_lossy().into_owned();
+        match addr.sa_family as libc::c_int {
+            libc::AF_INET if !ifaddr.ifa_netmask.is_null() => {
+                let address = unsafe { &*(addr as *const _ as *const libc::sockaddr_in) };
+                let netmask = unsafe { &*(ifaddr.ifa_netmask as *const libc::sockaddr_in) };
+                let address = Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr));
+                let netmask = Ipv4Addr::from(u32::from_be(netmask.sin_addr.s_addr));
+                addresses.push((name, address, netmask));
+            },
+            libc::AF_PACKET => {
+                let link = unsafe { &*(addr as *const _ as *const libc::sockaddr_ll) };
+                if link.sll_hatype == ARPHRD_ETHER && link.sll_halen == 6 {
+                    let mut mac = [0u8; 6];
+                    mac.copy_from_slice(&link.sll_addr[..6]);
+                    links.push((name, link.sll_ifindex, mac));
+                }
+            },
+            _ => (),
+        }
+    }
+    unsafe { libc::freeifaddrs(list) };
+    Ok(addresses
+        .into_iter()
+        .filter_map(|(name, address, netmask)| {
+            let (_, index, mac) = links.iter().find(|(link, _, _)| *link == name)?;
+            Some((Interface { name, index: *index, mac: *mac, address }, netmask))
+        })
+        .collect())
+}
+
+// The interface named, or else the one on whose subnet `target` is.
+fn find_interface(target: Ipv4Addr, name: Option<&str>) -> io::Result<Option<Interface>> {
+    let on_subnet = |address: Ipv4Addr, netmask: Ipv4Addr| u32::from(address) & u32::from(netmask) == u32::from(target) & u32::from(netmask);
+    Ok(interfaces()?
+        .into_iter()
+        .find(|(interface, netmask)| match name {
+            Some(name) => interface.name == name,
+            None => on_subnet(interface.address, *netmask),
+        })
+        .map(|(interface, _)| interface))
+}
+
+fn request(interface: &Interface, target: Ipv4Addr) -> [u8; ARP_PACKET_SIZE] {
+    let mut packet = [0u8; ARP_PACKET_SIZE];
+    packet[0..2].copy_from_slice(&ARPHRD_ETHER.to_be_bytes());
+    packet[2..4].copy_from_slice(&ETH_P_IP.to_be_bytes());
+    packet[4] = 6;
+    packet[5] = 4;
+    packet[6..8].copy_from_slice(&ARP_REQUEST.to_be_bytes());
+    packet[8..14].copy_from_slice(&interface.mac);
+    packet[14..18].copy_from_slice(&interface.address.octets());
+    // The target hardware address is what is being asked for, so it stays zero.
+    packet[24..28].copy_from_slice(&target.octets());
+    packet
+}
+
+// The hardware address in a reply from `target`, if the packet is one.
+fn parse_reply(packet: &[u8], target: Ipv4Addr) -> Option<[u8; 6]> {
+    if packet.len() < ARP_PACKET_SIZE || packet[6..8] != ARP_REPLY.to_be_bytes() || packet[14..18] != target.octets() {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    mac.copy_from_slice(&packet[8..14]);
+    Some(mac)
+}
+
+fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":")
+}
+
+struct Prober {
+    socket: Socket,
+    interface: Interface,
+    target: Ipv4Addr,
+}
+
+impl Prober {
+    fn open(label: &str, target: Ipv4Addr, opts: &PingOptions) -> io::Result<Prober> {
+        let interface = find_interface(target, opts.interface.as_deref())?.ok_or_else(|| match &opts.interface {
+            Some(name) => io::Error::new(io::ErrorKind::NotFound, format!("{}: {} is not an Ethernet interface with an IPv4 address", label, name)),
+            None => io::Error::new(io::ErrorKind::InvalidInput, format!("{}: not on a local network (ARP only reaches the local subnet)", label)),
+        })?;
+        let protocol = Protocol::from(ETH_P_ARP.to_be() as i32);
+        let socket = Socket::new(Domain::PACKET, Type::DGRAM, Some(protocol)).map_err(|err| match err.kind() {
+            io::ErrorKind::PermissionDenied => MultipingError::PermissionDenied("ARP probes need root privileges".to_string()).into(),
+            _ => err,
+        })?;
+        tracing::debug!(target = %label, interface = %interface.name, "ARP socket opened");
+        Ok(Prober { socket, interface, target })
+    }
+
+    fn send(&self) -> io::Result<()> {
+        let packet = request(&self.interface, self.target);
+        let mut destination: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        destination.sll_family = libc::AF_PACKET as libc::c_ushort;
+        destination.sll_protocol = ETH_P_ARP.to_be();
+        destination.sll_ifindex = self.interface.index;
+        destination.sll_halen = 6;
+        destination.sll_addr[..6].copy_from_slice(&BROADCAST);
+        let sent = unsafe {
+            libc::sendto(
+                self.socket.as_raw_fd(),
+                packet.as_ptr() as *const libc::c_void,
+                packet.len(),
+                0,
+                &destination as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // Waits for the target's reply, returning its hardware address.
+    fn receive(&self, deadline: Instant) -> io::Result<Option<[u8; 6]>> {
+        let mut buf = [0u8; 128];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
+            let len = match (&self.socket).read(&mut buf) {
+                Ok(len) => len,
+                Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(None),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if let Some(mac) = parse_reply(&buf[..len], self.target) {
+                return Ok(Some(mac));
+            }
+        }
+    }
+}
+
+/// Asks for the target's hardware address with an ARP request per probe,
+/// broadcast on the local network it is on, and measures how long the
+/// answer takes. Needs root privileges, and only reaches IPv4 hosts on the
+/// local subnet.
+pub async fn execute_ping(target: String, address: IpAddr, opts: &PingOptions, clock: RunClock, sender: mpsc::Sender<PingEvent>) -> Result<PingReport, io::Error> {
+    let IpAddr::V4(ipv4) = address else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}: ARP only works for IPv4 addresses", target)));
+    };
+    let opts = opts.clone();
+    let prober = Prober::open(&target, ipv4, &opts)?;
+    tokio::task::spawn_blocking(move || {
+        let _ = sender.blocking_send(PingEvent::new(&target, EventKind::Started { address }));
+        let timeout = opts.reply_timeout();
+        let mut pings = Vec::new();
+        let (mut seq, mut received) = (0, 0);
+        let mut mac = None;
+        let mut next_probe = Instant::now();
+        while opts.more_probes(seq) {
+            seq += 1;
+            std::thread::sleep(next_probe.saturating_duration_since(Instant::now()));
+            next_probe += opts.next_interval();
+            let started = Instant::now();
+            prober.send()?;
+            match prober.receive(started + timeout)? {
+                Some(hardware) => {
+                    let time = round_ms(started.elapsed().as_secs_f32() * 1000.0);
+                    let mut ping = PingInfo {
+                        bytes_sent: ARP_PACKET_SIZE as u32,
+                        icmp_seq: seq,
+                        time,
+                        ..Default::default()
+                    };
+                    clock.stamp(&mut ping);
+                    let _ = sender.blocking_send(PingEvent::new(&target, EventKind::Reply(ping.clone())));
+                    keep(&mut pings, ping, opts.unlimited());
+                    received += 1;
+                    mac = Some(hardware);
+                },
+                None => {
+                    let _ = sender.blocking_send(PingEvent::new(&target, EventKind::Lost { icmp_seq: seq }));
+                },
+            }
+        }
+        let packets = packet_statistics(seq, received);
+        let trips = round_trip_statistics(&pings);
+        Ok(PingReport {
+            destination: target,
+            mac: mac.as_ref().map(format_mac),
+            pings,
+            packets: Some(packets),
+            trips,
+            timed_out: false,
+            ..Default::default()
+        })
+    })
+    .await?
+}
@@ -0,0 +1,568 @@
+//! The command line of the multiping binary. It lives in the library so
+//! that `cargo xtask man` can make the man page from it at build time.
+
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use clap::{ArgAction, Args, Parser, Subcommand};
+use clap_complete::Shell;
+use tracing::level_filters::LevelFilter;
+use crate::alert::WebhookFormat;
+#[cfg(unix)]
+use crate::control::Control;
+use crate::dns::RecordType;
+use crate::http::HttpMethod;
+use crate::influx::Sink;
+use crate::output::{Format, Levels, SortBy};
+use crate::prometheus::Buckets;
+use crate::run::RunInfo;
+use crate::stream::StreamFormat;
+use crate::{db, kube, nagios, rotate, silence};
+use crate::{Backend, Dscp, PingFlavor, Probe, SshHost, Target};
+
+/// Ping multiple sites concurrently and collect per-target statistics.
+#[derive(Debug, Parser)]
+#[command(name = "multiping", version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Log at this level (error, warn, info, debug, or trace) to standard error, or to --log-file
+    #[arg(long, global = true)]
+    pub log_level: Option<LevelFilter>,
+    /// Append the log to this file as JSON lines (info level unless --log-level says otherwise)
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+    /// Send the log to the systemd journal, with its fields (info level unless --log-level says otherwise)
+    #[cfg(target_os = "linux")]
+    #[arg(long, global = true, conflicts_with = "log_file")]
+    pub log_journald: bool,
+    // Without a subcommand, the targets are pinged once, as with `ping`.
+    #[command(flatten)]
+    pub ping: PingArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Ping the targets once and report the results (the default)
+    Ping(PingArgs),
+    /// Keep pinging the targets in rounds, reporting every round and state change
+    Monitor(MonitorArgs),
+    /// Keep pinging the targets and expose the results to Prometheus
+    Serve(ServeArgs),
+    /// Keep pinging the targets and send every round to a collector
+    Agent(AgentArgs),
+    /// Take the rounds of agents and compare the sites they ping from
+    Collector(CollectorArgs),
+    /// Show statistics of the runs stored with --db
+    Report(ReportArgs),
+    /// Compare two JSON reports and flag the regressions
+    Compare(CompareArgs),
+    /// Send a command to a monitor or server started with --daemon or --control-socket
+    #[cfg(unix)]
+    Ctl(CtlArgs),
+    /// Print the completion script for a shell
+    Completions {
+        shell: Shell,
+    },
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct TargetArgs {
+    /// TOML file with targets and settings; command line flags take precedence
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// Comma-separated targets, optionally with overrides (host;count=50;timeout=2;interval=0.5;size=1400);
+    /// use - to read them from standard input
+    #[arg(long, value_delimiter = ',', value_parser = Target::from_str)]
+    pub targets: Vec<Target>,
+    /// File with one target per line
+    #[arg(long)]
+    pub targets_file: Option<PathBuf>,
+    /// Largest number of hosts that network targets (e.g. 10.0.0.0/24) may expand to
+    #[arg(long, default_value = "1024")]
+    pub max_hosts: usize,
+    /// Ping every address a name resolves to, as separate targets
+    #[arg(long)]
+    pub all_ips: bool,
+    /// Leave out invalid target names and addresses, with a warning, instead of failing
+    #[arg(long)]
+    pub skip_invalid: bool,
+    /// Ping the pods (or --k8s-kind services) of a Kubernetes namespace that match a label selector, e.g. shop/app=web; may be repeated
+    #[arg(long, value_name = "NAMESPACE/SELECTOR")]
+    pub from_k8s: Vec<kube::Selector>,
+    /// What --from-k8s pings: pods or services
+    #[arg(long, default_value = "pods")]
+    pub k8s_kind: kube::Kind,
+    /// Kubernetes API server for --from-k8s, e.g. http://127.0.0.1:8001 for kubectl proxy (the cluster multiping runs in by default)
+    #[arg(long)]
+    pub k8s_api: Option<String>,
+    /// Seconds between looking up the --from-k8s targets again, in monitor, serve, and agent
+    #[arg(long, default_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+    pub k8s_refresh: u64,
+    /// Ping the running Docker containers, e.g. those of a Compose stack with --docker-label
+    #[arg(long)]
+    pub from_docker: bool,
+    /// Docker daemon for --from-docker: a unix:// socket or a tcp:// address (unix:///var/run/docker.sock by default)
+    #[arg(long, env = "DOCKER_HOST", requires = "from_docker")]
+    pub docker_host: Option<String>,
+    /// Only ping the containers with this label, KEY or KEY=VALUE, e.g. com.docker.compose.project=shop; may be repeated
+    #[arg(long, requires = "from_docker")]
+    pub docker_label: Vec<String>,
+    #[arg(skip)]
+    pub config_targets: Vec<Target>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ProbeArgs {
+    /// Seconds to wait for each reply (10 by default)
+    #[arg(long)]
+    pub timeout: Option<u32>,
+    /// Give up on a target, as stalled, after this many seconds without a reply, loss, or error from it
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub stall_timeout: Option<u32>,
+    /// Probes per target (10 by default); 0 keeps probing until interrupted
+    #[arg(short, long)]
+    pub count: Option<u32>,
+    /// Send this many probes first and leave them out of the statistics, as ARP and route cache misses slow them down
+    #[arg(long, default_value = "0")]
+    pub warmup: u32,
+    /// Seconds between probes (1 by default); below 0.2 requires root for ICMP
+    #[arg(short, long)]
+    pub interval: Option<f32>,
+    /// Move each probe by up to this percent of the interval at random, so targets drift apart
+    #[arg(long, default_value = "0")]
+    pub jitter: f32,
+    /// Send each probe as soon as the last is answered, like ping -A (native backend only)
+    #[arg(long)]
+    pub adaptive: bool,
+    /// Send this many probes at once, without waiting for replies, like ping -l (native backend only)
+    #[arg(long, default_value = "0")]
+    pub preload: u32,
+    /// Milliseconds between the starts of consecutive targets, to spread out the first probes
+    #[arg(long, default_value = "0")]
+    pub stagger: u64,
+    /// ICMP payload bytes per probe (56 by default)
+    #[arg(short, long)]
+    pub size: Option<usize>,
+    /// Probe engine: the built-in ICMP prober, the system ping command, or a single fping for all targets
+    #[arg(long, default_value = "native")]
+    pub backend: Backend,
+    /// How to read the system ping's output: auto, iputils, busybox, bsd, solaris, or windows
+    #[arg(long, default_value = "auto")]
+    pub ping_flavor: PingFlavor,
+    /// Ping program for --backend system, e.g. /bin/busybox-ping (ping on the PATH by default)
+    #[arg(long)]
+    pub ping_path: Option<PathBuf>,
+    /// Extra arguments for --backend system's ping, split at spaces, e.g. "-Q 0x10"
+    #[arg(long, allow_hyphen_values = true)]
+    pub ping_args: Option<String>,
+    /// Run the system ping on this machine over SSH, e.g. ssh://user@jumphost, and ping the targets from there
+    #[arg(long)]
+    pub via: Option<SshHost>,
+    /// What to measure: icmp echo replies, tcp connection times, http response times, udp round trips, tls handshake times, dns query times, or arp replies on the local network
+    #[arg(long, default_value = "icmp")]
+    pub probe: Probe,
+    /// Port for tcp, http, udp, tls, and dns probes (80 by default, 443 for https URLs and tls, 33434 for udp, 53 for dns)
+    #[arg(long)]
+    pub port: Option<u16>,
+    /// Request method for http probes: GET or HEAD
+    #[arg(long, default_value = "GET")]
+    pub http_method: HttpMethod,
+    /// Name dns probes look up
+    #[arg(long, default_value = "example.com")]
+    pub dns_query: String,
+    /// Record type dns probes ask for: A, AAAA, CNAME, MX, NS, PTR, SOA, or TXT
+    #[arg(long, default_value = "A")]
+    pub dns_record: RecordType,
+    /// Look up the hostnames of targets given as addresses
+    #[arg(long)]
+    pub rdns: bool,
+    /// Only use IPv4 addresses
+    #[arg(short = '4', long, conflicts_with = "ipv6")]
+    pub ipv4: bool,
+    /// Only use IPv6 addresses
+    #[arg(short = '6', long)]
+    pub ipv6: bool,
+    /// Send probes from this local address, like ping -I; targets are pinged at addresses of its IP version
+    #[arg(long, conflicts_with = "interface")]
+    pub source_ip: Option<IpAddr>,
+    /// Send probes through this network interface, e.g. eth0, like ping -I
+    #[arg(long)]
+    pub interface: Option<String>,
+    /// Ping every target through each of these interfaces, e.g. eth0,wwan0, and compare them
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["interface", "source_ip"])]
+    pub interfaces: Vec<String>,
+    /// Mark probes with this DSCP, a number up to 63 or a class such as ef or af41, like ping -Q
+    #[arg(long)]
+    pub qos: Option<Dscp>,
+    /// Time to live of outgoing probes (hop limit for IPv6), like ping -t
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=255))]
+    pub ttl: Option<u32>,
+    /// Ping at most this many targets at the same time
+    #[arg(long)]
+    pub max_concurrent: Option<usize>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ThresholdArgs {
+    /// Fail a target that loses more than this percentage of packets
+    #[arg(long)]
+    pub fail_on_loss: Option<f32>,
+    /// Fail a target whose average round trip exceeds these milliseconds
+    #[arg(long)]
+    pub fail_on_avg: Option<f32>,
+}
+
+#[derive(Debug, Args)]
+pub struct AlertArgs {
+    /// POST an alert here when a target is unreachable or crosses a threshold
+    #[arg(long)]
+    pub webhook: Option<String>,
+    /// Payload for --webhook: generic (the alert as JSON), slack, or discord
+    #[arg(long, default_value = "generic")]
+    pub webhook_format: WebhookFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct PingArgs {
+    #[command(flatten)]
+    pub targets: TargetArgs,
+    #[command(flatten)]
+    pub probe: ProbeArgs,
+    #[command(flatten)]
+    pub thresholds: ThresholdArgs,
+    #[command(flatten)]
+    pub alerts: AlertArgs,
+    /// Find each target's path MTU with don't-fragment probes of varying sizes
+    #[arg(long)]
+    pub pmtu: bool,
+    /// Trace the path (with mtr or traceroute) to targets that lose more than this percentage of packets
+    #[arg(long)]
+    pub trace_on_loss: Option<f32>,
+    /// Report format: text, json, yaml, csv, nagios, junit, or parquet (text by default, parquet for an --output ending in .parquet)
+    #[arg(long)]
+    pub format: Option<Format>,
+    /// Render the report through this Handlebars template instead of --format
+    #[arg(long, conflicts_with = "format")]
+    pub template: Option<PathBuf>,
+    /// Round trip and loss (RTA,PL%) from which --format nagios warns
+    #[arg(long, default_value = "100,20%")]
+    pub warning: nagios::Limits,
+    /// Round trip and loss (RTA,PL%) from which --format nagios is critical
+    #[arg(long, default_value = "500,60%")]
+    pub critical: nagios::Limits,
+    /// Order of the report: name, or worst first by loss or avg
+    #[arg(long)]
+    pub sort_by: Option<SortBy>,
+    /// Report only the N fastest targets by avg, fastest first, with a line of totals over all of them
+    #[arg(long, conflicts_with_all = ["sort_by", "bottom"])]
+    pub top: Option<usize>,
+    /// Report only the N slowest targets by avg, slowest first, with a line of totals over all of them
+    #[arg(long, conflicts_with = "sort_by")]
+    pub bottom: Option<usize>,
+    /// Never color the text report (it is only colored on terminals)
+    #[arg(long)]
+    pub no_color: bool,
+    /// Loss percentages shown in yellow and red
+    #[arg(long, default_value = "1,10")]
+    pub loss_colors: Levels,
+    /// Round trip milliseconds shown in yellow and red
+    #[arg(long, default_value = "100,250")]
+    pub rtt_colors: Levels,
+    /// Write every reply, lost probe, and summary to standard output as it happens: ndjson, or csv for the replies
+    #[arg(long)]
+    pub stream: Option<StreamFormat>,
+    /// Add the details of the run (ID, times, host, version, command line, backend) to JSON, YAML, and Parquet reports, templates, and ndjson streams
+    #[arg(long)]
+    pub metadata: bool,
+    #[arg(skip)]
+    pub run: Option<Box<RunInfo>>,
+    /// Also send the samples and summaries elsewhere: influx or statsd
+    #[arg(long)]
+    pub sink: Option<Sink>,
+    /// InfluxDB write URL, e.g. http://localhost:8086/api/v2/write?org=acme&bucket=pings
+    #[arg(long)]
+    pub influx_url: Option<String>,
+    /// InfluxDB API token
+    #[arg(long, env = "INFLUX_TOKEN", hide_env_values = true)]
+    pub influx_token: Option<String>,
+    /// Append the InfluxDB points to this file instead of sending them
+    #[arg(long, conflicts_with = "influx_url")]
+    pub influx_file: Option<PathBuf>,
+    /// StatsD server (host:port) for --sink statsd
+    #[arg(long, default_value = "127.0.0.1:8125")]
+    pub statsd_addr: String,
+    /// Tag the StatsD metrics with the target and its labels, DogStatsD style
+    #[arg(long)]
+    pub dogstatsd: bool,
+    /// Compare the results with this earlier JSON report and fail on regressions
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
+    /// Percent increase in avg or p95 round trip (or points of loss) that counts as a regression
+    #[arg(long, default_value = "10")]
+    pub max_regression: f32,
+    /// Also write a standalone HTML report with latency charts to this file
+    #[arg(long)]
+    pub report_html: Option<PathBuf>,
+    /// Store the run in this SQLite database, for the report subcommand
+    #[arg(long)]
+    pub db: Option<PathBuf>,
+    /// Write the report, or the --stream, to this file instead of standard output
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    #[command(flatten)]
+    pub rotation: RotationArgs,
+    /// Show a live dashboard while pinging
+    #[arg(long)]
+    pub tui: bool,
+    /// Save the output of the system ping to this directory, a file per target
+    #[arg(long, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Parse the ping output recorded with --record in this directory instead of pinging
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+    /// Print only the report, with no progress
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Print a line per probe instead of progress bars; -vv adds debug details
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// Print the summary of every target on standard output as soon as it finishes, instead of progress bars
+    #[arg(long, conflicts_with_all = ["quiet", "verbose", "tui", "stream"])]
+    pub progressive: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RoundArgs {
+    #[command(flatten)]
+    pub alerts: AlertArgs,
+    /// Consider a target down after this many failing rounds in a row
+    #[arg(long, default_value = "3")]
+    pub down_after: u32,
+    /// Consider a down target up again after this many healthy rounds in a row
+    #[arg(long, default_value = "2")]
+    pub up_after: u32,
+    /// Stop alerting on a target that goes down or comes back up more than this many times within --flap-window
+    #[arg(long)]
+    pub flap_threshold: Option<u32>,
+    /// Minutes over which the state changes of --flap-threshold are counted
+    #[arg(long, default_value = "30")]
+    pub flap_window: u64,
+    /// Run this shell command when a target goes down; it gets TARGET, LOSS, and AVG_RTT in its environment
+    #[arg(long)]
+    pub on_down: Option<String>,
+    /// Run this shell command when a target comes back up
+    #[arg(long)]
+    pub on_up: Option<String>,
+    /// Store every round in this SQLite database, for the report subcommand
+    #[arg(long)]
+    pub db: Option<PathBuf>,
+    /// Trace the path to every target each this many rounds, and log when it changes
+    #[arg(long)]
+    pub trace_every: Option<u32>,
+}
+
+#[derive(Debug, Args)]
+pub struct MonitorArgs {
+    #[command(flatten)]
+    pub targets: TargetArgs,
+    #[command(flatten)]
+    pub probe: ProbeArgs,
+    #[command(flatten)]
+    pub thresholds: ThresholdArgs,
+    #[command(flatten)]
+    pub rounds: RoundArgs,
+    /// Seconds between the start of two probing rounds
+    #[arg(long, default_value = "10")]
+    pub period: u64,
+    /// Format of every round's report: text, json, yaml, or csv (text by default)
+    #[arg(long)]
+    pub format: Option<Format>,
+    /// Write every round's summaries, and path changes, to standard output as events: ndjson, or csv for the replies
+    #[arg(long, conflicts_with = "format")]
+    pub stream: Option<StreamFormat>,
+    /// Write the --stream to this file instead of standard output
+    #[arg(long, requires = "stream")]
+    pub output: Option<PathBuf>,
+    /// Add the details of the run (ID, start time, host, version, command line, backend) to JSON and YAML reports and ndjson streams
+    #[arg(long)]
+    pub metadata: bool,
+    #[command(flatten)]
+    pub rotation: RotationArgs,
+    #[cfg(unix)]
+    #[command(flatten)]
+    pub daemon: DaemonArgs,
+}
+
+// Long runs can keep their --stream file in check without logrotate.
+#[derive(Debug, Args)]
+pub struct RotationArgs {
+    /// Add to the --output file instead of replacing it (with --stream)
+    #[arg(long, requires = "stream", requires = "output")]
+    pub append: bool,
+    /// Move the --output file aside to FILE.1 before it grows past this size, e.g. 10M (with --stream)
+    #[arg(long, requires = "stream", requires = "output", value_parser = rotate::parse_size)]
+    pub rotate_size: Option<u64>,
+    /// Move the --output file aside to FILE.1 once it is this old, e.g. 60m or 1d (with --stream)
+    #[arg(long, requires = "stream", requires = "output", value_parser = silence::parse_duration)]
+    pub rotate_every: Option<Duration>,
+    /// How many files moved aside to keep, as FILE.1 (the newest) to FILE.N
+    #[arg(long, default_value = "5")]
+    pub rotate_keep: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub targets: TargetArgs,
+    #[command(flatten)]
+    pub probe: ProbeArgs,
+    #[command(flatten)]
+    pub thresholds: ThresholdArgs,
+    #[command(flatten)]
+    pub rounds: RoundArgs,
+    /// Seconds between the start of two probing rounds
+    #[arg(long, default_value = "60")]
+    pub period: u64,
+    /// Address where /metrics and the API are served
+    #[arg(long, default_value = "127.0.0.1:9090")]
+    pub listen: SocketAddr,
+    /// Token that API requests adding or removing targets have to bear, as in Authorization: Bearer TOKEN;
+    /// without one, the targets can only be changed when --listen is a loopback address
+    #[arg(long)]
+    pub api_token: Option<String>,
+    /// Upper bounds, in seconds, of the round trip time histogram buckets
+    #[arg(long, default_value = "0.0005,0.001,0.0025,0.005,0.01,0.025,0.05,0.1,0.25,0.5,1,2.5")]
+    pub buckets: Buckets,
+    #[cfg(unix)]
+    #[command(flatten)]
+    pub daemon: DaemonArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct AgentArgs {
+    #[command(flatten)]
+    pub targets: TargetArgs,
+    #[command(flatten)]
+    pub probe: ProbeArgs,
+    #[command(flatten)]
+    pub thresholds: ThresholdArgs,
+    #[command(flatten)]
+    pub rounds: RoundArgs,
+    /// Seconds between the start of two probing rounds
+    #[arg(long, default_value = "10")]
+    pub period: u64,
+    /// Collector to send the rounds to, as HOST:PORT
+    #[arg(long)]
+    pub collector: String,
+    /// Name of the site the targets are pinged from (the host name by default)
+    #[arg(long)]
+    pub site: Option<String>,
+    /// Connect to the collector over TLS
+    #[arg(long)]
+    pub tls: bool,
+    /// PEM file with the certificate of the authority the collector's certificate is checked against, besides the usual ones
+    #[arg(long, requires = "tls")]
+    pub ca_cert: Option<PathBuf>,
+    /// Token the collector asks agents for
+    #[arg(long, env = "MULTIPING_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct CollectorArgs {
+    /// Address where agents connect
+    #[arg(long, default_value = "0.0.0.0:7878")]
+    pub listen: SocketAddr,
+    /// Seconds between two summaries of what the sites reported
+    #[arg(long, default_value = "10")]
+    pub period: u64,
+    /// Summary format: text, json, or yaml (text by default)
+    #[arg(long)]
+    pub format: Option<Format>,
+    /// PEM file with the certificate chain to take agents over TLS with
+    #[arg(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM file with the private key of --tls-cert
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    /// Token that agents have to bear
+    #[arg(long, env = "MULTIPING_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+}
+
+#[cfg(unix)]
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    /// Fork into the background, with a PID file and a control socket for the ctl subcommand
+    #[arg(long)]
+    pub daemon: bool,
+    /// Where --daemon writes its PID (multiping.pid in $XDG_RUNTIME_DIR, or /run/multiping for root, by default)
+    #[arg(long)]
+    pub pid_file: Option<PathBuf>,
+    /// Take ctl commands on this Unix socket (multiping.sock in $XDG_RUNTIME_DIR, or /run/multiping for root, with --daemon unless given)
+    #[arg(long)]
+    pub control_socket: Option<PathBuf>,
+    /// Print a systemd unit that runs this command as a service, and exit
+    #[cfg(target_os = "linux")]
+    #[arg(long, conflicts_with = "daemon")]
+    pub generate_systemd_unit: bool,
+    // Bound before the fork, for the rounds to take up.
+    #[arg(skip)]
+    pub control: Option<Control>,
+}
+
+#[cfg(unix)]
+#[derive(Debug, Args)]
+pub struct CtlArgs {
+    /// Control socket of the monitor or server (multiping.sock in $XDG_RUNTIME_DIR, or /run/multiping for root, by default)
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: CtlCommand,
+}
+
+#[cfg(unix)]
+#[derive(Debug, Subcommand)]
+pub enum CtlCommand {
+    /// Show how every target fared in its last round
+    Status,
+    /// Start pinging a target, given as --targets takes it
+    Add { target: String },
+    /// Stop pinging a target, as reports name it
+    Remove { target: String },
+    /// Stop the monitor or server
+    Stop,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// SQLite database the runs were stored in
+    #[arg(long)]
+    pub db: PathBuf,
+    /// Only this target, as named in the reports
+    #[arg(long)]
+    pub target: Option<String>,
+    /// Start of the time range: a duration back from now (30m, 12h, 7d) or a time (2024-05-01T12:00)
+    #[arg(long, value_parser = db::parse_time)]
+    pub since: Option<String>,
+    /// End of the time range, exclusive, in the same forms as --since
+    #[arg(long, value_parser = db::parse_time)]
+    pub until: Option<String>,
+    /// Report format: text, json, yaml, or csv
+    #[arg(long, default_value = "text")]
+    pub format: Format,
+}
+
+#[derive(Debug, Args)]
+pub struct CompareArgs {
+    pub baseline: PathBuf,
+    pub current: PathBuf,
+    /// Percent increase in avg or p95 round trip (or points of loss) that counts as a regression
+    #[arg(long, default_value = "10")]
+    pub max_regression: f32,
+    /// Report format: text, json, yaml, or csv
+    #[arg(long, default_value = "text")]
+    pub format: Format,
+}
@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use crate::api::same_token;
+use crate::output::{Matrix, MatrixRow};
+use crate::report::{PacketStatistics, PingReport, RoundTripStatistics};
+use crate::stream::Event;
+use crate::targets::Target;
+use crate::MultipingError;
+
+// A summary is far shorter; agents that send longer lines are cut off.
+const MAX_LINE: u64 = 1 << 20;
+// How many lines wait for a collector that is away before rounds are dropped.
+const BACKLOG: usize = 4096;
+// How long an agent waits before connecting again.
+const RETRY: Duration = Duration::from_secs(5);
+
+// The first line of every connection, naming the site the agent pings from.
+#[derive(Serialize)]
+struct Hello<'a> {
+    event: &'static str,
+    site: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<&'a str>,
+}
+
+/// Sends the rounds of an agent to a collector as lines of JSON: a `hello`
+/// with the name of the site, then the `summary` or `error` events of every
+/// round, as `--stream ndjson` writes them. It connects again whenever the
+/// collector goes away, and what it could not send waits for it.
+pub struct Agent {
+    sender: mpsc::Sender<String>,
+}
+
+impl Agent {
+    /// Starts sending to the collector at `address`, a HOST:PORT, over TLS
+    /// when `tls` is given.
+    pub fn start(address: String, site: String, token: Option<String>, tls: Option<TlsConnector>) -> Agent {
+        let (sender, mut receiver) = mpsc::channel::<String>(BACKLOG);
+        tokio::spawn(async move {
+            let mut pending = None;
+            loop {
+                let hello = serde_json::to_string(&Hello { event: "hello", site: &site, token: token.as_deref() }).unwrap_or_default();
+                let mut stream = match connect(&address, tls.as_ref()).await {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        eprintln!("Cannot reach the collector at {}: {}", address, err);
+                        tokio::time::sleep(RETRY).await;
+                        continue;
+                    },
+                };
+                let sent: io::Result<()> = async {
+                    stream.write_all(format!("{}\n", hello).as_bytes()).await?;
+                    loop {
+                        let line = match pending.take() {
+                            Some(line) => line,
+                            None => match receiver.recv().await {
+                                Some(line) => line,
+                                None => return stream.shutdown().await,
+                            },
+                        };
+                        if let Err(err) = stream.write_all(format!("{}\n", line).as_bytes()).await {
+                            pending = Some(line);
+                            return Err(err);
+                        }
+                    }
+                }
+                .await;
+                match sent {
+                    Ok(()) => return,
+                    Err(err) => eprintln!("Lost the collector at {}: {}", address, err),
+                }
+                tokio::time::sleep(RETRY).await;
+            }
+        });
+        Agent { sender }
+    }
+
+    /// Queues the summaries of a round, which are dropped when the collector
+    /// has been away for too long to keep them.
+    pub fn send(&self, targets: &[Target], results: &[io::Result<PingReport>]) {
+        for (target, result) in targets.iter().zip(results) {
+            let line = match result {
+                Ok(report) => Event::Summary { report }.to_json(),
+                Err(err) => Event::Error { target: &target.label(), error: &MultipingError::from(err) }.to_json(),
+            };
+            if self.sender.try_send(line).is_err() {
+                eprintln!("The collector is too far behind; dropping the round");
+                return;
+            }
+        }
+    }
+}
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Connection for S {}
+
+async fn connect(address: &str, tls: Option<&TlsConnector>) -> io::Result<Box<dyn Connection>> {
+    let stream = TcpStream::connect(address).await?;
+    let Some(tls) = tls else {
+        return Ok(Box::new(stream));
+    };
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host).trim_start_matches('[').trim_end_matches(']');
+    let name = ServerName::try_from(host.to_string()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    Ok(Box::new(tls.connect(name, stream).await?))
+}
+
+/// What agents connect with over TLS: the usual roots, and the one in the
+/// PEM file `ca` for collectors with a certificate of their own making.
+pub fn tls_connector(ca: Option<&Path>) -> io::Result<TlsConnector> {
+    let mut roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    if let Some(ca) = ca {
+        for certificate in certificates(ca)? {
+            roots.add(certificate).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", ca.display(), err)))?;
+        }
+    }
+    let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// What a collector takes agents over TLS with: the certificate chain and
+/// the private key in the PEM files `certificate` and `key`.
+pub fn tls_acceptor(certificate: &Path, key: &Path) -> io::Result<TlsAcceptor> {
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", key.display(), err)))?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certificates(certificate)?, key)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", certificate.display(), err)))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn certificates(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err));
+    let certificates = CertificateDer::pem_file_iter(path).map_err(invalid)?.collect::<Result<Vec<_>, _>>().map_err(invalid)?;
+    if certificates.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: no certificates", path.display())));
+    }
+    Ok(certificates)
+}
+
+// Just the parts of the lines from agents that the summary needs.
+#[derive(Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Line {
+    Hello {
+        site: String,
+        token: Option<String>,
+    },
+    Summary {
+        destination: String,
+        packets: Option<PacketStatistics>,
+        trips: Option<RoundTripStatistics>,
+    },
+    Error {
+        target: String,
+        #[serde(flatten)]
+        error: MultipingError,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Takes the rounds of agents at several sites and keeps the latest report
+/// about every target from each, to compare them.
+pub struct Collector {
+    token: Option<String>,
+    sites: Mutex<BTreeMap<String, BTreeMap<String, PingReport>>>,
+}
+
+impl Collector {
+    /// A collector that only takes agents whose `hello` bears `token`, when
+    /// there is one.
+    pub fn new(token: Option<String>) -> Arc<Collector> {
+        Arc::new(Collector { token, sites: Mutex::new(BTreeMap::new()) })
+    }
+
+    /// Takes agents on `listener`, over TLS when `tls` is given, until the
+    /// process stops.
+    pub async fn listen(self: Arc<Self>, listener: TcpListener, tls: Option<TlsAcceptor>) {
+        loop {
+            let Ok((stream, peer)) = listener.accept().await else {
+                continue;
+            };
+            let collector = self.clone();
+            let tls = tls.clone();
+            tokio::spawn(async move {
+                let served = match tls {
+                    Some(tls) => match tls.accept(stream).await {
+                        Ok(stream) => collector.serve(stream).await,
+                        Err(err) => Err(err),
+                    },
+                    None => collector.serve(stream).await,
+                };
+                if let Err(err) = served {
+                    eprintln!("Agent at {}: {}", peer, err);
+                }
+            });
+        }
+    }
+
+    fn admits(&self, given: Option<&str>) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        given.is_some_and(|given| same_token(given.as_bytes(), token.as_bytes()))
+    }
+
+    async fn serve<S: AsyncRead + Unpin>(&self, stream: S) -> io::Result<()> {
+        let mut lines = BufReader::new(stream.take(MAX_LINE)).lines();
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let site = match lines.next_line().await?.map(|line| serde_json::from_str(&line)) {
+            Some(Ok(Line::Hello { site, token })) if self.admits(token.as_deref()) => site,
+            Some(Ok(Line::Hello { .. })) => return Err(io::Error::new(io::ErrorKind::PermissionDenied, "wrong token")),
+            _ => return Err(invalid("expected a hello first".to_string())),
+        };
+        loop {
+            // Every line gets its allowance afresh.
+            lines.get_mut().get_mut().set_limit(MAX_LINE);
+            let Some(line) = lines.next_line().await? else {
+                return Ok(());
+            };
+            let line: Line = serde_json::from_str(&line).map_err(|err| invalid(format!("{}: {}", site, err)))?;
+            let report = match line {
+                Line::Summary { destination, packets, trips } => PingReport { destination, packets, trips, ..Default::default() },
+                Line::Error { target, error } => PingReport { destination: target, error: Some(error), ..Default::default() },
+                Line::Hello { .. } | Line::Other => continue,
+            };
+            let mut sites = self.sites.lock().unwrap();
+            sites.entry(site.clone()).or_default().insert(report.destination.clone(), report);
+        }
+    }
+
+    /// The latest reports from every site, by site.
+    pub fn sites(&self) -> BTreeMap<String, Vec<PingReport>> {
+        let sites = self.sites.lock().unwrap();
+        sites.iter().map(|(site, reports)| (site.clone(), reports.values().cloned().collect())).collect()
+    }
+
+    /// A row per target, with its report from every site, in the order of
+    /// the sites. Sites that have not reported a target have an empty one.
+    pub fn matrix(&self) -> Matrix {
+        let sites = self.sites.lock().unwrap();
+        let mut targets: Vec<&String> = sites.values().flat_map(|reports| reports.keys()).collect();
+        targets.sort();
+        targets.dedup();
+        let rows = targets
+            .into_iter()
+            .map(|target| MatrixRow {
+                target: target.clone(),
+                reports: sites.values().map(|reports| reports.get(target).cloned().unwrap_or_default()).collect(),
+            })
+            .collect();
+        Matrix { interfaces: sites.keys().cloned().collect(), rows }
+    }
+}
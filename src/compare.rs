@@ -0,0 +1,170 @@
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::report::PingReport;
+
+/// The figures of a target that runs are compared on.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    pub target: String,
+    /// Average round trip time, in milliseconds.
+    pub avg: Option<f32>,
+    /// 95th percentile round trip time, in milliseconds.
+    pub p95: Option<f32>,
+    pub loss_percent: Option<f32>,
+}
+
+impl From<&PingReport> for Metrics {
+    fn from(report: &PingReport) -> Metrics {
+        Metrics {
+            target: report.destination.clone(),
+            avg: report.trips.as_ref().map(|trips| trips.avg),
+            p95: report.samples.as_ref().map(|samples| samples.p95),
+            loss_percent: report.packets.as_ref().map(|packets| packets.loss_percent),
+        }
+    }
+}
+
+// Just the parts of a JSON report that comparisons need.
+#[derive(Deserialize)]
+struct Entry {
+    destination: String,
+    packets: Option<Packets>,
+    trips: Option<Trips>,
+    samples: Option<Samples>,
+}
+
+#[derive(Deserialize)]
+struct Packets {
+    loss_percent: f32,
+}
+
+#[derive(Deserialize)]
+struct Trips {
+    avg: f32,
+}
+
+#[derive(Deserialize)]
+struct Samples {
+    p95: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Report {
+    Targets(Vec<Entry>),
+    Grouped { targets: Vec<Entry> },
+}
+
+/// Reads the metrics of every target in a JSON report written by `--format json`.
+pub fn read_metrics<P: AsRef<Path>>(path: P) -> io::Result<Vec<Metrics>> {
+    let path = path.as_ref();
+    let report: Report = serde_json::from_reader(BufReader::new(File::open(path)?))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err)))?;
+    let (Report::Targets(entries) | Report::Grouped { targets: entries }) = report;
+    Ok(entries
+        .into_iter()
+        .map(|entry| Metrics {
+            target: entry.destination,
+            avg: entry.trips.map(|trips| trips.avg),
+            p95: entry.samples.map(|samples| samples.p95),
+            loss_percent: entry.packets.map(|packets| packets.loss_percent),
+        })
+        .collect())
+}
+
+/// How one metric of a target moved from the baseline to the current run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub target: String,
+    pub metric: &'static str,
+    pub baseline: f32,
+    pub current: f32,
+    /// Percent change for round trip times, percentage points for loss.
+    pub change: f32,
+    pub regression: bool,
+}
+
+impl Change {
+    /// Describes the change, e.g. `avg 12 ms, up 30% from 9.2 ms`.
+    pub fn describe(&self) -> String {
+        let (unit, change_unit) = if self.metric == "loss" { ("%", " points") } else { (" ms", "%") };
+        let direction = if self.change < 0.0 { "down" } else { "up" };
+        format!(
+            "{} {}{}, {} {}{} from {}{}",
+            self.metric, self.current, unit, direction, self.change.abs(), change_unit, self.baseline, unit
+        )
+    }
+}
+
+/// Compares every target found in both runs. Round trip times regress when
+/// they grow by more than `max_increase` percent, and loss when it grows by
+/// more than `max_increase` percentage points.
+pub fn compare(baseline: &[Metrics], current: &[Metrics], max_increase: f32) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for now in current {
+        let Some(before) = baseline.iter().find(|before| before.target == now.target) else {
+            continue;
+        };
+        let metrics = [("avg", before.avg, now.avg), ("p95", before.p95, now.p95), ("loss", before.loss_percent, now.loss_percent)];
+        for (metric, before, now_value) in metrics {
+            let (Some(before), Some(current)) = (before, now_value) else {
+                continue;
+            };
+            let change = if metric == "loss" {
+                current - before
+            } else if before > 0.0 {
+                (current - before) / before * 100.0
+            } else {
+                0.0
+            };
+            let change = (change * 10.0).round() / 10.0;
+            changes.push(Change { target: now.target.clone(), metric, baseline: before, current, change, regression: change > max_increase });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(target: &str, avg: f32, p95: Option<f32>, loss_percent: f32) -> Metrics {
+        Metrics { target: target.to_string(), avg: Some(avg), p95, loss_percent: Some(loss_percent) }
+    }
+
+    #[test]
+    fn regressions_past_the_allowed_increase() {
+        let baseline = [metrics("a", 10.0, Some(20.0), 0.0), metrics("gone", 1.0, None, 0.0)];
+        let current = [metrics("a", 13.0, Some(21.0), 5.0), metrics("new", 1.0, None, 0.0)];
+        let changes = compare(&baseline, &current, 10.0);
+        let found: Vec<(&str, f32, bool)> = changes.iter().map(|change| (change.metric, change.change, change.regression)).collect();
+        // Only targets in both runs, and metrics both have, are compared.
+        assert_eq!(found, [("avg", 30.0, true), ("p95", 5.0, false), ("loss", 5.0, false)]);
+        assert_eq!(changes[0].describe(), "avg 13 ms, up 30% from 10 ms");
+        assert_eq!(changes[2].describe(), "loss 5%, up 5 points from 0%");
+    }
+
+    #[test]
+    fn improvements_go_down() {
+        let changes = compare(&[metrics("a", 0.0, None, 20.0)], &[metrics("a", 5.0, None, 10.0)], 0.0);
+        // A baseline of nothing has no percent change.
+        assert_eq!((changes[0].change, changes[0].regression), (0.0, false));
+        assert_eq!(changes[1].describe(), "loss 10%, down 10 points from 20%");
+    }
+
+    #[test]
+    fn reads_plain_and_grouped_reports() {
+        let path = std::env::temp_dir().join(format!("multiping-compare-{}.json", std::process::id()));
+        let entry = r#"{"destination": "a", "packets": {"loss_percent": 0.0}, "trips": {"avg": 1.5}, "samples": {"p95": 2.5}}"#;
+        for report in [format!("[{}]", entry), format!(r#"{{"targets": [{}], "groups": []}}"#, entry)] {
+            std::fs::write(&path, report).unwrap();
+            assert_eq!(read_metrics(&path).unwrap(), [metrics("a", 1.5, Some(2.5), 0.0)]);
+        }
+        std::fs::write(&path, "{}").unwrap();
+        assert_eq!(read_metrics(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(path).unwrap();
+    }
+}
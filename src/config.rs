@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Deserializer};
+use crate::email::Email;
+use crate::output::Format;
+use crate::pagerduty::PagerDuty;
+use crate::silence::Silence;
+use crate::targets::Target;
+use crate::thresholds::Thresholds;
+
+/// Settings read from a TOML file. Anything left out falls back to the
+/// command line or the built-in defaults.
+///
+/// ```toml
+/// count = 20
+/// format = "json"
+/// targets = ["google.com", { host = "10.0.0.1", count = 50, timeout = 2, name = "gateway" }]
+///
+/// [groups]
+/// dc1 = ["10.1.0.1", "10.1.0.2"]
+///
+/// [thresholds]
+/// max_loss = 5.0
+/// max_avg = 200.0
+///
+/// [email]
+/// server = "smtp.example.com"
+/// from = "alerts@example.com"
+/// to = ["oncall@example.com"]
+///
+/// [pagerduty]
+/// routing_key = "..."
+///
+/// [[silences]]
+/// targets = ["gateway"]
+/// schedule = "0 3 * * 0"
+/// duration = "2h"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default, deserialize_with = "deserialize_targets")]
+    pub targets: Vec<Target>,
+    /// Targets by group; each one's `group` is set to the group's name.
+    #[serde(default, deserialize_with = "deserialize_groups")]
+    pub groups: BTreeMap<String, Vec<Target>>,
+    pub count: Option<u32>,
+    pub timeout: Option<u32>,
+    pub interval: Option<f32>,
+    pub size: Option<usize>,
+    pub format: Option<Format>,
+    pub output: Option<PathBuf>,
+    #[serde(default)]
+    pub thresholds: Thresholds,
+    /// Where `monitor` and `serve` mail the targets that went down.
+    pub email: Option<Email>,
+    /// Where `monitor` and `serve` open incidents for the targets that go down.
+    pub pagerduty: Option<PagerDuty>,
+    /// When `monitor` and `serve` hold back alerts.
+    #[serde(default)]
+    pub silences: Vec<Silence>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TargetEntry {
+    Host(String),
+    Detailed(Target),
+}
+
+fn deserialize_targets<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Target>, D::Error> {
+    let entries = Vec::<TargetEntry>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            TargetEntry::Host(host) => Target::new(host),
+            TargetEntry::Detailed(target) => target,
+        })
+        .collect())
+}
+
+fn deserialize_groups<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, Vec<Target>>, D::Error> {
+    #[derive(Deserialize)]
+    struct Group(#[serde(deserialize_with = "deserialize_targets")] Vec<Target>);
+
+    let groups = BTreeMap::<String, Group>::deserialize(deserializer)?;
+    Ok(groups
+        .into_iter()
+        .map(|(name, Group(targets))| {
+            let targets = targets.into_iter().map(|target| Target { group: Some(name.clone()), ..target }).collect();
+            (name, targets)
+        })
+        .collect())
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err))
+        })
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use crate::api;
+use crate::monitor::StateTracker;
+use crate::report::PingReport;
+use crate::targets::Target;
+use crate::PingOptions;
+
+/// Where `multiping ctl` finds the control socket unless told otherwise.
+pub const SOCKET: &str = "multiping.sock";
+/// Where `--daemon` writes its PID unless told otherwise.
+pub const PID_FILE: &str = "multiping.pid";
+
+/// Where the file `name` goes unless told otherwise: in `$XDG_RUNTIME_DIR`,
+/// in `/run/multiping` for root, and otherwise in a directory of the user's
+/// own in the temporary directory, which is made if need be. Others can
+/// write to the temporary directory itself, and so plant links there.
+pub fn default_path(name: &str) -> io::Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir).join(name));
+    }
+    let uid = unsafe { libc::geteuid() };
+    let dir = if uid == 0 { PathBuf::from("/run/multiping") } else { std::env::temp_dir().join(format!("multiping-{}", uid)) };
+    match fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(io::Error::new(err.kind(), format!("cannot create {}: {}", dir.display(), err))),
+        _ => (),
+    }
+    let metadata = fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o022 != 0 {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is not a directory only this user can write to", dir.display())));
+    }
+    Ok(dir.join(name))
+}
+
+// Commands are a single short line.
+const MAX_COMMAND: u64 = 4096;
+
+/// Forks into the background, detached from the terminal, with the
+/// standard streams on /dev/null. Only the child returns; the parent writes
+/// its PID to `pid_file` and exits.
+///
+/// Call it before any thread is started, as the child has none but this one.
+pub fn daemonize(pid_file: &Path) -> io::Result<()> {
+    if let Some(pid) = running(pid_file) {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("already running as PID {}, from {}", pid, pid_file.display())));
+    }
+    // What is left of a run that is over goes first; it is removed, not
+    // followed, should it be a link.
+    if fs::symlink_metadata(pid_file).is_ok() {
+        fs::remove_file(pid_file)?;
+    }
+    // Created before the fork, so that a path that cannot be written to
+    // fails while there is still a terminal to tell. A new file only, so
+    // that nothing planted in its place is written through.
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o644)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(pid_file)
+        .map_err(|err| io::Error::new(err.kind(), format!("cannot create {}: {}", pid_file.display(), err)))?;
+    let null = fs::OpenOptions::new().read(true).write(true).open("/dev/null")?;
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => {
+            use std::os::unix::io::AsRawFd;
+            unsafe {
+                libc::setsid();
+                for fd in 0..3 {
+                    libc::dup2(null.as_raw_fd(), fd);
+                }
+            }
+            Ok(())
+        },
+        pid => {
+            writeln!(file, "{}", pid)?;
+            std::process::exit(0);
+        },
+    }
+}
+
+// The PID in `pid_file`, if that process is still there.
+fn running(pid_file: &Path) -> Option<i32> {
+    let pid = fs::read_to_string(pid_file).ok()?.trim().parse().ok()?;
+    (unsafe { libc::kill(pid, 0) } == 0).then_some(pid)
+}
+
+/// A socket that `multiping ctl` sends commands to: `status`, `add TARGET`,
+/// `remove TARGET`, and `stop`. Each connection takes one command and gets
+/// the answer back as text, which starts with `error:` when it failed.
+#[derive(Debug)]
+pub struct Control {
+    listener: UnixListener,
+    path: PathBuf,
+    /// Removed along with the socket when the process stops.
+    pub pid_file: Option<PathBuf>,
+}
+
+impl Control {
+    /// Listens on `path`, replacing a socket left behind by a process that
+    /// is gone, but not one still answering, nor anything but a socket.
+    pub fn bind(path: &Path) -> io::Result<Control> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path.display())));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{} is in use by another multiping", path.display())));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Control { listener, path: path.to_path_buf(), pid_file: None })
+    }
+
+    /// Answers commands about `targets` until the process stops, which SIGTERM
+    /// also does. Targets added are checked against `opts`.
+    pub(crate) fn start(self, targets: Arc<Mutex<Vec<Target>>>, opts: PingOptions) -> io::Result<Arc<Board>> {
+        let listener = tokio::net::UnixListener::from_std(self.listener)?;
+        let board = Arc::new(Board { targets, opts, status: Mutex::new(HashMap::new()), path: self.path, pid_file: self.pid_file });
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        let terminating = board.clone();
+        tokio::spawn(async move {
+            terminate.recv().await;
+            terminating.exit();
+        });
+        let serving = board.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+                tokio::spawn(serving.clone().answer(stream));
+            }
+        });
+        Ok(board)
+    }
+}
+
+/// What the control socket answers from, kept up to date by the rounds.
+pub(crate) struct Board {
+    targets: Arc<Mutex<Vec<Target>>>,
+    opts: PingOptions,
+    status: Mutex<HashMap<String, String>>,
+    path: PathBuf,
+    pid_file: Option<PathBuf>,
+}
+
+impl Board {
+    pub fn record(&self, targets: &[Target], results: &[io::Result<PingReport>], tracker: &StateTracker) {
+        let mut status = self.status.lock().unwrap();
+        for (target, result) in targets.iter().zip(results) {
+            let label = target.label();
+            let state = tracker.state(&label).map_or("pending".to_string(), |state| state.to_string());
+            let line = match result {
+                Ok(report) => match (&report.packets, &report.trips) {
+                    (Some(packets), Some(trips)) => format!("{}: {}, {}% loss, avg {} ms", label, state, packets.loss_percent, trips.avg),
+                    (Some(packets), None) => format!("{}: {}, {}% loss", label, state, packets.loss_percent),
+                    _ => format!("{}: {}", label, state),
+                },
+                Err(err) => format!("{}: {}, {}", label, state, err),
+            };
+            status.insert(label, line);
+        }
+    }
+
+    async fn answer(self: Arc<Self>, stream: tokio::net::UnixStream) {
+        let (reader, mut writer) = stream.into_split();
+        let mut command = String::new();
+        if BufReader::new(reader.take(MAX_COMMAND)).read_line(&mut command).await.is_err() {
+            return;
+        }
+        let command = command.trim();
+        let (verb, argument) = command.split_once(' ').map_or((command, ""), |(verb, argument)| (verb, argument.trim()));
+        let stopping = verb == "stop";
+        let answer = match verb {
+            "status" => self.statuses(),
+            "add" => self.add(argument),
+            "remove" => self.remove(argument),
+            "stop" => Ok("stopping".to_string()),
+            _ => Err(format!("unknown command '{}' (expected status, add, remove, or stop)", command)),
+        };
+        let answer = match answer {
+            Ok(answer) => answer,
+            Err(err) => format!("error: {}", err),
+        };
+        let _ = writer.write_all(format!("{}\n", answer).as_bytes()).await;
+        let _ = writer.shutdown().await;
+        if stopping {
+            self.exit();
+        }
+    }
+
+    fn statuses(&self) -> Result<String, String> {
+        let targets = self.targets.lock().unwrap();
+        let status = self.status.lock().unwrap();
+        let lines: Vec<String> = targets
+            .iter()
+            .map(|target| {
+                let label = target.label();
+                status.get(&label).cloned().unwrap_or_else(|| format!("{}: pending", label))
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    }
+
+    // The target is given as the command line takes it.
+    fn add(&self, target: &str) -> Result<String, String> {
+        let target: Target = target.parse()?;
+        let mut targets = self.targets.lock().unwrap();
+        api::admit(&target, &self.opts, &targets).map_err(|(_, message)| message)?;
+        let label = target.label();
+        targets.push(target);
+        Ok(format!("added {}", label))
+    }
+
+    fn remove(&self, label: &str) -> Result<String, String> {
+        let mut targets = self.targets.lock().unwrap();
+        let index = targets.iter().position(|target| target.label() == label).ok_or_else(|| format!("{} is not pinged", label))?;
+        targets.remove(index);
+        self.status.lock().unwrap().remove(label);
+        Ok(format!("removed {}", label))
+    }
+
+    fn exit(&self) {
+        let _ = fs::remove_file(&self.path);
+        if let Some(pid_file) = &self.pid_file {
+            let _ = fs::remove_file(pid_file);
+        }
+        std::process::exit(0);
+    }
+}
+
+/// Sends `command` to the control socket at `path` and returns the answer,
+/// or the error it reported.
+pub fn send(path: &Path, command: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path).map_err(|err| io::Error::new(err.kind(), format!("cannot reach {}: {}", path.display(), err)))?;
+    writeln!(stream, "{}", command)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer)?;
+    let answer = answer.trim_end().to_string();
+    match answer.strip_prefix("error: ") {
+        Some(err) => Err(io::Error::new(io::ErrorKind::InvalidInput, err.to_string())),
+        None => Ok(answer),
+    }
+}
@@ -0,0 +1,307 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use crate::report::{rfc3339, unix_nanos, PingReport};
+use crate::stats;
+use crate::targets::Target;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS targets (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS summaries (
+    run INTEGER NOT NULL REFERENCES runs (id),
+    target INTEGER NOT NULL REFERENCES targets (id),
+    address TEXT,
+    sent INTEGER,
+    received INTEGER,
+    loss_percent REAL,
+    rtt_min REAL,
+    rtt_avg REAL,
+    rtt_max REAL,
+    rtt_p95 REAL,
+    timed_out INTEGER NOT NULL,
+    error TEXT
+);
+CREATE TABLE IF NOT EXISTS samples (
+    run INTEGER NOT NULL REFERENCES runs (id),
+    target INTEGER NOT NULL REFERENCES targets (id),
+    icmp_seq INTEGER NOT NULL,
+    ttl INTEGER NOT NULL,
+    bytes INTEGER NOT NULL,
+    rtt_ms REAL NOT NULL,
+    timestamp TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS samples_by_target ON samples (target, timestamp);
+";
+
+fn sql_error(err: rusqlite::Error) -> io::Error {
+    io::Error::other(format!("database: {}", err))
+}
+
+/// Statistics of one target over the runs in a time range.
+#[derive(Debug, Clone, Serialize)]
+pub struct History {
+    pub target: String,
+    pub runs: u32,
+    /// Runs in which the target could not be pinged at all.
+    pub errors: u32,
+    pub sent: u64,
+    pub received: u64,
+    pub loss_percent: f32,
+    pub min: Option<f32>,
+    pub avg: Option<f32>,
+    pub max: Option<f32>,
+    pub p95: Option<f32>,
+    /// Percentage of the runs in which the target answered.
+    pub availability: f32,
+    /// Stretches of runs in which the target did not answer.
+    pub outages: u32,
+    /// Mean seconds until the target answered again after an outage.
+    pub mttr: Option<u64>,
+    /// Seconds the longest outage lasted, up to the last run for one still going on.
+    pub longest_outage: Option<u64>,
+    /// When the first and the last of the runs started.
+    pub first: String,
+    pub last: String,
+}
+
+/// A SQLite file with the runs, targets, samples, and summaries of past pings.
+#[derive(Debug)]
+pub struct Database {
+    connection: Connection,
+    path: PathBuf,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it and its tables as needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Database> {
+        let connection = Connection::open(&path).map_err(sql_error)?;
+        connection.execute_batch(SCHEMA).map_err(sql_error)?;
+        Ok(Database { connection, path: path.as_ref().to_path_buf() })
+    }
+
+    /// Where the database is, to open it again elsewhere.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stores a run: the `results` `PingRunner::run` returned for `targets`.
+    pub fn record(&mut self, targets: &[Target], results: &[io::Result<PingReport>]) -> io::Result<()> {
+        let transaction = self.connection.transaction().map_err(sql_error)?;
+        transaction.execute("INSERT INTO runs (started) VALUES (?1)", params![rfc3339(SystemTime::now())]).map_err(sql_error)?;
+        let run = transaction.last_insert_rowid();
+        for (target, result) in targets.iter().zip(results) {
+            transaction
+                .execute("INSERT OR IGNORE INTO targets (name) VALUES (?1)", params![target.label()])
+                .map_err(sql_error)?;
+            let target: i64 = transaction
+                .query_row("SELECT id FROM targets WHERE name = ?1", params![target.label()], |row| row.get(0))
+                .map_err(sql_error)?;
+            let report = match result {
+                Ok(report) => report,
+                Err(err) => {
+                    transaction
+                        .execute(
+                            "INSERT INTO summaries (run, target, timed_out, error) VALUES (?1, ?2, 0, ?3)",
+                            params![run, target, err.to_string()],
+                        )
+                        .map_err(sql_error)?;
+                    continue;
+                },
+            };
+            let packets = report.packets.as_ref();
+            let trips = report.trips.as_ref();
+            transaction
+                .execute(
+                    "INSERT INTO summaries (run, target, address, sent, received, loss_percent, rtt_min, rtt_avg, rtt_max, rtt_p95, timed_out)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        run,
+                        target,
+                        report.address.map(|address| address.to_string()),
+                        packets.map(|packets| packets.transmitted),
+                        packets.map(|packets| packets.received),
+                        packets.map(|packets| packets.loss_percent),
+                        trips.map(|trips| trips.min),
+                        trips.map(|trips| trips.avg),
+                        trips.map(|trips| trips.max),
+                        report.samples.as_ref().map(|samples| samples.p95),
+                        report.timed_out,
+                    ],
+                )
+                .map_err(sql_error)?;
+            for ping in &report.pings {
+                transaction
+                    .execute(
+                        "INSERT INTO samples (run, target, icmp_seq, ttl, bytes, rtt_ms, timestamp) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![run, target, ping.icmp_seq, ping.ttl, ping.bytes_sent, ping.time, ping.timestamp],
+                    )
+                    .map_err(sql_error)?;
+            }
+        }
+        transaction.commit().map_err(sql_error)
+    }
+
+    /// Summarizes the runs that started in `since..until` (RFC 3339
+    /// timestamps, either end open when `None`), for `target` or for every
+    /// target in name order.
+    pub fn history(&self, target: Option<&str>, since: Option<&str>, until: Option<&str>) -> io::Result<Vec<History>> {
+        let names: Vec<String> = match target {
+            Some(target) => vec![target.to_string()],
+            None => {
+                let mut statement = self.connection.prepare("SELECT name FROM targets ORDER BY name").map_err(sql_error)?;
+                let names = statement.query_map([], |row| row.get(0)).map_err(sql_error)?;
+                names.collect::<Result<_, _>>().map_err(sql_error)?
+            },
+        };
+        let mut histories = Vec::new();
+        for name in names {
+            if let Some(history) = self.target_history(&name, since, until)? {
+                histories.push(history);
+            }
+        }
+        Ok(histories)
+    }
+
+    fn target_history(&self, name: &str, since: Option<&str>, until: Option<&str>) -> io::Result<Option<History>> {
+        // Open ends compare against bounds no timestamp reaches.
+        let since = since.unwrap_or("");
+        let until = until.unwrap_or("~");
+        let (runs, errors, sent, received, first, last): (u32, u32, f64, f64, Option<String>, Option<String>) = self
+            .connection
+            .query_row(
+                "SELECT COUNT(*), COUNT(summaries.error), TOTAL(summaries.sent), TOTAL(summaries.received), MIN(runs.started), MAX(runs.started)
+                 FROM summaries JOIN runs ON runs.id = summaries.run JOIN targets ON targets.id = summaries.target
+                 WHERE targets.name = ?1 AND runs.started >= ?2 AND runs.started < ?3",
+                params![name, since, until],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+            )
+            .map_err(sql_error)?;
+        let (Some(first), Some(last)) = (first, last) else {
+            return Ok(None);
+        };
+        let (sent, received) = (sent as u64, received as u64);
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT samples.rtt_ms FROM samples JOIN runs ON runs.id = samples.run JOIN targets ON targets.id = samples.target
+                 WHERE targets.name = ?1 AND runs.started >= ?2 AND runs.started < ?3 ORDER BY samples.rtt_ms",
+            )
+            .map_err(sql_error)?;
+        let times: Vec<f32> = statement
+            .query_map(params![name, since, until], |row| row.get(0))
+            .map_err(sql_error)?
+            .collect::<Result<_, _>>()
+            .map_err(sql_error)?;
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT runs.started, summaries.error IS NULL AND COALESCE(summaries.received, 0) > 0
+                 FROM summaries JOIN runs ON runs.id = summaries.run JOIN targets ON targets.id = summaries.target
+                 WHERE targets.name = ?1 AND runs.started >= ?2 AND runs.started < ?3 ORDER BY runs.started",
+            )
+            .map_err(sql_error)?;
+        let answered: Vec<(String, bool)> = statement
+            .query_map(params![name, since, until], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(sql_error)?
+            .collect::<Result<_, _>>()
+            .map_err(sql_error)?;
+        let up = answered.iter().filter(|(_, up)| *up).count();
+        let outages = outages(&answered);
+        let recovered: Vec<u64> = outages.iter().filter(|(_, recovered)| *recovered).map(|(seconds, _)| *seconds).collect();
+        let loss_percent = if sent == 0 { 0.0 } else { sent.saturating_sub(received) as f32 / sent as f32 * 100.0 };
+        Ok(Some(History {
+            target: name.to_string(),
+            runs,
+            errors,
+            sent,
+            received,
+            loss_percent,
+            min: times.first().copied(),
+            avg: (!times.is_empty()).then(|| stats::round_ms(times.iter().sum::<f32>() / times.len() as f32)),
+            max: times.last().copied(),
+            p95: (!times.is_empty()).then(|| stats::percentile(&times, 95.0)),
+            availability: up as f32 / runs.max(1) as f32 * 100.0,
+            outages: outages.len() as u32,
+            mttr: (!recovered.is_empty()).then(|| recovered.iter().sum::<u64>() / recovered.len() as u64),
+            longest_outage: outages.iter().map(|(seconds, _)| *seconds).max(),
+            first,
+            last,
+        }))
+    }
+}
+
+// How long every outage lasted, in seconds, from the start of the first run
+// the target did not answer in to the start of the next run it answered in,
+// and whether it did. An outage still going on lasts until the last run.
+fn outages(runs: &[(String, bool)]) -> Vec<(u64, bool)> {
+    let seconds = |from: &str, to: &str| match (unix_nanos(from), unix_nanos(to)) {
+        (Some(from), Some(to)) => ((to - from).max(0) / 1_000_000_000) as u64,
+        _ => 0,
+    };
+    let mut outages = Vec::new();
+    let mut down_since: Option<&str> = None;
+    for (started, up) in runs {
+        match (down_since, *up) {
+            (None, false) => down_since = Some(started),
+            (Some(since), true) => {
+                outages.push((seconds(since, started), true));
+                down_since = None;
+            },
+            _ => (),
+        }
+    }
+    if let (Some(since), Some((last, _))) = (down_since, runs.last()) {
+        outages.push((seconds(since, last), false));
+    }
+    outages
+}
+
+/// Turns a `report --since`/`--until` bound into an RFC 3339 timestamp:
+/// either a duration back from now, such as `30m`, `12h`, or `7d`, or the
+/// start of a timestamp, such as `2024-05-01` or `2024-05-01T12:00`.
+pub fn parse_time(value: &str) -> Result<String, String> {
+    let units = [('s', 1), ('m', 60), ('h', 3600), ('d', 86400)];
+    for (suffix, seconds) in units {
+        if let Some(amount) = value.strip_suffix(suffix).and_then(|amount| amount.parse::<u64>().ok()) {
+            let time = SystemTime::now().checked_sub(Duration::from_secs(amount * seconds)).unwrap_or(SystemTime::UNIX_EPOCH);
+            return Ok(rfc3339(time));
+        }
+    }
+    let well_formed = value.len() >= 10 && value.chars().all(|c| c.is_ascii_digit() || "-:.TZ".contains(c));
+    if !well_formed {
+        return Err(format!("invalid time '{}' (expected e.g. 7d, 12h, or 2024-05-01T12:00)", value));
+    }
+    Ok(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_back_from_now() {
+        let bound = |seconds| rfc3339(SystemTime::now() - Duration::from_secs(seconds));
+        let (earliest, time, latest) = (bound(12 * 3600), parse_time("12h").unwrap(), bound(12 * 3600));
+        assert!(earliest <= time && time <= latest, "{} not between {} and {}", time, earliest, latest);
+        let (earliest, time, latest) = (bound(7 * 86400), parse_time("7d").unwrap(), bound(7 * 86400));
+        assert!(earliest <= time && time <= latest, "{} not between {} and {}", time, earliest, latest);
+    }
+
+    #[test]
+    fn timestamps_as_given() {
+        assert_eq!(parse_time("2024-05-01"), Ok("2024-05-01".to_string()));
+        assert_eq!(parse_time("2024-05-01T12:00Z"), Ok("2024-05-01T12:00Z".to_string()));
+        for invalid in ["yesterday", "2024-05", "7w", "2024-05-01 12:00"] {
+            assert!(parse_time(invalid).is_err(), "{} parsed", invalid);
+        }
+    }
+}
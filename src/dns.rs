@@ -0,0 +1,206 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use crate::PingOptions;
+use crate::event::{EventKind, PingEvent};
+use crate::report::{PingInfo, PingReport, RunClock};
+use crate::stats::{keep, packet_statistics, round_ms, round_trip_statistics};
+use crate::udp::open;
+
+const HEADER_SIZE: usize = 12;
+const RECURSION_DESIRED: u16 = 0x0100;
+const CLASS_IN: u16 = 1;
+const NOERROR: u8 = 0;
+const NXDOMAIN: u8 = 3;
+const MAX_NAME: usize = 253;
+const MAX_LABEL: usize = 63;
+
+/// The kind of record DNS probes ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Cname,
+    Mx,
+    Ns,
+    Ptr,
+    Soa,
+    Txt,
+}
+
+impl FromStr for RecordType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(RecordType::A),
+            "AAAA" => Ok(RecordType::Aaaa),
+            "CNAME" => Ok(RecordType::Cname),
+            "MX" => Ok(RecordType::Mx),
+            "NS" => Ok(RecordType::Ns),
+            "PTR" => Ok(RecordType::Ptr),
+            "SOA" => Ok(RecordType::Soa),
+            "TXT" => Ok(RecordType::Txt),
+            _ => Err(format!("unknown record type '{}' (expected A, AAAA, CNAME, MX, NS, PTR, SOA, or TXT)", s)),
+        }
+    }
+}
+
+impl RecordType {
+    fn code(self) -> u16 {
+        match self {
+            RecordType::A => 1,
+            RecordType::Ns => 2,
+            RecordType::Cname => 5,
+            RecordType::Soa => 6,
+            RecordType::Ptr => 12,
+            RecordType::Mx => 15,
+            RecordType::Txt => 16,
+            RecordType::Aaaa => 28,
+        }
+    }
+}
+
+// A query asking the server to look `name` up recursively, with a zero
+// ID for each probe to fill in. `None` when the name is not valid.
+fn query(name: &str, record: RecordType) -> Option<Vec<u8>> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.len() > MAX_NAME {
+        return None;
+    }
+    let mut packet = vec![0u8; HEADER_SIZE];
+    packet[2..4].copy_from_slice(&RECURSION_DESIRED.to_be_bytes());
+    // A single question, and no records.
+    packet[4..6].copy_from_slice(&1u16.to_be_bytes());
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > MAX_LABEL {
+            return None;
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&record.code().to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Some(packet)
+}
+
+// The response code of the answer to query `id`, if the packet is one.
+fn response_code(packet: &[u8], id: u16) -> Option<u8> {
+    if packet.len() < HEADER_SIZE || packet[0..2] != id.to_be_bytes() || packet[2] & 0x80 == 0 {
+        return None;
+    }
+    Some(packet[3] & 0x0f)
+}
+
+// Sends the query and waits for its answer, returning the response code;
+// `None` when no answer came in time.
+async fn probe(address: SocketAddr, query: &[u8], id: u16, opts: &PingOptions) -> io::Result<Option<u8>> {
+    let socket = open(address, opts)?;
+    let mut buf = [0u8; 4096];
+    let exchange = async {
+        socket.connect(address).await?;
+        socket.send(query).await?;
+        loop {
+            let len = socket.recv(&mut buf).await?;
+            if let Some(code) = response_code(&buf[..len], id) {
+                return Ok::<_, io::Error>(code);
+            }
+        }
+    };
+    match tokio::time::timeout(opts.reply_timeout(), exchange).await {
+        Ok(Ok(code)) => Ok(Some(code)),
+        Ok(Err(_)) | Err(_) => Ok(None),
+    }
+}
+
+/// Sends a DNS query over UDP per probe to the target, a resolver, and
+/// measures how long the answer takes. Answers that the name exists or does
+/// not count as replies; failures such as SERVFAIL or REFUSED count as lost.
+pub(crate) async fn execute_ping(target: String, address: IpAddr, opts: &PingOptions, clock: RunClock, sender: mpsc::Sender<PingEvent>) -> Result<PingReport, io::Error> {
+    let port = opts.port.unwrap_or(opts.probe.default_port());
+    let address = SocketAddr::new(address, port);
+    let mut packet = query(&opts.dns_query, opts.dns_record)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("'{}' is not a valid name to look up", opts.dns_query)))?;
+    let _ = sender.send(PingEvent::new(&target, EventKind::Started { address: address.ip() })).await;
+    let mut pings = Vec::new();
+    let (mut seq, mut received) = (0, 0);
+    let mut next_probe = Instant::now();
+    while opts.more_probes(seq) {
+        seq += 1;
+        tokio::time::sleep_until(next_probe).await;
+        next_probe += opts.next_interval();
+        let id = (std::process::id() as u16).wrapping_add(seq as u16);
+        packet[0..2].copy_from_slice(&id.to_be_bytes());
+        let started = Instant::now();
+        match probe(address, &packet, id, opts).await? {
+            Some(NOERROR | NXDOMAIN) => {
+                let time = round_ms(started.elapsed().as_secs_f32() * 1000.0);
+                let mut ping = PingInfo {
+                    bytes_sent: packet.len() as u32,
+                    icmp_seq: seq,
+                    time,
+                    ..Default::default()
+                };
+                clock.stamp(&mut ping);
+                let _ = sender.send(PingEvent::new(&target, EventKind::Reply(ping.clone()))).await;
+                keep(&mut pings, ping, opts.unlimited());
+                received += 1;
+            },
+            answer => {
+                if let Some(code) = answer {
+                    tracing::debug!(%target, rcode = code, "DNS query failed");
+                }
+                let _ = sender.send(PingEvent::new(&target, EventKind::Lost { icmp_seq: seq })).await;
+            },
+        }
+    }
+    let packets = packet_statistics(seq, received);
+    let trips = round_trip_statistics(&pings);
+    Ok(PingReport {
+        destination: target,
+        pings,
+        packets: Some(packets),
+        trips,
+        timed_out: false,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_types() {
+        assert_eq!("aaaa".parse(), Ok(RecordType::Aaaa));
+        assert_eq!("MX".parse::<RecordType>().map(RecordType::code), Ok(15));
+        assert!("SRV".parse::<RecordType>().is_err());
+    }
+
+    #[test]
+    fn queries() {
+        let packet = query("example.com.", RecordType::Aaaa).unwrap();
+        let mut expected = vec![0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        expected.extend_from_slice(b"\x07example\x03com\x00");
+        expected.extend_from_slice(&[0, 28, 0, 1]);
+        assert_eq!(packet, expected);
+        assert!(query("a..b", RecordType::A).is_none());
+        assert!(query(&"a".repeat(64), RecordType::A).is_none());
+        assert!(query(&["a"; 127].join("."), RecordType::A).is_some());
+        assert!(query(&["a"; 128].join("."), RecordType::A).is_none());
+    }
+
+    #[test]
+    fn response_codes() {
+        let mut answer = vec![0x12, 0x34, 0x81, 0x83, 0, 1, 0, 0, 0, 0, 0, 0];
+        assert_eq!(response_code(&answer, 0x1234), Some(NXDOMAIN));
+        // Another probe's answer, a query, or a short packet is none.
+        assert_eq!(response_code(&answer, 0x1235), None);
+        answer[2] = 0x01;
+        assert_eq!(response_code(&answer, 0x1234), None);
+        assert_eq!(response_code(&answer[..11], 0x1234), None);
+    }
+}
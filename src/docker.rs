@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+use serde::Deserialize;
+use crate::http;
+use crate::targets::Target;
+use crate::MultipingError;
+
+const DEFAULT_HOST: &str = "unix:///var/run/docker.sock";
+const TIMEOUT: Duration = Duration::from_secs(10);
+// The label Compose marks the containers of a project with.
+const COMPOSE_PROJECT: &str = "com.docker.compose.project";
+
+/// The running containers of the Docker daemon at `host`, a `unix://` socket
+/// or a `tcp://` address as in `DOCKER_HOST` (the local socket when `None`),
+/// that have all of the `labels` (`KEY` or `KEY=VALUE`), as targets. They are
+/// named after the containers, carry their labels, and count towards the
+/// group of their Compose project, if any. Containers with no address of
+/// their own, such as those on the host network, are left out.
+pub async fn discover(host: Option<&str>, labels: &[String]) -> io::Result<Vec<Target>> {
+    let host = host.unwrap_or(DEFAULT_HOST);
+    let mut path = "/containers/json".to_string();
+    if !labels.is_empty() {
+        let filters = serde_json::json!({ "label": labels }).to_string();
+        path.push_str(&format!("?filters={}", http::encode(&filters)));
+    }
+    let (status, body) = if let Some(socket) = host.strip_prefix("unix://") {
+        request_socket(socket, &path).await?
+    } else if let Some(address) = host.strip_prefix("tcp://") {
+        http::get(&format!("http://{}{}", address, path), &[], &http::tls_connector(), TIMEOUT).await?
+    } else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported Docker host '{}' (expected unix:// or tcp://)", host)));
+    };
+    if status != 200 {
+        // The daemon says what went wrong in a message.
+        let message = serde_json::from_slice::<Failure>(&body).map_or_else(|_| String::from_utf8_lossy(&body).trim().to_string(), |failure| failure.message);
+        return Err(io::Error::other(format!("{}: HTTP {}: {}", host, status, message)));
+    }
+    let containers: Vec<Container> = serde_json::from_slice(&body).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", host, err)))?;
+    Ok(containers.into_iter().filter_map(Container::target).collect())
+}
+
+#[cfg(unix)]
+async fn request_socket(socket: &str, path: &str) -> io::Result<(u16, Vec<u8>)> {
+    let request = format!("GET {} HTTP/1.0\r\nHost: docker\r\nUser-Agent: multiping/{}\r\n\r\n", path, env!("CARGO_PKG_VERSION"));
+    let exchange = async {
+        let stream = tokio::net::UnixStream::connect(socket).await.map_err(|err| io::Error::new(err.kind(), format!("cannot reach Docker at {}: {}", socket, err)))?;
+        http::fetch(stream, request.as_bytes()).await
+    };
+    tokio::time::timeout(TIMEOUT, exchange).await.map_err(|_| MultipingError::Timeout(format!("{}: Docker did not answer", socket)))?
+}
+
+#[cfg(not(unix))]
+async fn request_socket(_: &str, _: &str) -> io::Result<(u16, Vec<u8>)> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Docker sockets only work on Unix; give a tcp:// --docker-host"))
+}
+
+#[derive(Deserialize)]
+struct Failure {
+    message: String,
+}
+
+// Just the parts of a container that make a target.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Container {
+    id: String,
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    network_settings: Option<NetworkSettings>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct NetworkSettings {
+    #[serde(default)]
+    networks: BTreeMap<String, Network>,
+}
+
+#[derive(Deserialize)]
+struct Network {
+    #[serde(rename = "IPAddress", default)]
+    ip_address: String,
+    #[serde(rename = "GlobalIPv6Address", default)]
+    ipv6_address: String,
+}
+
+impl Container {
+    // The address on the first network, by name, that gives it one.
+    fn target(self) -> Option<Target> {
+        let networks = self.network_settings?.networks;
+        let address: IpAddr = networks.values().flat_map(|network| [&network.ip_address, &network.ipv6_address]).find_map(|address| address.parse().ok())?;
+        let name = self.names.first().map_or_else(|| self.id.chars().take(12).collect(), |name| name.trim_start_matches('/').to_string());
+        let group = self.labels.get(COMPOSE_PROJECT).cloned();
+        Some(Target { host: address.to_string(), name: Some(name), group, labels: self.labels, address: Some(address), ..Default::default() })
+    }
+}
@@ -0,0 +1,225 @@
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use serde::{de, Deserialize, Deserializer};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use crate::IpVersion;
+use crate::alert::Alert;
+use crate::http::tls_connector;
+use crate::report::{rfc3339, unix_nanos};
+use crate::resolve::resolve;
+
+// How long the whole exchange with the server may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the connection to the mail server is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    /// TLS from the start, usually on port 465.
+    Tls,
+    /// A plain connection upgraded with STARTTLS, usually on port 587.
+    StartTls,
+    /// No encryption, for relays on the local network.
+    None,
+}
+
+impl FromStr for Security {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tls" => Ok(Security::Tls),
+            "starttls" => Ok(Security::StartTls),
+            "none" => Ok(Security::None),
+            _ => Err(format!("unknown security '{}' (expected tls, starttls, or none)", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Security {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+/// A mail server to send alert summaries through, and who gets them.
+///
+/// ```toml
+/// [email]
+/// server = "smtp.example.com"
+/// username = "alerts@example.com"
+/// password = "..."
+/// from = "alerts@example.com"
+/// to = ["oncall@example.com"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Email {
+    pub server: String,
+    /// 465 for TLS, 25 for none, and 587 otherwise when left out.
+    pub port: Option<u16>,
+    /// STARTTLS when left out, unless the port is 465.
+    pub security: Option<Security>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl Email {
+    fn security(&self) -> Security {
+        match (self.security, self.port) {
+            (Some(security), _) => security,
+            (None, Some(465)) => Security::Tls,
+            (None, _) => Security::StartTls,
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.security() {
+            Security::Tls => 465,
+            Security::StartTls => 587,
+            Security::None => 25,
+        })
+    }
+
+    /// Mails a summary of the `alerts` to the recipients.
+    pub async fn send(&self, alerts: &[Alert]) -> io::Result<()> {
+        if self.username.is_some() && self.security() == Security::None {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "refusing to send the password over an unencrypted connection"));
+        }
+        tokio::time::timeout(TIMEOUT, self.deliver(&self.message(alerts, SystemTime::now())))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("{}: the mail server timed out", self.server)))?
+    }
+
+    fn message(&self, alerts: &[Alert], now: SystemTime) -> String {
+        let subject = match alerts {
+            [alert] => format!("multiping: {} is down", alert.target),
+            _ => format!("multiping: {} targets are down", alerts.len()),
+        };
+        let mut message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+            self.from, self.to.join(", "), subject, date(now)
+        );
+        message.push_str("These targets went down:\r\n\r\n");
+        for alert in alerts {
+            message.push_str(&format!("{}: {}", alert.target, alert.reason));
+            let mut figures = Vec::new();
+            if let Some(loss) = alert.loss_percent {
+                figures.push(format!("{}% loss", loss));
+            }
+            if let Some(avg) = alert.avg {
+                figures.push(format!("{} ms average", avg));
+            }
+            if !figures.is_empty() {
+                message.push_str(&format!(" ({})", figures.join(", ")));
+            }
+            message.push_str("\r\n");
+        }
+        message
+    }
+
+    async fn deliver(&self, message: &str) -> io::Result<()> {
+        let address = resolve(&self.server, IpVersion::Any, TIMEOUT).await?;
+        let stream = TcpStream::connect((address, self.port())).await?;
+        let name = || ServerName::try_from(self.server.clone()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err));
+        match self.security() {
+            Security::Tls => {
+                let stream = tls_connector().connect(name()?, stream).await?;
+                self.session(BufReader::new(stream), message, false).await
+            },
+            Security::StartTls => {
+                let mut stream = BufReader::new(stream);
+                reply(&mut stream, 220).await?;
+                command(&mut stream, "EHLO localhost", 250).await?;
+                command(&mut stream, "STARTTLS", 220).await?;
+                let stream = tls_connector().connect(name()?, stream.into_inner()).await?;
+                self.session(BufReader::new(stream), message, true).await
+            },
+            Security::None => self.session(BufReader::new(stream), message, false).await,
+        }
+    }
+
+    // Everything after the connection is set up. A server that already
+    // greeted, before STARTTLS, does not again.
+    async fn session<S: AsyncRead + AsyncWrite + Unpin>(&self, mut stream: BufReader<S>, message: &str, greeted: bool) -> io::Result<()> {
+        if !greeted {
+            reply(&mut stream, 220).await?;
+        }
+        command(&mut stream, "EHLO localhost", 250).await?;
+        if let Some(username) = &self.username {
+            let credentials = format!("\0{}\0{}", username, self.password.as_deref().unwrap_or(""));
+            command(&mut stream, &format!("AUTH PLAIN {}", base64(credentials.as_bytes())), 235).await?;
+        }
+        command(&mut stream, &format!("MAIL FROM:<{}>", self.from), 250).await?;
+        for recipient in &self.to {
+            command(&mut stream, &format!("RCPT TO:<{}>", recipient), 250).await?;
+        }
+        command(&mut stream, "DATA", 354).await?;
+        // Lines starting with a dot get another, so none ends the message early.
+        let body: Vec<String> = message
+            .split("\r\n")
+            .map(|line| if line.starts_with('.') { format!(".{}", line) } else { line.to_string() })
+            .collect();
+        command(&mut stream, &format!("{}\r\n.", body.join("\r\n")), 250).await?;
+        command(&mut stream, "QUIT", 221).await
+    }
+}
+
+// Sends a command and checks the server's reply has the `expected` code.
+async fn command<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut BufReader<S>, line: &str, expected: u16) -> io::Result<()> {
+    stream.get_mut().write_all(format!("{}\r\n", line).as_bytes()).await?;
+    reply(stream, expected).await
+}
+
+// Reads a reply, which continues over the lines with a dash after the code.
+async fn reply<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut BufReader<S>, expected: u16) -> io::Result<()> {
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the mail server closed the connection"));
+        }
+        let code = line.get(..3).and_then(|code| code.parse::<u16>().ok());
+        if code != Some(expected) {
+            return Err(io::Error::other(format!("the mail server answered: {}", line.trim_end())));
+        }
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(());
+        }
+    }
+}
+
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::new();
+    for chunk in input.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                output.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+// An RFC 5322 date, as in `Tue, 14 Oct 2026 14:48:29 +0000`.
+fn date(time: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let timestamp = rfc3339(time);
+    let field = |range: std::ops::Range<usize>| timestamp.get(range).unwrap_or("");
+    let month = field(5..7).parse::<usize>().unwrap_or(1);
+    // 1970-01-01 was a Thursday.
+    let days = unix_nanos(&timestamp).unwrap_or(0) / 86_400_000_000_000;
+    format!(
+        "{}, {} {} {} {} +0000",
+        DAYS[days.rem_euclid(7) as usize], field(8..10), MONTHS[month.clamp(1, 12) - 1], field(0..4), field(11..19)
+    )
+}
@@ -0,0 +1,99 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use serde::{Deserialize, Serialize};
+
+/// Why a target could not be pinged. Errors travel as `io::Error`s; the
+/// ones multiping raises carry a `MultipingError` inside, and `from`
+/// classifies the others by their kind.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum MultipingError {
+    /// The system ping could not be started, or failed before it reported.
+    SpawnFailed(String),
+    /// The target's name has no usable address.
+    Unresolvable(String),
+    Timeout(String),
+    /// The system ping said something multiping does not understand.
+    ParseFailure(String),
+    /// Probing needs privileges the process does not have.
+    PermissionDenied(String),
+    Other(String),
+}
+
+impl MultipingError {
+    pub fn message(&self) -> &str {
+        match self {
+            MultipingError::SpawnFailed(message)
+            | MultipingError::Unresolvable(message)
+            | MultipingError::Timeout(message)
+            | MultipingError::ParseFailure(message)
+            | MultipingError::PermissionDenied(message)
+            | MultipingError::Other(message) => message,
+        }
+    }
+
+    /// The kind of the error, as it is named in JSON reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MultipingError::SpawnFailed(_) => "spawn_failed",
+            MultipingError::Unresolvable(_) => "unresolvable",
+            MultipingError::Timeout(_) => "timeout",
+            MultipingError::ParseFailure(_) => "parse_failure",
+            MultipingError::PermissionDenied(_) => "permission_denied",
+            MultipingError::Other(_) => "other",
+        }
+    }
+
+    /// The exit status of a run in which a target failed this way. Status 1
+    /// is left for targets that crossed a threshold.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            MultipingError::Other(_) => 2,
+            MultipingError::SpawnFailed(_) => 3,
+            MultipingError::Unresolvable(_) => 4,
+            MultipingError::Timeout(_) => 5,
+            MultipingError::ParseFailure(_) => 6,
+            MultipingError::PermissionDenied(_) => 7,
+        }
+    }
+
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            MultipingError::Unresolvable(_) => io::ErrorKind::NotFound,
+            MultipingError::Timeout(_) => io::ErrorKind::TimedOut,
+            MultipingError::ParseFailure(_) => io::ErrorKind::InvalidData,
+            MultipingError::PermissionDenied(_) => io::ErrorKind::PermissionDenied,
+            MultipingError::SpawnFailed(_) | MultipingError::Other(_) => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl fmt::Display for MultipingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl Error for MultipingError {}
+
+impl From<MultipingError> for io::Error {
+    fn from(err: MultipingError) -> io::Error {
+        io::Error::new(err.kind(), err)
+    }
+}
+
+impl From<&io::Error> for MultipingError {
+    fn from(err: &io::Error) -> MultipingError {
+        if let Some(err) = err.get_ref().and_then(|inner| inner.downcast_ref::<MultipingError>()) {
+            return err.clone();
+        }
+        let message = err.to_string();
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => MultipingError::PermissionDenied(message),
+            io::ErrorKind::TimedOut => MultipingError::Timeout(message),
+            io::ErrorKind::InvalidData => MultipingError::ParseFailure(message),
+            _ => MultipingError::Other(message),
+        }
+    }
+}
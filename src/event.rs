@@ -0,0 +1,37 @@
+use std::net::IpAddr;
+use crate::error::MultipingError;
+use crate::report::{PacketStatistics, PingError, PingInfo, PingReport};
+
+/// Something that happened while pinging `target`, as sent to the channel
+/// given to `PingRunner::run_with_sender`.
+#[derive(Debug, Clone)]
+pub struct PingEvent {
+    pub target: String,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum EventKind {
+    /// The target resolved to `address` and the first probe is going out.
+    Started { address: IpAddr },
+    Reply(PingInfo),
+    /// Probe `icmp_seq` got no answer in time.
+    Lost { icmp_seq: u32 },
+    /// A probe was answered with an ICMP error.
+    IcmpError(PingError),
+    /// Packet counts reported by the system ping when it exits.
+    Statistics(PacketStatistics),
+    /// Last event of a target that was pinged.
+    Finished(Box<PingReport>),
+    /// Last event of a target that could not be pinged.
+    Failed(MultipingError),
+    /// The hops to the target differ from the previous trace, in monitor
+    /// mode. Hops are given by the router that answered, if any did.
+    PathChanged { previous: Vec<Option<String>>, current: Vec<Option<String>> },
+}
+
+impl PingEvent {
+    pub(crate) fn new(target: &str, kind: EventKind) -> PingEvent {
+        PingEvent { target: target.to_string(), kind }
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdout, Command};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use crate::parser::{parse_fping_error_line, parse_fping_line, parse_fping_statistics, parse_fping_timeout_line};
+use crate::{stopped, Dscp, IpVersion, PingOptions};
+use crate::error::MultipingError;
+use crate::event::{EventKind, PingEvent};
+use crate::report::{PacketStatistics, PingError, PingInfo, PingReport, RunClock};
+use crate::stats::{keep, packet_statistics, round_trip_statistics};
+use crate::system::{interrupt, STOP_POLL};
+
+// What fping printed about one of its targets.
+#[derive(Debug)]
+pub(crate) enum Output {
+    Reply(PingInfo),
+    Lost(u32),
+    Error(PingError),
+    Statistics(PacketStatistics),
+    // A line about no target in particular, usually why fping failed.
+    Message(String),
+}
+
+/// The output of the fping process a target is pinged by, or why the
+/// process could not be started.
+pub(crate) type Session = Result<mpsc::UnboundedReceiver<Output>, MultipingError>;
+
+// fping applies its options to every target it is given, and takes each
+// address once, so targets share a process when they agree on both.
+#[derive(Debug, Clone, PartialEq)]
+struct Settings {
+    count: u32,
+    timeout: u32,
+    interval: f32,
+    size: usize,
+    ip_version: IpVersion,
+    source_ip: Option<IpAddr>,
+    interface: Option<String>,
+    dscp: Option<Dscp>,
+    ttl: Option<u32>,
+}
+
+impl From<&PingOptions> for Settings {
+    fn from(opts: &PingOptions) -> Settings {
+        Settings {
+            count: opts.count,
+            timeout: opts.timeout,
+            interval: opts.interval,
+            size: opts.size,
+            ip_version: opts.ip_version,
+            source_ip: opts.source_ip,
+            interface: opts.interface.clone(),
+            dscp: opts.dscp,
+            ttl: opts.ttl,
+        }
+    }
+}
+
+fn fping_command(addresses: &[IpAddr], opts: &PingOptions) -> Command {
+    let mut command = Command::new("fping");
+    if opts.unlimited() {
+        command.arg("-l");
+    } else {
+        command.arg("-c").arg(opts.count.to_string());
+    }
+    // fping warns about timeouts longer than the period, and counts slower
+    // replies as lost either way. Only the default timeout gets here
+    // longer; those given are refused before fping starts.
+    let period = (opts.interval * 1000.0).round() as u64;
+    command.arg("-p").arg(period.to_string());
+    command.arg("-t").arg((opts.timeout as u64 * 1000).min(period).to_string());
+    command.arg("-b").arg(opts.size.to_string());
+    match opts.ip_version {
+        IpVersion::Any => (),
+        IpVersion::V4 => { command.arg("-4"); },
+        IpVersion::V6 => { command.arg("-6"); },
+    }
+    if let Some(source) = opts.source_ip {
+        command.arg("-S").arg(source.to_string());
+    }
+    if let Some(interface) = &opts.interface {
+        command.arg("-I").arg(interface);
+    }
+    if let Some(dscp) = opts.dscp {
+        command.arg("-O").arg(dscp.tos().to_string());
+    }
+    if let Some(ttl) = opts.ttl {
+        command.arg("-H").arg(ttl.to_string());
+    }
+    command.args(addresses.iter().map(IpAddr::to_string));
+    command.env("LC_ALL", "C");
+    command
+}
+
+/// Starts fping for the targets given, one process per set of options, and
+/// returns each target's session in the same order as `targets`. Targets
+/// given as `None` are pinged some other way.
+pub(crate) fn start(targets: Vec<Option<(IpAddr, PingOptions)>>) -> Vec<Option<Session>> {
+    let mut processes: Vec<(PingOptions, Vec<IpAddr>)> = Vec::new();
+    let mut placed = Vec::new();
+    for target in &targets {
+        let Some((address, opts)) = target else {
+            placed.push(None);
+            continue;
+        };
+        let settings = Settings::from(opts);
+        let index = processes
+            .iter()
+            .position(|(shared, addresses)| Settings::from(shared) == settings && !addresses.contains(address))
+            .unwrap_or_else(|| {
+                processes.push((opts.clone(), Vec::new()));
+                processes.len() - 1
+            });
+        processes[index].1.push(*address);
+        placed.push(Some(index));
+    }
+    let mut sessions: Vec<HashMap<IpAddr, Session>> = processes
+        .into_iter()
+        .map(|(opts, addresses)| spawn(&addresses, &opts))
+        .collect();
+    targets
+        .iter()
+        .zip(placed)
+        .map(|(target, index)| sessions[index?].remove(&target.as_ref()?.0))
+        .collect()
+}
+
+fn spawn(addresses: &[IpAddr], opts: &PingOptions) -> HashMap<IpAddr, Session> {
+    let child = fping_command(addresses, opts)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => {
+            tracing::debug!(?addresses, pid = child.id(), "fping started");
+            child
+        },
+        Err(err) => {
+            let err = MultipingError::SpawnFailed(format!("cannot run fping ({})", err));
+            return addresses.iter().map(|address| (*address, Err(err.clone()))).collect();
+        },
+    };
+    let mut routes = HashMap::new();
+    let mut sessions = HashMap::new();
+    for address in addresses {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        routes.insert(address.to_string(), sender);
+        sessions.insert(*address, Ok(receiver));
+    }
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    if let (Some(stdout), Some(stderr)) = (stdout, stderr) {
+        tokio::spawn(dispatch(child, stdout, stderr, routes, opts.unlimited()));
+    }
+    sessions
+}
+
+// Replies come on standard output and everything else on standard error.
+async fn dispatch(mut child: Child, stdout: ChildStdout, stderr: ChildStderr, routes: HashMap<String, mpsc::UnboundedSender<Output>>, unlimited: bool) {
+    let mut stdout = BufReader::new(stdout).lines();
+    let mut stderr = BufReader::new(stderr).lines();
+    let (mut stdout_open, mut stderr_open) = (true, true);
+    let mut interrupted = false;
+    let mut poll = tokio::time::interval(STOP_POLL);
+    while stdout_open || stderr_open {
+        if unlimited && stopped() && !interrupted {
+            interrupt(&mut child);
+            interrupted = true;
+        }
+        // Nobody is waiting for the output once every target timed out.
+        if routes.values().all(|route| route.is_closed()) {
+            break;
+        }
+        tokio::select! {
+            line = stdout.next_line(), if stdout_open => match line {
+                Ok(Some(line)) => route(&routes, &line),
+                _ => stdout_open = false,
+            },
+            line = stderr.next_line(), if stderr_open => match line {
+                Ok(Some(line)) => route(&routes, &line),
+                _ => stderr_open = false,
+            },
+            _ = poll.tick() => (),
+        }
+    }
+    let _ = child.kill().await;
+}
+
+fn route(routes: &HashMap<String, mpsc::UnboundedSender<Output>>, line: &str) {
+    tracing::trace!(%line, "fping output");
+    let (host, output) = if let Some((host, ping)) = parse_fping_line(line) {
+        (host, Output::Reply(ping))
+    } else if let Some((host, icmp_seq)) = parse_fping_timeout_line(line) {
+        (host, Output::Lost(icmp_seq))
+    } else if let Some((host, error)) = parse_fping_error_line(line) {
+        (host, Output::Error(error))
+    } else if let Some((host, statistics)) = parse_fping_statistics(line) {
+        (host, Output::Statistics(statistics))
+    } else {
+        tracing::debug!(%line, "unparsed fping output");
+        for route in routes.values() {
+            let _ = route.send(Output::Message(line.trim().to_string()));
+        }
+        return;
+    };
+    if let Some(route) = routes.get(&host) {
+        let _ = route.send(output);
+    }
+}
+
+pub async fn execute_ping(target: String, address: IpAddr, opts: &PingOptions, clock: RunClock, sender: mpsc::Sender<PingEvent>, session: Session) -> Result<PingReport, io::Error> {
+    let mut output = session.map_err(|err| MultipingError::SpawnFailed(format!("{}: {}", target, err)))?;
+    // fping pings all of its targets from the start, like ping does one.
+    let deadline = Instant::now() + opts.probe_interval() * opts.count + Duration::from_secs(opts.timeout as u64);
+    let _ = sender.send(PingEvent::new(&target, EventKind::Started { address })).await;
+    let mut pings = Vec::new();
+    let mut errors = Vec::new();
+    let mut received = 0;
+    let mut packets = None;
+    let mut message = None;
+    let mut timed_out = false;
+    loop {
+        let next = if opts.unlimited() {
+            output.recv().await
+        } else {
+            match tokio::time::timeout_at(deadline, output.recv()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    timed_out = true;
+                    break;
+                },
+            }
+        };
+        match next {
+            Some(Output::Reply(mut ping)) => {
+                clock.stamp(&mut ping);
+                let _ = sender.send(PingEvent::new(&target, EventKind::Reply(ping.clone()))).await;
+                received += !ping.duplicate as u32;
+                keep(&mut pings, ping, opts.unlimited());
+            },
+            Some(Output::Lost(icmp_seq)) => {
+                let _ = sender.send(PingEvent::new(&target, EventKind::Lost { icmp_seq })).await;
+            },
+            Some(Output::Error(error)) => {
+                let _ = sender.send(PingEvent::new(&target, EventKind::IcmpError(error.clone()))).await;
+                keep(&mut errors, error, opts.unlimited());
+            },
+            Some(Output::Statistics(statistics)) => {
+                let _ = sender.send(PingEvent::new(&target, EventKind::Statistics(statistics.clone()))).await;
+                packets = Some(statistics);
+            },
+            Some(Output::Message(line)) => message = Some(line),
+            None => break,
+        }
+    }
+    // fping has no standard deviation in its summary, so the round trip
+    // statistics come from the samples.
+    let trips = round_trip_statistics(&pings);
+    let packets = match packets {
+        Some(packets) => packets,
+        None if timed_out => packet_statistics(opts.count, received),
+        None => {
+            let reason = message.unwrap_or_else(|| "fping exited without reporting it".to_string());
+            return Err(MultipingError::SpawnFailed(format!("{}: {}", target, reason)).into());
+        },
+    };
+    Ok(PingReport {
+        destination: target,
+        pings,
+        errors,
+        packets: Some(packets),
+        trips,
+        timed_out,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::io;
+use serde::Serialize;
+use crate::report::PingReport;
+use crate::stats;
+use crate::targets::Target;
+
+/// Aggregates over the targets of one group.
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupSummary {
+    pub group: String,
+    pub targets: usize,
+    /// Targets that answered at least one probe.
+    pub reachable: usize,
+    /// Percentage of the group's targets that were reachable.
+    pub availability: f32,
+    /// Highest loss percentage of any target; targets that could not be
+    /// pinged count as 100.
+    pub worst_loss: f32,
+    /// Mean of the targets' average round trip times, in milliseconds.
+    pub mean_avg: Option<f32>,
+}
+
+#[derive(Default)]
+struct Totals {
+    targets: usize,
+    reachable: usize,
+    worst_loss: f32,
+    avgs: Vec<f32>,
+}
+
+/// Summarizes the results of every group of `targets`, in group name order.
+/// `results` are the ones `PingRunner::run` returned for `targets`.
+pub fn summarize(targets: &[Target], results: &[io::Result<PingReport>]) -> Vec<GroupSummary> {
+    let mut totals: BTreeMap<&str, Totals> = BTreeMap::new();
+    for (target, result) in targets.iter().zip(results) {
+        let Some(group) = &target.group else {
+            continue;
+        };
+        let totals = totals.entry(group).or_default();
+        totals.targets += 1;
+        let report = result.as_ref().ok();
+        let packets = report.and_then(|report| report.packets.as_ref());
+        let reachable = match packets {
+            Some(packets) => packets.received > 0,
+            None => report.is_some_and(|report| !report.pings.is_empty()),
+        };
+        totals.reachable += reachable as usize;
+        let loss = packets.map_or(if reachable { 0.0 } else { 100.0 }, |packets| packets.loss_percent);
+        totals.worst_loss = totals.worst_loss.max(loss);
+        if let Some(trips) = report.and_then(|report| report.trips.as_ref()) {
+            totals.avgs.push(trips.avg);
+        }
+    }
+    totals
+        .into_iter()
+        .map(|(group, totals)| GroupSummary {
+            group: group.to_string(),
+            targets: totals.targets,
+            reachable: totals.reachable,
+            availability: totals.reachable as f32 / totals.targets as f32 * 100.0,
+            worst_loss: totals.worst_loss,
+            mean_avg: (!totals.avgs.is_empty())
+                .then(|| stats::round_ms(totals.avgs.iter().sum::<f32>() / totals.avgs.len() as f32)),
+        })
+        .collect()
+}
+
+/// Aggregates over every target of a run.
+#[derive(Debug, Clone)]
+pub struct FleetSummary {
+    pub targets: usize,
+    /// Targets that could not be pinged at all.
+    pub failed: usize,
+    pub transmitted: u32,
+    pub received: u32,
+    /// Loss over the probes of every target together.
+    pub loss_percent: f32,
+    /// Mean of the targets' average round trip times, in milliseconds.
+    pub mean_avg: Option<f32>,
+    /// The targets with the lowest and the highest average round trip.
+    pub fastest: Option<(String, f32)>,
+    pub slowest: Option<(String, f32)>,
+}
+
+/// Summarizes `results` all together, whatever their groups.
+pub fn fleet(results: &[io::Result<PingReport>]) -> FleetSummary {
+    let reports: Vec<&PingReport> = results.iter().filter_map(|result| result.as_ref().ok()).collect();
+    let packets = reports.iter().filter_map(|report| report.packets.as_ref());
+    let (transmitted, received) = packets.fold((0, 0), |(sent, received), packets| (sent + packets.transmitted, received + packets.received));
+    let mut avgs: Vec<(String, f32)> = reports
+        .iter()
+        .filter_map(|report| Some((report.destination.clone(), report.trips.as_ref()?.avg)))
+        .collect();
+    avgs.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let loss_percent = stats::packet_statistics(transmitted, received).loss_percent;
+    FleetSummary {
+        targets: results.len(),
+        failed: results.len() - reports.len(),
+        transmitted,
+        received,
+        loss_percent: (loss_percent * 100.0).round() / 100.0,
+        mean_avg: (!avgs.is_empty()).then(|| stats::round_ms(avgs.iter().map(|(_, avg)| avg).sum::<f32>() / avgs.len() as f32)),
+        fastest: avgs.first().cloned(),
+        slowest: avgs.last().cloned(),
+    }
+}
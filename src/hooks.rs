@@ -0,0 +1,56 @@
+use tokio::process::Command;
+use crate::monitor::{State, Transition};
+
+/// Commands run when a target goes down or comes back up under continuous
+/// pinging.
+///
+/// The commands run in the shell with the `TARGET`, `LOSS` (percent), and
+/// `AVG_RTT` (milliseconds) environment variables set from the round that
+/// changed the state; the last two are empty when unknown.
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pub on_down: Option<String>,
+    pub on_up: Option<String>,
+}
+
+impl Hooks {
+    /// Starts the command for `transition`, if there is one, without
+    /// waiting for it.
+    pub fn run(&self, transition: &Transition) {
+        let command = match (transition.from, transition.to) {
+            (_, State::Down) => &self.on_down,
+            (State::Down, State::Up) => &self.on_up,
+            _ => return,
+        };
+        if let Some(command) = command {
+            run(command, transition);
+        }
+    }
+}
+
+fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    let (shell, flag) = ("cmd", "/C");
+    #[cfg(not(windows))]
+    let (shell, flag) = ("sh", "-c");
+    let mut child = Command::new(shell);
+    child.arg(flag).arg(command);
+    child
+}
+
+fn run(command: &str, transition: &Transition) {
+    let target = &transition.target;
+    let mut child = shell(command);
+    child
+        .env("TARGET", target)
+        .env("LOSS", transition.loss_percent.map_or(String::new(), |loss| loss.to_string()))
+        .env("AVG_RTT", transition.avg.map_or(String::new(), |avg| avg.to_string()));
+    match child.spawn() {
+        Ok(mut child) => {
+            tokio::spawn(async move {
+                let _ = child.wait().await;
+            });
+        },
+        Err(err) => eprintln!("Cannot run hook for {}: {}", target, err),
+    }
+}
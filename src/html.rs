@@ -0,0 +1,123 @@
+use std::fmt::Write as _;
+use std::io;
+use std::io::Write;
+use std::time::SystemTime;
+use crate::report::{rfc3339, PingReport};
+
+const CHART_WIDTH: f64 = 720.0;
+const CHART_HEIGHT: f64 = 160.0;
+// Room for the axis labels.
+const MARGIN_X: f64 = 64.0;
+const MARGIN_Y: f64 = 24.0;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+.bad { color: #c0392b; font-weight: bold; }
+svg { background: #fafafa; border: 1px solid #ddd; }
+svg text { font-size: 11px; fill: #555; }
+";
+
+pub(crate) fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn cell<T: ToString>(value: Option<T>) -> String {
+    value.map_or("-".to_string(), |value| value.to_string())
+}
+
+// Round trip times over the run, as an SVG line chart.
+fn chart(report: &PingReport) -> String {
+    if report.pings.is_empty() {
+        return "<p>No replies.</p>\n".to_string();
+    }
+    let start = report.pings.iter().map(|ping| ping.offset).fold(f64::INFINITY, f64::min);
+    let end = report.pings.iter().map(|ping| ping.offset).fold(f64::NEG_INFINITY, f64::max);
+    let max = report.pings.iter().map(|ping| ping.time as f64).fold(0.0, f64::max);
+    let span = (end - start).max(f64::EPSILON);
+    let top = if max > 0.0 { max } else { 1.0 };
+    let (width, height) = (CHART_WIDTH - 2.0 * MARGIN_X, CHART_HEIGHT - 2.0 * MARGIN_Y);
+    let point = |offset: f64, time: f64| (MARGIN_X + (offset - start) / span * width, MARGIN_Y + height - time / top * height);
+    let mut pings: Vec<_> = report.pings.iter().collect();
+    pings.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+    let mut svg = String::new();
+    let _ = writeln!(svg, "<svg width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">", CHART_WIDTH, CHART_HEIGHT, CHART_WIDTH, CHART_HEIGHT);
+    let (left, bottom) = point(start, 0.0);
+    let (right, high) = point(end, top);
+    let _ = writeln!(svg, "<polyline points=\"{},{} {},{} {},{}\" fill=\"none\" stroke=\"#999\"/>", left, high, left, bottom, right, bottom);
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{:.3} ms</text>", left - 4.0, high + 4.0, top);
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">0</text>", left - 4.0, bottom + 4.0);
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\">{:.1} s</text>", left, bottom + 16.0, start);
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{:.1} s</text>", right, bottom + 16.0, end);
+    let line: Vec<String> = pings
+        .iter()
+        .map(|ping| {
+            let (x, y) = point(ping.offset, ping.time as f64);
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect();
+    let _ = writeln!(svg, "<polyline points=\"{}\" fill=\"none\" stroke=\"#2980b9\" stroke-width=\"1.5\"/>", line.join(" "));
+    for ping in &pings {
+        let (x, y) = point(ping.offset, ping.time as f64);
+        let _ = writeln!(
+            svg,
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2\" fill=\"#2980b9\"><title>icmp_seq={} {} ms</title></circle>",
+            x, y, ping.icmp_seq, ping.time
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Writes a standalone HTML page with a summary table and a latency chart
+/// per target.
+pub fn write_html<W: Write>(results: &[PingReport], writer: &mut W) -> io::Result<()> {
+    let mut page = String::new();
+    let _ = writeln!(page, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>multiping report</title>");
+    let _ = writeln!(page, "<style>{}</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(page, "<h1>multiping report</h1>\n<p>Generated {}</p>", rfc3339(SystemTime::now()));
+    page.push_str("<table>\n<tr><th>Target</th><th>Address</th><th>Sent</th><th>Recv</th><th>Loss</th><th>Min</th><th>Avg</th><th>Max</th><th>P95</th><th>Jitter</th></tr>\n");
+    for (index, report) in results.iter().enumerate() {
+        let packets = report.packets.as_ref();
+        let trips = report.trips.as_ref();
+        let samples = report.samples.as_ref();
+        let loss = packets.map(|packets| packets.loss_percent);
+        let loss_class = if loss.is_some_and(|loss| loss > 0.0) { " class=\"bad\"" } else { "" };
+        let _ = writeln!(
+            page,
+            "<tr><td><a href=\"#target-{}\">{}</a>{}</td><td>{}</td><td>{}</td><td>{}</td><td{}>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            index,
+            escape(&report.destination),
+            if report.timed_out { " (timed out)" } else { "" },
+            cell(report.address),
+            cell(packets.map(|packets| packets.transmitted)),
+            cell(packets.map(|packets| packets.received)),
+            loss_class,
+            cell(loss.map(|loss| format!("{}%", loss))),
+            cell(trips.map(|trips| trips.min)),
+            cell(trips.map(|trips| trips.avg)),
+            cell(trips.map(|trips| trips.max)),
+            cell(samples.map(|samples| samples.p95)),
+            cell(samples.map(|samples| samples.jitter)),
+        );
+    }
+    page.push_str("</table>\n");
+    for (index, report) in results.iter().enumerate() {
+        let _ = writeln!(page, "<h2 id=\"target-{}\">{}</h2>", index, escape(&report.destination));
+        page.push_str(&chart(report));
+    }
+    page.push_str("</body>\n</html>\n");
+    writer.write_all(page.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markup_is_escaped() {
+        assert_eq!(escape(r#"<a href="x">&</a>"#), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+}
@@ -0,0 +1,263 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use crate::{IpVersion, PingOptions};
+use crate::error::MultipingError;
+use crate::event::{EventKind, PingEvent};
+use crate::report::{PingInfo, PingReport, RunClock};
+use crate::resolve::resolve;
+use crate::tcp::connect;
+use crate::stats::{keep, packet_statistics, round_ms, round_trip_statistics};
+
+const MAX_STATUS_LINE: usize = 8192;
+// Longer answers to `get` are cut off.
+const MAX_RESPONSE: u64 = 64 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpMethod {
+    Get,
+    Head,
+}
+
+impl FromStr for HttpMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "GET" => Ok(HttpMethod::Get),
+            "HEAD" => Ok(HttpMethod::Head),
+            _ => Err(format!("unsupported HTTP method '{}' (expected GET or HEAD)", s)),
+        }
+    }
+}
+
+impl HttpMethod {
+    fn as_str(self) -> &'static str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Head => "HEAD",
+        }
+    }
+}
+
+struct Url {
+    tls: bool,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+// Targets may be plain host names (probed over http) or http(s) URLs.
+fn parse_url(target: &str, port: Option<u16>) -> io::Result<Url> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("{}: invalid URL", target));
+    let (tls, rest) = if let Some(rest) = target.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = target.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        (false, target)
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let (host, explicit_port) = if let Some(bracketed) = authority.strip_prefix('[') {
+        let (host, after) = bracketed.split_once(']').ok_or_else(invalid)?;
+        (host, after.strip_prefix(':'))
+    } else {
+        match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        }
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let explicit_port = explicit_port.map(|port| port.parse::<u16>().map_err(|_| invalid())).transpose()?;
+    let port = explicit_port.or(port).unwrap_or(if tls { 443 } else { 80 });
+    Ok(Url { tls, host: host.to_string(), port, path: path.to_string() })
+}
+
+/// Percent-encodes `value` for a query string.
+pub(crate) fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// The host name that `target` connects to.
+pub(crate) fn url_host(target: &str) -> io::Result<String> {
+    parse_url(target, None).map(|url| url.host)
+}
+
+pub(crate) fn tls_connector() -> TlsConnector {
+    let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+}
+
+// Sends the request and waits for the status line. Returns the status code
+// and the instant the first byte of the response arrived.
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, request: &[u8]) -> io::Result<(u16, Instant)> {
+    stream.write_all(request).await?;
+    let mut response = Vec::new();
+    let mut first_byte = None;
+    let mut buf = [0u8; 1024];
+    while !response.contains(&b'\n') && response.len() < MAX_STATUS_LINE {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        first_byte.get_or_insert_with(Instant::now);
+        response.extend_from_slice(&buf[..read]);
+    }
+    let status = String::from_utf8_lossy(&response)
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no HTTP status line in the response"))?;
+    Ok((status, first_byte.unwrap_or_else(Instant::now)))
+}
+
+async fn probe(url: &Url, address: SocketAddr, request: &[u8], connector: &TlsConnector, opts: &PingOptions) -> io::Result<(u16, Instant)> {
+    let stream = connect(address, opts).await?;
+    if url.tls {
+        let name = ServerName::try_from(url.host.clone()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        exchange(connector.connect(name, stream).await?, request).await
+    } else {
+        exchange(stream, request).await
+    }
+}
+
+/// POSTs `body` to `url` with the extra `headers` and returns the response
+/// status code.
+pub(crate) async fn post(url: &str, headers: &[(&str, &str)], body: &str, timeout: Duration) -> io::Result<u16> {
+    let url = parse_url(url, None)?;
+    let address = SocketAddr::new(resolve(&url.host, IpVersion::Any, timeout).await?, url.port);
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: multiping/{}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path, url.host, env!("CARGO_PKG_VERSION"), body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+    let connector = tls_connector();
+    // Alerts go out from wherever the system routes them.
+    let opts = PingOptions::default();
+    let exchange = probe(&url, address, request.as_bytes(), &connector, &opts);
+    let (status, _) = tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| MultipingError::Timeout(format!("{}: request timed out", url.host)))??;
+    Ok(status)
+}
+
+/// GETs `url` with the extra `headers`, over TLS with `connector` for https
+/// URLs, and returns the response status code and body. The request is
+/// HTTP/1.0, so that the body comes unchunked and ends with the connection.
+pub(crate) async fn get(url: &str, headers: &[(&str, &str)], connector: &TlsConnector, timeout: Duration) -> io::Result<(u16, Vec<u8>)> {
+    let url = parse_url(url, None)?;
+    let address = SocketAddr::new(resolve(&url.host, IpVersion::Any, timeout).await?, url.port);
+    let mut request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: multiping/{}\r\nAccept: application/json\r\n", url.path, url.host, env!("CARGO_PKG_VERSION"));
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    let exchange = async {
+        let stream = connect(address, &PingOptions::default()).await?;
+        if url.tls {
+            let name = ServerName::try_from(url.host.clone()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            fetch(connector.connect(name, stream).await?, request.as_bytes()).await
+        } else {
+            fetch(stream, request.as_bytes()).await
+        }
+    };
+    tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| MultipingError::Timeout(format!("{}: request timed out", url.host)))?
+}
+
+/// Sends `request` and reads the whole response, returning its status code
+/// and body.
+pub(crate) async fn fetch<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, request: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+    stream.write_all(request).await?;
+    let mut response = Vec::new();
+    match (&mut stream).take(MAX_RESPONSE).read_to_end(&mut response).await {
+        // Servers that close without a TLS close_notify have still said it all.
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof && !response.is_empty() => (),
+        read => { read?; },
+    }
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "no HTTP status line in the response");
+    let end = response.windows(4).position(|window| window == b"\r\n\r\n").ok_or_else(invalid)?;
+    let status = String::from_utf8_lossy(&response[..end])
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(invalid)?;
+    Ok((status, response.split_off(end + 4)))
+}
+
+/// Sends one HTTP request per probe to `url`, over a fresh connection each
+/// time, and records the time to the first byte of the response and its
+/// status code. Requests that fail or time out count as lost.
+pub(crate) async fn execute_ping(url: &str, target: String, address: IpAddr, opts: &PingOptions, clock: RunClock, sender: mpsc::Sender<PingEvent>) -> Result<PingReport, io::Error> {
+    let timeout = opts.reply_timeout();
+    let url = parse_url(url, opts.port)?;
+    let address = SocketAddr::new(address, url.port);
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: multiping/{}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        opts.http_method.as_str(), url.path, url.host, env!("CARGO_PKG_VERSION")
+    );
+    let connector = tls_connector();
+    let _ = sender.send(PingEvent::new(&target, EventKind::Started { address: address.ip() })).await;
+    let mut pings = Vec::new();
+    let (mut seq, mut received) = (0, 0);
+    let mut next_probe = Instant::now();
+    while opts.more_probes(seq) {
+        seq += 1;
+        tokio::time::sleep_until(next_probe).await;
+        next_probe += opts.next_interval();
+        let started = Instant::now();
+        match tokio::time::timeout(timeout, probe(&url, address, request.as_bytes(), &connector, opts)).await {
+            Ok(Ok((status, first_byte))) => {
+                let time = round_ms(first_byte.duration_since(started).as_secs_f32() * 1000.0);
+                let mut ping = PingInfo {
+                    icmp_seq: seq,
+                    time,
+                    status: Some(status),
+                    ..Default::default()
+                };
+                clock.stamp(&mut ping);
+                let _ = sender.send(PingEvent::new(&target, EventKind::Reply(ping.clone()))).await;
+                keep(&mut pings, ping, opts.unlimited());
+                received += 1;
+            },
+            Ok(Err(_)) | Err(_) => {
+                let _ = sender.send(PingEvent::new(&target, EventKind::Lost { icmp_seq: seq })).await;
+            },
+        }
+    }
+    let packets = packet_statistics(seq, received);
+    let trips = round_trip_statistics(&pings);
+    Ok(PingReport {
+        destination: target,
+        pings,
+        packets: Some(packets),
+        trips,
+        timed_out: false,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,114 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::http;
+use crate::report::{unix_nanos, PingReport};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    Influx,
+    Statsd,
+}
+
+impl FromStr for Sink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "influx" => Ok(Sink::Influx),
+            "statsd" => Ok(Sink::Statsd),
+            _ => Err(format!("unknown sink '{}' (expected influx or statsd)", s)),
+        }
+    }
+}
+
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+/// Renders the reports in InfluxDB line protocol: a `multiping_ping` point
+/// per reply and a `multiping_summary` point per target.
+pub fn line_protocol(reports: &[PingReport]) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let mut out = String::new();
+    for report in reports {
+        let mut target = escape_tag(&report.destination);
+        for (key, value) in &report.labels {
+            target.push_str(&format!(",{}={}", escape_tag(key), escape_tag(value)));
+        }
+        for ping in &report.pings {
+            let mut fields = format!("icmp_seq={}i,ttl={}i,bytes={}i,rtt_ms={}", ping.icmp_seq, ping.ttl, ping.bytes_sent, ping.time);
+            if let Some(status) = ping.status {
+                fields.push_str(&format!(",status={}i", status));
+            }
+            let timestamp = unix_nanos(&ping.timestamp).unwrap_or(now as i128);
+            out.push_str(&format!("multiping_ping,target={} {} {}\n", target, fields, timestamp));
+        }
+        let mut fields = Vec::new();
+        if let Some(packets) = &report.packets {
+            fields.push(format!("sent={}i", packets.transmitted));
+            fields.push(format!("received={}i", packets.received));
+            fields.push(format!("loss_percent={}", packets.loss_percent));
+        }
+        if let Some(trips) = &report.trips {
+            fields.push(format!("rtt_min_ms={}", trips.min));
+            fields.push(format!("rtt_avg_ms={}", trips.avg));
+            fields.push(format!("rtt_max_ms={}", trips.max));
+            fields.push(format!("rtt_stddev_ms={}", trips.stddev));
+        }
+        if let Some(samples) = &report.samples {
+            fields.push(format!("rtt_p95_ms={}", samples.p95));
+            fields.push(format!("jitter_ms={}", samples.jitter));
+        }
+        fields.push(format!("timed_out={}", report.timed_out));
+        out.push_str(&format!("multiping_summary,target={} {} {}\n", target, fields.join(","), now));
+    }
+    out
+}
+
+/// Sends the points to an InfluxDB write endpoint, such as
+/// `http://localhost:8086/api/v2/write?org=acme&bucket=pings`.
+pub async fn push(url: &str, token: Option<&str>, points: &str) -> io::Result<()> {
+    let authorization = token.map(|token| format!("Token {}", token));
+    let mut headers = vec![("Content-Type", "text/plain; charset=utf-8")];
+    if let Some(authorization) = &authorization {
+        headers.push(("Authorization", authorization));
+    }
+    let status = http::post(url, &headers, points, Duration::from_secs(10)).await?;
+    if !(200..300).contains(&status) {
+        return Err(io::Error::other(format!("InfluxDB answered with status {}", status)));
+    }
+    Ok(())
+}
+
+/// Appends the points to a file, for loading into InfluxDB later.
+pub fn append<P: AsRef<Path>>(path: P, points: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(points.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::PacketStatistics;
+
+    #[test]
+    fn tags_are_escaped() {
+        assert_eq!(escape_tag("a b,c=d\\e"), "a\\ b\\,c\\=d\\\\e");
+    }
+
+    #[test]
+    fn summaries_carry_the_labels() {
+        let report = PingReport {
+            destination: "web 1".to_string(),
+            labels: [("site".to_string(), "a=b".to_string())].into(),
+            packets: Some(PacketStatistics { transmitted: 4, received: 3, loss_percent: 25.0 }),
+            ..Default::default()
+        };
+        let points = line_protocol(&[report]);
+        assert!(points.starts_with("multiping_summary,target=web\\ 1,site=a\\=b sent=4i,received=3i,loss_percent=25,timed_out=false "), "{}", points);
+    }
+}
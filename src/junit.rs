@@ -0,0 +1,103 @@
+use std::io;
+use std::io::Write;
+use crate::html::escape;
+use crate::report::PingReport;
+use crate::thresholds::Thresholds;
+
+// Targets outside any group are tested in a suite of their own.
+const DEFAULT_SUITE: &str = "multiping";
+
+fn suite(report: &PingReport) -> &str {
+    report.group.as_deref().unwrap_or(DEFAULT_SUITE)
+}
+
+fn summary(report: &PingReport) -> String {
+    let Some(packets) = &report.packets else {
+        return "no packets were sent".to_string();
+    };
+    let mut summary = format!("{} sent, {} received, {}% loss", packets.transmitted, packets.received, packets.loss_percent);
+    if let Some(trips) = &report.trips {
+        summary.push_str(&format!(", rtt min/avg/max {}/{}/{} ms", trips.min, trips.avg, trips.max));
+    }
+    summary
+}
+
+fn write_case<W: Write>(report: &PingReport, thresholds: &Thresholds, writer: &mut W) -> io::Result<()> {
+    writeln!(writer, r#"    <testcase classname="{}" name="{}">"#, escape(suite(report)), escape(&report.destination))?;
+    if let Some(error) = &report.error {
+        writeln!(writer, r#"      <error type="{}" message="{}"/>"#, error.name(), escape(&error.to_string()))?;
+    } else {
+        let violations = thresholds.violations(report);
+        if !violations.is_empty() {
+            writeln!(writer, r#"      <failure type="threshold" message="{}"/>"#, escape(&violations.join("; ")))?;
+        }
+        writeln!(writer, "      <system-out>{}</system-out>", escape(&summary(report)))?;
+    }
+    writeln!(writer, "    </testcase>")
+}
+
+/// Writes a JUnit XML report with a test case per target, in a suite per
+/// group. Targets fail when they exceed `thresholds`, and those that could
+/// not be pinged are errors.
+pub fn write<W: Write>(results: &[PingReport], thresholds: &Thresholds, writer: &mut W) -> io::Result<()> {
+    let mut suites: Vec<&str> = Vec::new();
+    for report in results {
+        if !suites.contains(&suite(report)) {
+            suites.push(suite(report));
+        }
+    }
+    let count = |reports: &[&PingReport]| {
+        let errors = reports.iter().filter(|report| report.error.is_some()).count();
+        let failures = reports
+            .iter()
+            .filter(|report| report.error.is_none() && !thresholds.violations(report).is_empty())
+            .count();
+        format!(r#"tests="{}" failures="{}" errors="{}""#, reports.len(), failures, errors)
+    };
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<testsuites name="multiping" {}>"#, count(&results.iter().collect::<Vec<_>>()))?;
+    for name in suites {
+        let reports: Vec<&PingReport> = results.iter().filter(|report| suite(report) == name).collect();
+        writeln!(writer, r#"  <testsuite name="{}" {}>"#, escape(name), count(&reports))?;
+        for report in reports {
+            write_case(report, thresholds, writer)?;
+        }
+        writeln!(writer, "  </testsuite>")?;
+    }
+    writeln!(writer, "</testsuites>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::PacketStatistics;
+
+    fn render(reports: &[PingReport], thresholds: &Thresholds) -> String {
+        let mut out = Vec::new();
+        write(reports, thresholds, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn targets_over_the_limits_fail() {
+        let report = PingReport {
+            destination: "a<b>&\"c\"".to_string(),
+            group: Some("dc".to_string()),
+            packets: Some(PacketStatistics { transmitted: 4, received: 2, loss_percent: 50.0 }),
+            ..Default::default()
+        };
+        let xml = render(&[report], &Thresholds { max_loss: Some(10.0), max_avg: None });
+        assert!(xml.contains(r#"<testsuites name="multiping" tests="1" failures="1" errors="0">"#), "{}", xml);
+        assert!(xml.contains(r#"<testsuite name="dc" tests="1" failures="1" errors="0">"#), "{}", xml);
+        assert!(xml.contains(r#"<testcase classname="dc" name="a&lt;b&gt;&amp;&quot;c&quot;">"#), "{}", xml);
+        assert!(xml.contains(r#"<failure type="threshold""#), "{}", xml);
+        assert!(xml.contains("<system-out>4 sent, 2 received, 50% loss</system-out>"), "{}", xml);
+    }
+
+    #[test]
+    fn targets_outside_groups_share_a_suite() {
+        let xml = render(&[PingReport { destination: "a".to_string(), ..Default::default() }], &Thresholds::default());
+        assert!(xml.contains(r#"<testsuite name="multiping" tests="1" failures="0" errors="0">"#), "{}", xml);
+        assert!(xml.contains("<system-out>no packets were sent</system-out>"), "{}", xml);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use serde::Deserialize;
+use crate::collector::tls_connector;
+use crate::http;
+use crate::targets::Target;
+
+// Where Kubernetes puts the credentials of a pod's service account.
+const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What is pinged of the objects a selector picks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// The IP of every running pod.
+    Pods,
+    /// The cluster IP of every service that has one.
+    Services,
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pods" | "pod" => Ok(Kind::Pods),
+            "services" | "service" | "svc" => Ok(Kind::Services),
+            _ => Err(format!("unknown Kubernetes kind '{}' (expected pods or services)", s)),
+        }
+    }
+}
+
+/// A namespace and a label selector within it, written `NAMESPACE/SELECTOR`,
+/// e.g. `shop/app=web,tier!=cache`. A namespace alone picks all of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    pub namespace: String,
+    pub labels: String,
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (namespace, labels) = s.split_once('/').unwrap_or((s, ""));
+        if namespace.is_empty() {
+            return Err(format!("invalid Kubernetes selector '{}' (expected NAMESPACE/SELECTOR, e.g. default/app=web)", s));
+        }
+        Ok(Selector { namespace: namespace.to_string(), labels: labels.to_string() })
+    }
+}
+
+impl std::fmt::Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.namespace, self.labels)
+    }
+}
+
+/// The API server of a cluster, and what to show it.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    url: String,
+    token: Option<String>,
+    ca: Option<PathBuf>,
+}
+
+impl Cluster {
+    /// The API server at `url`, such as `http://127.0.0.1:8001` for
+    /// `kubectl proxy`, or, without one, the cluster the process runs in.
+    /// Either way, the service account of the pod, if there is one, signs
+    /// the requests in.
+    pub fn new(url: Option<&str>) -> io::Result<Cluster> {
+        let account = Path::new(SERVICE_ACCOUNT);
+        let token = std::fs::read_to_string(account.join("token")).ok().map(|token| token.trim().to_string());
+        let ca = Some(account.join("ca.crt")).filter(|ca| ca.exists());
+        let url = match url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => {
+                let host = std::env::var("KUBERNETES_SERVICE_HOST").map_err(|_| {
+                    io::Error::new(io::ErrorKind::NotFound, "not running in a Kubernetes cluster (give --k8s-api, e.g. http://127.0.0.1:8001 for kubectl proxy)")
+                })?;
+                let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+                match host.parse::<IpAddr>() {
+                    Ok(IpAddr::V6(_)) => format!("https://[{}]:{}", host, port),
+                    _ => format!("https://{}:{}", host, port),
+                }
+            },
+        };
+        Ok(Cluster { url, token, ca })
+    }
+
+    /// A target for every pod or service `selector` picks, named after it
+    /// and labeled with its labels and namespace.
+    pub async fn discover(&self, selector: &Selector, kind: Kind) -> io::Result<Vec<Target>> {
+        let resource = match kind {
+            Kind::Pods => "pods",
+            Kind::Services => "services",
+        };
+        let url = format!("{}/api/v1/namespaces/{}/{}?labelSelector={}", self.url, http::encode(&selector.namespace), resource, http::encode(&selector.labels));
+        let authorization = self.token.as_ref().map(|token| format!("Bearer {}", token));
+        let headers: Vec<(&str, &str)> = authorization.iter().map(|value| ("Authorization", value.as_str())).collect();
+        let (status, body) = http::get(&url, &headers, &tls_connector(self.ca.as_deref())?, TIMEOUT).await?;
+        if status != 200 {
+            // The API server says what went wrong in a Status object.
+            let message = serde_json::from_slice::<Failure>(&body).map_or_else(|_| String::from_utf8_lossy(&body).trim().to_string(), |failure| failure.message);
+            let kind = match status {
+                401 | 403 => io::ErrorKind::PermissionDenied,
+                404 => io::ErrorKind::NotFound,
+                _ => io::ErrorKind::Other,
+            };
+            return Err(io::Error::new(kind, format!("{}: HTTP {}: {}", selector, status, message)));
+        }
+        let list: List = serde_json::from_slice(&body).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", selector, err)))?;
+        Ok(list.items.into_iter().filter_map(|object| object.target(kind, &selector.namespace)).collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct Failure {
+    message: String,
+}
+
+// Just the parts of pods and services that make targets.
+#[derive(Deserialize)]
+struct List {
+    items: Vec<Object>,
+}
+
+#[derive(Deserialize)]
+struct Object {
+    metadata: Metadata,
+    #[serde(default)]
+    spec: Spec,
+    #[serde(default)]
+    status: Status,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    name: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Default, Deserialize)]
+struct Spec {
+    #[serde(rename = "clusterIP")]
+    cluster_ip: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct Status {
+    phase: Option<String>,
+    #[serde(rename = "podIP")]
+    pod_ip: Option<String>,
+}
+
+impl Object {
+    // Pods that are not running and headless services have nothing to ping.
+    fn target(self, kind: Kind, namespace: &str) -> Option<Target> {
+        let address = match kind {
+            Kind::Pods if self.status.phase.as_deref() == Some("Running") => self.status.pod_ip,
+            Kind::Pods => None,
+            Kind::Services => self.spec.cluster_ip,
+        };
+        let address: IpAddr = address?.parse().ok()?;
+        let mut labels = self.metadata.labels;
+        labels.insert("namespace".to_string(), namespace.to_string());
+        Some(Target { host: address.to_string(), name: Some(self.metadata.name), labels, address: Some(address), ..Default::default() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selectors() {
+        let selector: Selector = "shop/app=web,tier!=cache".parse().unwrap();
+        assert_eq!((selector.namespace.as_str(), selector.labels.as_str()), ("shop", "app=web,tier!=cache"));
+        assert_eq!(selector.to_string(), "shop/app=web,tier!=cache");
+        assert_eq!("shop".parse::<Selector>().unwrap().labels, "");
+        assert!("/app=web".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!("pods".parse(), Ok(Kind::Pods));
+        assert_eq!("svc".parse(), Ok(Kind::Services));
+        assert!("nodes".parse::<Kind>().is_err());
+    }
+
+    #[test]
+    fn running_pods_and_services_with_an_ip_are_targets() {
+        let object = |json: &str| serde_json::from_str::<Object>(json).unwrap();
+        let pod = object(r#"{"metadata": {"name": "web-1", "labels": {"app": "web"}}, "status": {"phase": "Running", "podIP": "10.1.0.5"}}"#);
+        let target = pod.target(Kind::Pods, "shop").unwrap();
+        assert_eq!((target.host.as_str(), target.name.as_deref()), ("10.1.0.5", Some("web-1")));
+        assert_eq!(target.labels.get("namespace").map(String::as_str), Some("shop"));
+        assert_eq!(target.labels.get("app").map(String::as_str), Some("web"));
+        let pending = object(r#"{"metadata": {"name": "web-2"}, "status": {"phase": "Pending"}}"#);
+        assert!(pending.target(Kind::Pods, "shop").is_none());
+        let headless = object(r#"{"metadata": {"name": "db"}, "spec": {"clusterIP": "None"}}"#);
+        assert!(headless.target(Kind::Services, "shop").is_none());
+    }
+}
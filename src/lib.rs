@@ -0,0 +1,755 @@
+//! Ping multiple sites concurrently and collect per-target statistics.
+
+use tokio::sync::{mpsc, Semaphore};
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::str::FromStr;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use serde::{de, Deserialize, Deserializer};
+
+pub mod alert;
+mod api;
+#[cfg(target_os = "linux")]
+mod arp;
+pub mod cli;
+pub mod collector;
+pub mod compare;
+pub mod config;
+#[cfg(unix)]
+pub mod control;
+pub mod db;
+pub mod dns;
+pub mod docker;
+pub mod email;
+mod error;
+mod event;
+mod fping;
+pub mod groups;
+pub mod hooks;
+pub mod html;
+pub mod http;
+pub mod influx;
+pub mod junit;
+pub mod kube;
+pub mod monitor;
+pub mod nagios;
+#[cfg(unix)]
+mod native;
+pub mod output;
+pub mod pagerduty;
+mod parser;
+pub mod progress;
+pub mod prometheus;
+mod report;
+mod resolve;
+mod rolling;
+pub mod rotate;
+pub mod run;
+pub mod silence;
+mod stats;
+pub mod statsd;
+pub mod stream;
+mod system;
+#[cfg(target_os = "linux")]
+pub mod systemd;
+pub mod targets;
+mod tcp;
+pub mod template;
+mod tls;
+mod udp;
+pub mod thresholds;
+pub mod trace;
+pub mod tui;
+
+pub use error::MultipingError;
+pub use event::{EventKind, PingEvent};
+pub use report::{Hop, PacketStatistics, PingError, PingErrorKind, PingInfo, PingReport, RoundTripStatistics, SampleStatistics, SequenceStatistics};
+pub use targets::Target;
+use report::RunClock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Native,
+    System,
+    /// One fping process for all targets, instead of a ping per target.
+    Fping,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" => Ok(Backend::Native),
+            "system" => Ok(Backend::System),
+            "fping" => Ok(Backend::Fping),
+            _ => Err(format!("unknown backend '{}' (expected native, system, or fping)", s)),
+        }
+    }
+}
+
+/// The ping implementation the system backend runs, which decides how its
+/// output is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PingFlavor {
+    /// Tell from the output.
+    Auto,
+    /// The ping of most Linux distributions.
+    Iputils,
+    Busybox,
+    /// macOS and the BSDs.
+    Bsd,
+    /// Solaris and illumos, run as `ping -s`.
+    Solaris,
+    Windows,
+}
+
+impl FromStr for PingFlavor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(PingFlavor::Auto),
+            "iputils" => Ok(PingFlavor::Iputils),
+            "busybox" => Ok(PingFlavor::Busybox),
+            "bsd" | "macos" => Ok(PingFlavor::Bsd),
+            "solaris" => Ok(PingFlavor::Solaris),
+            "windows" => Ok(PingFlavor::Windows),
+            _ => Err(format!("unknown ping flavor '{}' (expected auto, iputils, busybox, bsd, solaris, or windows)", s)),
+        }
+    }
+}
+
+/// What a single "ping" is: an ICMP echo, the time to open a connection,
+/// the answer to a UDP datagram, a TLS handshake, a DNS query, or an ARP
+/// request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    Icmp,
+    Tcp,
+    Http,
+    /// Usually answered with ICMP port unreachable, like traceroute's probes.
+    Udp,
+    /// Times the TCP connection and TLS handshake together.
+    Tls,
+    /// Times queries to a DNS resolver.
+    Dns,
+    /// Reaches hosts on the local network that do not answer ICMP.
+    Arp,
+}
+
+impl FromStr for Probe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "icmp" => Ok(Probe::Icmp),
+            "tcp" => Ok(Probe::Tcp),
+            "http" => Ok(Probe::Http),
+            "udp" => Ok(Probe::Udp),
+            "tls" => Ok(Probe::Tls),
+            "dns" => Ok(Probe::Dns),
+            "arp" => Ok(Probe::Arp),
+            _ => Err(format!("unknown probe '{}' (expected icmp, tcp, http, udp, tls, dns, or arp)", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Probe {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Probe {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Probe::Icmp | Probe::Arp => 0,
+            Probe::Tcp | Probe::Http => 80,
+            Probe::Udp => udp::DEFAULT_PORT,
+            Probe::Tls => 443,
+            Probe::Dns => 53,
+        }
+    }
+}
+
+/// A DiffServ code point, marking the traffic class probes are sent in.
+/// Written as a number up to 63 or as a class name, e.g. `ef`, `af41`, `cs1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dscp(pub u8);
+
+impl FromStr for Dscp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid DSCP '{}' (expected 0 to 63, or a class such as ef, af41, or cs1)", s);
+        let name = s.to_ascii_lowercase();
+        let value = if name == "ef" {
+            Some(46)
+        } else if name == "be" || name == "df" {
+            Some(0)
+        } else if let Some(class) = name.strip_prefix("cs") {
+            class.parse::<u8>().ok().filter(|class| *class <= 7).map(|class| class * 8)
+        } else if let Some(class) = name.strip_prefix("af") {
+            // Assured forwarding: a class from 1 to 4, then a drop precedence from 1 to 3.
+            match class.as_bytes() {
+                [class @ b'1'..=b'4', drop @ b'1'..=b'3'] => Some((class - b'0') * 8 + (drop - b'0') * 2),
+                _ => None,
+            }
+        } else {
+            s.parse::<u8>().ok().filter(|value| *value < 64)
+        };
+        value.map(Dscp).ok_or_else(invalid)
+    }
+}
+
+impl Dscp {
+    // The whole type of service byte, whose low two bits are for ECN.
+    pub(crate) fn tos(self) -> u8 {
+        self.0 << 2
+    }
+}
+
+/// A machine to run the system ping on over SSH, written as
+/// `ssh://[user@]host[:port]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshHost {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl FromStr for SshHost {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid SSH host '{}' (expected ssh://[user@]host[:port])", s);
+        let rest = s.strip_prefix("ssh://").ok_or_else(invalid)?.trim_end_matches('/');
+        let (user, rest) = match rest.rsplit_once('@') {
+            Some((user, rest)) => (Some(user.to_string()), rest),
+            None => (None, rest),
+        };
+        // IPv6 addresses are bracketed, as in URLs.
+        let (host, port) = match rest.strip_prefix('[') {
+            Some(bracketed) => match bracketed.split_once(']') {
+                Some((host, "")) => (host, None),
+                Some((host, port)) => (host, Some(port.strip_prefix(':').ok_or_else(invalid)?)),
+                None => return Err(invalid()),
+            },
+            None => match rest.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (rest, None),
+            },
+        };
+        let port = port.map(|port| port.parse::<u16>().map_err(|_| invalid())).transpose()?;
+        if host.is_empty() || user.as_deref() == Some("") {
+            return Err(invalid());
+        }
+        Ok(SshHost { user, host: host.to_string(), port })
+    }
+}
+
+impl std::fmt::Display for SshHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ssh://")?;
+        if let Some(user) = &self.user {
+            write!(f, "{}@", user)?;
+        }
+        match (self.host.contains(':'), self.port) {
+            (true, Some(port)) => write!(f, "[{}]:{}", self.host, port),
+            (false, Some(port)) => write!(f, "{}:{}", self.host, port),
+            (_, None) => write!(f, "{}", self.host),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpVersion {
+    Any,
+    V4,
+    V6,
+}
+
+#[derive(Debug, Clone)]
+pub struct PingOptions {
+    /// Probes per target; 0 keeps probing until `stop` is called.
+    pub count: u32,
+    /// Probes sent before the counted ones, whose replies, often slowed by
+    /// ARP and route cache misses, are kept but left out of the statistics.
+    pub warmup: u32,
+    /// Seconds to wait for each reply; a target is also given up on when it
+    /// has not finished `timeout` seconds after its last probe was due.
+    pub timeout: u32,
+    /// Seconds a target may go without a reply, loss, or error before it is
+    /// given up on as stalled, whatever is left of its run; `None` waits for
+    /// `timeout`, or forever in unlimited runs.
+    pub stall_timeout: Option<u32>,
+    /// Seconds between probes.
+    pub interval: f32,
+    /// Percent of `interval` each gap between probes is moved by at random,
+    /// so that targets do not probe in lockstep. The system backend ignores it.
+    pub jitter: f32,
+    /// Send each probe as soon as the last one is answered, like `ping -A`,
+    /// rather than waiting for the interval. Only the native backend does.
+    pub adaptive: bool,
+    /// Probes sent at once at the start, without waiting for replies, like
+    /// `ping -l`; adaptive runs keep that many out. Only the native backend
+    /// does.
+    pub preload: u32,
+    /// Milliseconds between the starts of consecutive targets.
+    pub stagger: u64,
+    /// ICMP payload bytes per probe, as in `ping -s`.
+    pub size: usize,
+    /// Look for the path MTU instead of pinging `count` times.
+    pub pmtu: bool,
+    pub backend: Backend,
+    /// How the output of the system ping is read.
+    pub ping_flavor: PingFlavor,
+    /// The ping program the system backend runs, instead of the one on the `PATH`.
+    pub ping_path: Option<PathBuf>,
+    /// Further arguments for the system ping, given before the target.
+    pub ping_args: Vec<String>,
+    pub probe: Probe,
+    /// Port for connection-based probes; `None` uses the probe's usual port.
+    pub port: Option<u16>,
+    pub http_method: http::HttpMethod,
+    /// The name DNS probes look up, and the record they ask for.
+    pub dns_query: String,
+    pub dns_record: dns::RecordType,
+    pub ip_version: IpVersion,
+    /// The address probes are sent from; `None` leaves it to the system.
+    pub source_ip: Option<IpAddr>,
+    /// The network interface probes are sent through, e.g. `eth0`.
+    pub interface: Option<String>,
+    /// The traffic class probes are marked with.
+    pub dscp: Option<Dscp>,
+    /// Time to live (hop limit for IPv6) of outgoing probes; `None` keeps the system's.
+    pub ttl: Option<u32>,
+    /// Upper bound on targets pinged at the same time; `None` pings all at once.
+    pub max_concurrent: Option<usize>,
+    /// Look up the hostnames of targets given as addresses.
+    pub rdns: bool,
+    /// Directory to save the output of the system ping to, a file per target.
+    pub record: Option<PathBuf>,
+    /// Directory of recorded ping output to parse instead of pinging; the
+    /// targets are not looked up either.
+    pub replay: Option<PathBuf>,
+    /// Run the system ping on this machine over SSH instead, which looks the
+    /// targets up itself. Only ICMP probes can be sent from there.
+    pub via: Option<SshHost>,
+}
+
+impl PingOptions {
+    pub(crate) fn reply_timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout as u64)
+    }
+
+    pub(crate) fn probe_interval(&self) -> Duration {
+        Duration::from_secs_f32(self.interval.max(0.0))
+    }
+
+    // The time until the probe after this one, with jitter applied.
+    pub(crate) fn next_interval(&self) -> Duration {
+        let spread = self.interval * self.jitter / 100.0;
+        Duration::from_secs_f32((self.interval + spread * random_unit()).max(0.0))
+    }
+
+    // What the probes were asked to be sent from, as reported: the source
+    // address if there is one, otherwise the interface.
+    pub(crate) fn source(&self) -> Option<String> {
+        self.source_ip.map(|address| address.to_string()).or_else(|| self.interface.clone())
+    }
+
+    // The probe, as reported: its kind, and the port for those sent to one.
+    // HTTP probes take theirs from the URL.
+    pub(crate) fn probe_name(&self) -> String {
+        let name = format!("{:?}", self.probe).to_lowercase();
+        match self.probe {
+            Probe::Tcp | Probe::Udp | Probe::Tls | Probe::Dns => format!("{}:{}", name, self.port.unwrap_or(self.probe.default_port())),
+            Probe::Icmp | Probe::Http | Probe::Arp => name,
+        }
+    }
+
+    // Adaptive runs send a probe once a reply is in, though for unprivileged
+    // users, like ping, no sooner than MIN_USER_INTERVAL after the last one.
+    pub(crate) fn adaptive_gap(&self) -> Duration {
+        if is_privileged() {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f32(MIN_USER_INTERVAL)
+        }
+    }
+
+    pub(crate) fn unlimited(&self) -> bool {
+        self.count == 0
+    }
+
+    // Whether another probe goes out after `sent` of them.
+    pub(crate) fn more_probes(&self, sent: u32) -> bool {
+        if self.unlimited() {
+            !stopped()
+        } else {
+            sent < self.count
+        }
+    }
+
+    // Like ping, only let privileged users send ICMP probes faster than
+    // MIN_USER_INTERVAL or preload more than MAX_USER_PRELOAD, so nobody
+    // floods a host by mistake.
+    fn check_interval(&self) -> io::Result<()> {
+        if self.interval.is_nan() || self.interval <= 0.0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid interval {} (must be positive)", self.interval)));
+        }
+        if !(0.0..=100.0).contains(&self.jitter) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid jitter {} (must be a percentage)", self.jitter)));
+        }
+        if self.probe == Probe::Icmp && self.interval < MIN_USER_INTERVAL && !is_privileged() {
+            return Err(MultipingError::PermissionDenied(format!("intervals below {}s need root privileges", MIN_USER_INTERVAL)).into());
+        }
+        if self.probe == Probe::Icmp && self.preload > MAX_USER_PRELOAD && !is_privileged() {
+            return Err(MultipingError::PermissionDenied(format!("preloading more than {} probes needs root privileges", MAX_USER_PRELOAD)).into());
+        }
+        Ok(())
+    }
+}
+
+const MIN_USER_INTERVAL: f32 = 0.2;
+const MAX_USER_PRELOAD: u32 = 3;
+
+// A random number in [-1, 1). Hashers are seeded at random, which is all
+// the randomness jitter needs.
+fn random_unit() -> f32 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Ends the runs with an unlimited count: their targets stop probing and
+/// report what they have so far.
+pub fn stop() {
+    STOPPED.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn stopped() -> bool {
+    STOPPED.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+fn is_privileged() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_privileged() -> bool {
+    true
+}
+
+impl Default for PingOptions {
+    fn default() -> Self {
+        PingOptions {
+            count: 10,
+            warmup: 0,
+            timeout: 10,
+            stall_timeout: None,
+            interval: 1.0,
+            jitter: 0.0,
+            adaptive: false,
+            preload: 0,
+            stagger: 0,
+            size: 56,
+            pmtu: false,
+            backend: Backend::Native,
+            ping_flavor: PingFlavor::Auto,
+            ping_path: None,
+            ping_args: Vec::new(),
+            probe: Probe::Icmp,
+            port: None,
+            http_method: http::HttpMethod::Get,
+            dns_query: "example.com".to_string(),
+            dns_record: dns::RecordType::A,
+            ip_version: IpVersion::Any,
+            source_ip: None,
+            interface: None,
+            dscp: None,
+            ttl: None,
+            max_concurrent: None,
+            rdns: false,
+            record: None,
+            replay: None,
+            via: None,
+        }
+    }
+}
+
+pub struct PingRunner;
+
+impl PingRunner {
+    /// Pings every target concurrently and returns one result per target,
+    /// in the same order as `targets`.
+    pub async fn run(targets: Vec<Target>, opts: PingOptions) -> Vec<io::Result<PingReport>> {
+        let (sender, mut receiver) = mpsc::channel(10);
+        let drain = tokio::spawn(async move {
+            while receiver.recv().await.is_some() {}
+        });
+        let results = PingRunner::run_with_sender(targets, opts, sender).await;
+        let _ = drain.await;
+        results
+    }
+
+    /// Like `run`, but sends every reply, lost probe, and finished target
+    /// to `sender` as it happens.
+    pub async fn run_with_sender(targets: Vec<Target>, opts: PingOptions, sender: mpsc::Sender<PingEvent>) -> Vec<io::Result<PingReport>> {
+        let resolved = resolve_targets(&targets, &opts).await;
+        let jobs = targets
+            .iter()
+            .zip(&resolved)
+            .map(|(target, resolved)| {
+                let opts = target.options(&opts);
+                let address = resolved.as_ref().ok()?.0;
+                uses_fping(&opts).then_some((address, opts))
+            })
+            .collect();
+        let sessions = fping::start(jobs);
+        let clock = RunClock::start();
+        let permits = Arc::new(Semaphore::new(opts.max_concurrent.filter(|&limit| limit > 0).unwrap_or(Semaphore::MAX_PERMITS)));
+        let mut tasks = Vec::new();
+        let started = Instant::now();
+        for (index, ((target, resolved), session)) in targets.into_iter().zip(resolved).zip(sessions).enumerate() {
+            let start = started + Duration::from_millis(opts.stagger.saturating_mul(index as u64));
+            let permits = permits.clone();
+            let opts = target.options(&opts);
+            let sender = sender.clone();
+            let task = tokio::spawn(async move {
+                let label = target.label();
+                let result = match resolved {
+                    Ok((address, resolve_time)) => {
+                        // The reverse lookup runs alongside the pings; a missing name is not an error.
+                        let hostname = (opts.rdns && target.host.parse::<IpAddr>().is_ok())
+                            .then(|| tokio::spawn(resolve::reverse(address, opts.reply_timeout())));
+                        tokio::time::sleep_until(start.into()).await;
+                        let _permit = permits.acquire_owned().await.map_err(io::Error::other)?;
+                        let (source, dscp, probe, warmup) = (opts.source(), opts.dscp, opts.probe_name(), opts.warmup);
+                        let result = match opts.stall_timeout {
+                            Some(stall) => {
+                                let (events, receiver) = mpsc::channel(10);
+                                let ping = execute_ping(&target, address, opts, clock, events, session);
+                                supervise(&label, Duration::from_secs(stall as u64), ping, receiver, &sender).await
+                            },
+                            None => execute_ping(&target, address, opts, clock, sender.clone(), session).await,
+                        };
+                        let hostname = match hostname {
+                            Some(lookup) => lookup.await.ok().and_then(Result::ok),
+                            None => None,
+                        };
+                        result.map(|mut report| {
+                            // Replayed names stand in for an address they were never looked up for.
+                            report.address = Some(address).filter(|address| !address.is_unspecified());
+                            report.group = target.group.clone();
+                            report.labels = target.labels.clone();
+                            report.hostname = hostname;
+                            report.probe = Some(probe);
+                            report.source = source;
+                            report.dscp = dscp.map(|dscp| dscp.0);
+                            report.resolve_time = resolve_time;
+                            let counted = stats::leave_out_warmup(&mut report, warmup);
+                            report.samples = stats::sample_statistics(&counted);
+                            report.sequence = stats::sequence_statistics(&counted);
+                            report.hops = stats::estimate_hops(&counted);
+                            report
+                        })
+                    },
+                    Err(err) => Err(err),
+                };
+                let kind = match &result {
+                    Ok(report) => {
+                        let (sent, received, loss) = report.packets.as_ref().map_or((0, 0, 100.0), |packets| (packets.transmitted, packets.received, packets.loss_percent));
+                        // Displayed, as f32s only convert to f64 with their rounding error showing.
+                        let avg = report.trips.as_ref().map_or("-".to_string(), |trips| trips.avg.to_string());
+                        tracing::info!(target = %label, sent, received, loss = %loss, avg = %avg, timed_out = report.timed_out, "target finished");
+                        EventKind::Finished(Box::new(report.clone()))
+                    },
+                    Err(err) => {
+                        tracing::warn!(target = %label, error = %err, "target failed");
+                        EventKind::Failed(MultipingError::from(err))
+                    },
+                };
+                let _ = sender.send(PingEvent::new(&label, kind)).await;
+                result
+            });
+            tasks.push(task);
+        }
+        drop(sender);
+        let mut results = Vec::new();
+        for task in tasks {
+            results.push(task.await.unwrap_or_else(|err| Err(err.into())));
+        }
+        results
+    }
+}
+
+// Passes the events of `ping` on to `sender`, and gives up on it once
+// `stall` goes by without one, as when a ping blocks without printing
+// anything. Dropping it kills the ping it runs.
+async fn supervise<F>(label: &str, stall: Duration, ping: F, mut events: mpsc::Receiver<PingEvent>, sender: &mpsc::Sender<PingEvent>) -> io::Result<PingReport>
+where
+    F: Future<Output = io::Result<PingReport>>,
+{
+    tokio::pin!(ping);
+    let result = loop {
+        tokio::select! {
+            result = &mut ping => break result,
+            event = tokio::time::timeout(stall, events.recv()) => match event {
+                Ok(Some(event)) => {
+                    let _ = sender.send(event).await;
+                },
+                Ok(None) => break (&mut ping).await,
+                Err(_) => {
+                    let message = format!("{}: stalled, nothing heard for {} s", label, stall.as_secs());
+                    return Err(MultipingError::Timeout(message).into());
+                },
+            },
+        }
+    };
+    while let Ok(event) = events.try_recv() {
+        let _ = sender.send(event).await;
+    }
+    result
+}
+
+// The name each target's probes connect to.
+pub(crate) fn probe_host(target: &str, opts: &PingOptions) -> io::Result<String> {
+    match opts.probe {
+        Probe::Http => http::url_host(target),
+        Probe::Icmp | Probe::Tcp | Probe::Udp | Probe::Tls | Probe::Dns | Probe::Arp => Ok(target.to_string()),
+    }
+}
+
+// Every name is looked up, concurrently, before pinging starts, so that
+// unresolvable targets fail right away. Returns each address with the
+// milliseconds its lookup took, if there was one. Replays need no network,
+// and the machine pings go through --via looks the names up there, so their
+// targets keep the address they are given as, if any.
+async fn resolve_targets(targets: &[Target], opts: &PingOptions) -> Vec<io::Result<(IpAddr, Option<f32>)>> {
+    let lookups: Vec<_> = targets
+        .iter()
+        .map(|target| {
+            let opts = target.options(opts);
+            let host = probe_host(&target.host, &opts);
+            let pinned = target.address;
+            let replayed = (opts.replay.is_some() || opts.via.is_some()).then(|| target.host.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)));
+            tokio::spawn(async move {
+                if let Some(address) = pinned.or(replayed) {
+                    return Ok((address, None));
+                }
+                let started = Instant::now();
+                let address = resolve::resolve(&host?, opts.ip_version, opts.reply_timeout()).await?;
+                Ok((address, Some(stats::round_ms(started.elapsed().as_secs_f32() * 1000.0))))
+            })
+        })
+        .collect();
+    let mut resolved = Vec::new();
+    for lookup in lookups {
+        resolved.push(lookup.await.unwrap_or_else(|err| Err(err.into())));
+    }
+    resolved
+}
+
+// fping only sends plain ICMP probes, and is not started for replays or for
+// targets that could not be pinged anyway.
+fn uses_fping(opts: &PingOptions) -> bool {
+    opts.backend == Backend::Fping && opts.probe == Probe::Icmp && !opts.pmtu && opts.replay.is_none() && opts.via.is_none() && opts.check_interval().is_ok()
+}
+
+async fn execute_ping(
+    target: &Target,
+    address: IpAddr,
+    opts: PingOptions,
+    clock: RunClock,
+    sender: mpsc::Sender<PingEvent>,
+    session: Option<fping::Session>,
+) -> Result<PingReport, io::Error> {
+    opts.check_interval()?;
+    let label = target.label();
+    // Recordings are of the system ping, whatever the backend.
+    if opts.replay.is_some() {
+        return system::execute_ping(label, &target.host, address, &opts, clock, sender).await;
+    }
+    if let Some(via) = &opts.via {
+        if opts.probe != Probe::Icmp || opts.pmtu {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("only ICMP probes can be sent from {}", via)));
+        }
+        return system::execute_ping(label, &target.host, address, &opts, clock, sender).await;
+    }
+    if opts.pmtu {
+        // Sizing probes needs control over the packets, which only the native prober has.
+        #[cfg(unix)]
+        if opts.probe == Probe::Icmp {
+            return native::discover_mtu(label, address, &opts, clock, sender).await;
+        }
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "path MTU discovery only works with native ICMP probes"));
+    }
+    match opts.probe {
+        Probe::Tcp => return tcp::execute_ping(label, address, &opts, clock, sender).await,
+        Probe::Udp => return udp::execute_ping(label, address, &opts, clock, sender).await,
+        Probe::Http => return http::execute_ping(&target.host, label, address, &opts, clock, sender).await,
+        Probe::Tls => return tls::execute_ping(&target.host, label, address, &opts, clock, sender).await,
+        Probe::Dns => return dns::execute_ping(label, address, &opts, clock, sender).await,
+        #[cfg(target_os = "linux")]
+        Probe::Arp => return arp::execute_ping(label, address, &opts, clock, sender).await,
+        #[cfg(not(target_os = "linux"))]
+        Probe::Arp => return Err(io::Error::new(io::ErrorKind::Unsupported, "ARP probes are only supported on Linux")),
+        Probe::Icmp => (),
+    }
+    if let Some(session) = session {
+        return fping::execute_ping(label, address, &opts, clock, sender, session).await;
+    }
+    #[cfg(unix)]
+    if opts.backend == Backend::Native {
+        match native::execute_ping(label.clone(), address, &opts, clock, sender.clone()).await {
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => (),
+            result => return result,
+        }
+    }
+    system::execute_ping(label, &target.host, address, &opts, clock, sender).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dscp_from_numbers_and_classes() {
+        let parsed: Vec<u8> = ["0", "63", "ef", "EF", "be", "df", "cs1", "cs7", "af11", "af41", "af43"].iter().map(|s| s.parse::<Dscp>().unwrap().0).collect();
+        assert_eq!(parsed, [0, 63, 46, 46, 0, 0, 8, 56, 10, 34, 38]);
+        assert_eq!("ef".parse::<Dscp>().unwrap().tos(), 184);
+        for invalid in ["64", "cs8", "af14", "af51", "af1", "-1", "fast"] {
+            assert!(invalid.parse::<Dscp>().is_err(), "{} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn ssh_hosts() {
+        let host: SshHost = "ssh://ops@probe1:2222".parse().unwrap();
+        assert_eq!(host, SshHost { user: Some("ops".to_string()), host: "probe1".to_string(), port: Some(2222) });
+        let host: SshHost = "ssh://[2001:db8::1]:22/".parse().unwrap();
+        assert_eq!((host.user, host.host.as_str(), host.port), (None, "2001:db8::1", Some(22)));
+        assert_eq!("ssh://[2001:db8::1]".parse::<SshHost>().unwrap().port, None);
+        for invalid in ["probe1", "ssh://", "ssh://@probe1", "ssh://probe1:ssh", "ssh://[2001:db8::1", "ssh://[2001:db8::1]22"] {
+            assert!(invalid.parse::<SshHost>().is_err(), "{} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn ssh_hosts_display_as_parsed() {
+        for written in ["ssh://probe1", "ssh://ops@probe1:2222", "ssh://[2001:db8::1]:22"] {
+            assert_eq!(written.parse::<SshHost>().unwrap().to_string(), written);
+        }
+    }
+}
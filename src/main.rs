@@ -1,215 +1,934 @@
-use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::sync::mpsc;
-use tokio::process::Command;
-use structopt::StructOpt;
+use clap::{CommandFactory, Parser};
 use std::io;
-use std::io::Write;
-use regex::Regex;
-
-#[derive(Debug, StructOpt)]
-struct CliArgs {
-    #[structopt(long, use_delimiter = true)]
-    targets: Vec<String>,
-    #[structopt(long, default_value = "10")]
-    timeout: u32,
-    #[structopt(short, long, default_value = "10")]
-    count: u32,
-}
-
-#[derive(Debug)]
-struct PingInfo {
-    bytes_sent: u32,
-    icmp_seq: u32,
-    ttl: u32,
-    time: f32,
-}
-
-#[derive(Debug)]
-struct PacketStatistics {
-    transmitted: u32,
-    received: u32,
-    loss_percent: f32,
-}
-
-#[derive(Debug)]
-struct RoundTripStatistics {
-    min: f32,
-    avg: f32,
-    max: f32,
-    stddev: f32,
-}
-
-#[derive(Debug)]
-struct PingReport {
-    destination: String,
-    pings: Vec<PingInfo>,
-    packets: Option<PacketStatistics>,
-    trips: Option<RoundTripStatistics>,
-}
-
-fn parse_ping_line(line: &str) -> Option<PingInfo> {
-    let re = Regex::new(r"^(?P<destination>\S+).*?(\d+) bytes from (?P<source>.*?): icmp_seq=(?P<icmp_seq>\d+) ttl=(?P<ttl>\d+) time=(?P<time>[\d.]+) ms$").unwrap();
-    if let Some(captures) = re.captures(line) {
-        let bytes_sent = captures[2].parse().ok()?;
-        let icmp_seq = captures["icmp_seq"].parse().ok()?;
-        let ttl = captures["ttl"].parse().ok()?;
-        let time = captures["time"].parse().ok()?;
-        Some(PingInfo {
-            bytes_sent,
-            icmp_seq,
-            ttl,
-            time,
-        })
+use std::fs::File;
+use std::io::{BufWriter, IsTerminal, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use multiping::{Backend, IpVersion, MultipingError, PingEvent, PingOptions, PingReport, PingRunner, Probe, Target};
+use multiping::cli::{AgentArgs, AlertArgs, Cli, CollectorArgs, Command, CompareArgs, MonitorArgs, PingArgs, ProbeArgs, ReportArgs, RotationArgs, RoundArgs, ServeArgs, TargetArgs, ThresholdArgs};
+#[cfg(unix)]
+use multiping::cli::{CtlArgs, CtlCommand, DaemonArgs};
+use multiping::alert::{Alert, Webhook};
+use multiping::collector::{self, Agent, Collector};
+use multiping::compare::{self, Metrics};
+use multiping::config::Config;
+#[cfg(unix)]
+use multiping::control::{self, Control};
+use multiping::db::Database;
+use multiping::hooks::Hooks;
+use multiping::monitor::{Flapping, Hysteresis, Monitor, PathTracker, Settings, StateTracker};
+use multiping::groups::{self, FleetSummary, GroupSummary};
+use multiping::output::{self, Colors, Envelope, Format, Matrix, MatrixRow};
+use multiping::progress::Tally;
+use multiping::{docker, html, influx, junit, kube, nagios, progress, prometheus, statsd, targets, template, trace, tui};
+#[cfg(target_os = "linux")]
+use multiping::systemd;
+use multiping::influx::Sink;
+use multiping::rotate::{Rotation, RotatingFile};
+use multiping::run::{self, RunInfo};
+use multiping::stream::{Event, Stream, StreamFormat};
+use multiping::thresholds::Thresholds;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
+
+// How often monitor and serve look for changes to the config file.
+const CONFIG_POLL: Duration = Duration::from_secs(2);
+
+fn ip_version(args: &ProbeArgs) -> IpVersion {
+    if args.ipv4 {
+        IpVersion::V4
+    } else if args.ipv6 {
+        IpVersion::V6
     } else {
-        None
-    }
-}
-
-fn parse_ping_statistics(line: &str) -> Option<PacketStatistics> {
-    let patterns = [
-        r"^.*?(\d+) packets transmitted, (\d+) packets received, ([0-9.]+)% packet loss$",
-        r"(\d+) packets transmitted, (\d+) received, (\d+)% packet loss, time (\d+)ms",
-    ];
-    for pattern in patterns {
-        if let Some(captures) = Regex::new(pattern).unwrap().captures(line) {
-            let transmitted = captures[1].parse().ok()?;
-            let received = captures[2].parse().ok()?;
-            let loss_percent = captures[3].parse().ok()?;
-            return Some(PacketStatistics {
-                transmitted,
-                received,
-                loss_percent,
-            })
-        }
-    }
-    None
-}
-
-fn parse_round_trip_statistics(line: &str) -> Option<RoundTripStatistics> {
-    let patterns = [
-        r"^.*?min/avg/max/stddev = ([0-9.]+)/([0-9.]+)/([0-9.]+)/([0-9.]+) ms$",
-        r"^.*?min/avg/max/mdev = ([0-9.]+)/([0-9.]+)/([0-9.]+)/([0-9.]+) ms$",
-    ];
-    for pattern in patterns {
-        if let Some(captures) = Regex::new(pattern).unwrap().captures(line) {
-            let min = captures[1].parse().ok()?;
-            let avg = captures[2].parse().ok()?;
-            let max = captures[3].parse().ok()?;
-            let stddev = captures[4].parse().ok()?;
-            return Some(RoundTripStatistics { min, avg, max, stddev })
-        }
-    }
-    None
-}
-
-async fn execute_ping(target: String, count: u32, timeout: u32, sender: mpsc::Sender<String>) -> Result<PingReport, io::Error> {
-    // let command = format!("ping -c {} {}", count, timeout, target);
-    let command = format!("ping -c {} {}", count, target);
-    // println!("{}", &command);
-    let mut child = Command::new("sh")
-        .arg("-c")
-        .arg(&command)
-        .stdout(std::process::Stdio::piped())
-        .spawn()?;
-    let mut pings = Vec::new();
-    let mut packets = None;
-    let mut trips = None;
-    if let Some(stdout) = child.stdout.take() {
-        let reader = BufReader::new(stdout);
-        let mut lines = reader.lines();
-        while let Some(line) = lines.next_line().await? {
-            let message = format!("{} {}", target, line);
-            let _ = sender.send(message).await;
-            if let Some(statistics) = parse_ping_line(&line) {
-                // println!("{:#?}", statistics);
-                pings.push(statistics);
+        match args.source_ip {
+            Some(IpAddr::V4(_)) => IpVersion::V4,
+            Some(IpAddr::V6(_)) => IpVersion::V6,
+            None => IpVersion::Any,
+        }
+    }
+}
+
+// Colors are left out when asked to, or when they would end up in a pipe or file.
+fn use_color(args: &PingArgs) -> bool {
+    !args.no_color && std::env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal()
+}
+
+// Group summaries follow the text table and wrap the JSON report, along with
+// the details of the run; CSV rows are per reply and leave them out.
+fn write_grouped<W: Write>(
+    format: Format,
+    envelope: &Envelope<'_>,
+    fleet: Option<&FleetSummary>,
+    matrix: Option<&Matrix>,
+    colors: Option<&Colors>,
+    writer: &mut W,
+) -> io::Result<()> {
+    let (results, groups) = (envelope.targets, envelope.groups);
+    match format {
+        Format::Text => {
+            match matrix {
+                Some(matrix) => output::write_matrix(matrix, colors, writer)?,
+                None => output::write_table(results, colors, writer)?,
             }
-            else if let Some(statistics) = parse_ping_statistics(&line) {
-                // println!("{:#?}", statistics);
-                packets = Some(statistics);
+            if !groups.is_empty() {
+                writeln!(writer)?;
+                output::write_groups(groups, colors, writer)?;
             }
-            else if let Some(statistics) = parse_round_trip_statistics(&line) {
-                // println!("{:#?}", statistics);
-                trips = Some(statistics);
+            if let Some(fleet) = fleet {
+                writeln!(writer)?;
+                output::write_fleet(fleet, writer)?;
             }
-        }
+            output::write_traces(results, writer)
+        },
+        Format::Json if !groups.is_empty() || envelope.run.is_some() => output::write_grouped_json(envelope, writer),
+        Format::Yaml if !groups.is_empty() || envelope.run.is_some() => output::write_yaml(envelope, writer),
+        Format::Parquet => output::write_parquet(results, envelope.run, writer),
+        format => output::write_results(format, results, writer),
+    }
+}
+
+// The text report of runs through several interfaces compares them. Every
+// target's copies follow each other, one per interface.
+fn interface_matrix(interfaces: &[String], targets: &[Target], reports: &[PingReport]) -> Matrix {
+    let report = |target: &Target| {
+        let label = target.label();
+        reports.iter().find(|report| report.destination == label).cloned().unwrap_or_default()
+    };
+    let rows = targets
+        .chunks(interfaces.len())
+        .map(|copies| MatrixRow {
+            target: Target { interface: None, ..copies[0].clone() }.label(),
+            reports: copies.iter().map(report).collect(),
+        })
+        .collect();
+    Matrix { interfaces: interfaces.to_vec(), rows }
+}
+
+// JSON reports, plugin output and test reports also cover the targets that
+// failed, with their errors.
+fn write_report(args: &PingArgs, targets: &[Target], results: &[PingReport], failed: &[PingReport], groups: &[GroupSummary], fleet: Option<&FleetSummary>) -> io::Result<()> {
+    let run = args.run.as_deref().map(RunInfo::finish);
+    if let Some(template) = &args.template {
+        let all = [results, failed].concat();
+        return match &args.output {
+            Some(path) => {
+                let mut file = BufWriter::new(File::create(path)?);
+                template::render(template, &all, groups, run.as_ref(), &mut file)?;
+                file.flush()
+            },
+            None => template::render(template, &all, groups, run.as_ref(), &mut io::stdout()),
+        };
+    }
+    let format = args.format.unwrap_or(Format::Text);
+    let all;
+    let results = if matches!(format, Format::Json | Format::Yaml | Format::Nagios | Format::Junit) && !failed.is_empty() {
+        all = [results, failed].concat();
+        &all
+    } else {
+        results
+    };
+    if format == Format::Nagios {
+        let (_, line) = nagios::check(results, args.warning, args.critical);
+        return match &args.output {
+            Some(path) => std::fs::write(path, format!("{}\n", line)),
+            None => writeln!(io::stdout(), "{}", line),
+        };
+    }
+    // Test cases fail on the thresholds the run is checked against.
+    if format == Format::Junit {
+        let thresholds = thresholds(&args.thresholds);
+        return match &args.output {
+            Some(path) => {
+                let mut file = BufWriter::new(File::create(path)?);
+                junit::write(results, &thresholds, &mut file)?;
+                file.flush()
+            },
+            None => junit::write(results, &thresholds, &mut io::stdout()),
+        };
+    }
+    let interfaces = &args.probe.interfaces;
+    let matrix = (format == Format::Text && !interfaces.is_empty()).then(|| interface_matrix(interfaces, targets, &[results, failed].concat()));
+    let envelope = Envelope { run: run.as_ref(), targets: results, groups };
+    match &args.output {
+        Some(path) => {
+            let mut file = BufWriter::new(File::create(path)?);
+            write_grouped(format, &envelope, fleet, matrix.as_ref(), None, &mut file)?;
+            file.flush()
+        },
+        None if format == Format::Text && use_color(args) => {
+            let colors = Colors { loss: args.loss_colors, rtt: args.rtt_colors };
+            write_grouped(format, &envelope, fleet, matrix.as_ref(), Some(&colors), &mut io::stdout())
+        },
+        None => write_grouped(format, &envelope, fleet, matrix.as_ref(), None, &mut io::stdout()),
     }
-    let status = child.wait().await?;
-    if !status.success() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("{} failed with exit code: {}", target, status),
-        ));
+}
+
+fn thresholds(args: &ThresholdArgs) -> Thresholds {
+    Thresholds {
+        max_loss: args.fail_on_loss,
+        max_avg: args.fail_on_avg,
     }
-    Ok(PingReport {
-        destination: target,
-        pings,
-        packets,
-        trips,
+}
+
+fn webhook(args: &AlertArgs) -> Option<Webhook> {
+    Some(Webhook { url: args.webhook.clone()?, format: args.webhook_format })
+}
+
+fn monitor(args: &RoundArgs, thresholds: Thresholds, opts: &PingOptions, config: Option<Config>) -> io::Result<Monitor> {
+    let (email, pagerduty, silences) = config.map(|config| (config.email, config.pagerduty, config.silences)).unwrap_or_default();
+    let hysteresis = Hysteresis { down_after: args.down_after, up_after: args.up_after };
+    let flapping = args.flap_threshold.map(|changes| Flapping { changes, window: Duration::from_secs(args.flap_window * 60) });
+    Ok(Monitor {
+        tracker: StateTracker::new(hysteresis, flapping, thresholds),
+        webhook: webhook(&args.alerts),
+        email,
+        pagerduty,
+        silences,
+        hooks: Some(Hooks { on_down: args.on_down.clone(), on_up: args.on_up.clone() }),
+        db: args.db.as_ref().map(Database::open).transpose()?,
+        paths: args.trace_every.map(|every| PathTracker::new(every, opts.timeout)),
+        stream: None,
+        events: None,
+        reload: None,
+        #[cfg(unix)]
+        control: None,
     })
 }
 
-fn print_results(results: Vec<PingReport>) {
-    for item in results {
-        println!("{}:", item.destination);
-        match item.packets {
-            Some(packets) => {
-                println!("  Sent: {} Received: {} Loss: {}%", packets.transmitted, packets.received, packets.loss_percent);  
-            },
-            None => (),
+async fn send_to_sink(args: &PingArgs, reports: &[PingReport]) -> io::Result<()> {
+    match args.sink {
+        Some(Sink::Influx) => {
+            let points = influx::line_protocol(reports);
+            match (&args.influx_url, &args.influx_file) {
+                (Some(url), _) => influx::push(url, args.influx_token.as_deref(), &points).await,
+                (None, Some(path)) => influx::append(path, &points),
+                (None, None) => Err(io::Error::new(io::ErrorKind::InvalidInput, "--sink influx needs --influx-url or --influx-file")),
+            }
+        },
+        Some(Sink::Statsd) => statsd::send(&args.statsd_addr, &statsd::metrics(reports, args.dogstatsd)).await,
+        None => Ok(()),
+    }
+}
+
+// Traces the targets that lost more than `max_loss` percent of their
+// packets, all at once, and attaches the hops to their reports.
+async fn trace_lossy(reports: &mut [PingReport], max_loss: f32, timeout: u32, quiet: bool) {
+    let mut traces = Vec::new();
+    for (index, report) in reports.iter().enumerate() {
+        let lossy = report.packets.as_ref().is_some_and(|packets| packets.loss_percent > max_loss);
+        if let (true, Some(address)) = (lossy, report.address) {
+            if !quiet {
+                eprintln!("Tracing the path to {}...", report.destination);
+            }
+            traces.push((index, tokio::spawn(trace::trace(address, timeout))));
         }
-        match item.trips {
-            Some(trips) => {
-                println!("  Min: {} Avg: {} Max: {} Std: {}", trips.min, trips.avg, trips.max, trips.stddev);
-            },
-            None => (),
+    }
+    for (index, trace) in traces {
+        match trace.await.unwrap_or_else(|err| Err(err.into())) {
+            Ok(hops) => reports[index].trace = Some(hops),
+            Err(err) => eprintln!("Cannot trace {}: {}", reports[index].destination, err),
         }
-        println!("");
     }
 }
 
-async fn launch_workers(args: CliArgs) -> io::Result<()> {
-    let mut tasks = Vec::new();
-    let (sender, mut receiver) = mpsc::channel::<String>(10);
-    for target in args.targets.clone() {
-        let sender_clone = sender.clone();
-        let task = tokio::spawn(execute_ping(target.clone(), args.count, args.timeout, sender_clone));
-        tasks.push(task);
+// Reports the results and tells whether every target stayed within the thresholds.
+async fn finish(args: &PingArgs, targets: &[Target], results: Vec<io::Result<PingReport>>) -> io::Result<ExitCode> {
+    let thresholds = thresholds(&args.thresholds);
+    let groups = groups::summarize(targets, &results);
+    let fleet = (args.top.is_some() || args.bottom.is_some()).then(|| groups::fleet(&results));
+    if let Some(path) = &args.db {
+        Database::open(path)?.record(targets, &results)?;
     }
-    let hubmsg = tokio::spawn(async move {
-        let total: usize = ((args.count + 3) as usize * args.targets.len());
-        let mut counter = 0;
-        while let Some(message) = receiver.recv().await {
-            let percentage = counter as f32 / total as f32 * 100.0;
-            print!("\r{:.1}%", percentage);
-            io::stdout().flush().unwrap();
-            if counter == total {
-                break;
+    if let Some(webhook) = webhook(&args.alerts) {
+        for (target, result) in targets.iter().zip(&results) {
+            let Some(alert) = Alert::check(&target.label(), result, &thresholds) else {
+                continue;
+            };
+            if let Err(err) = webhook.send(&alert).await {
+                eprintln!("Cannot send alert for {}: {}", alert.target, err);
             }
-            counter += 1;
-        }    
-    });
-    let mut results = Vec::new();
-    for task in tasks {
-        let res = task.await?;
+        }
+    }
+    let mut healthy = true;
+    // The exit status of the first target that could not be pinged.
+    let mut failure = None;
+    let mut reports = Vec::new();
+    let mut failed = Vec::new();
+    for (target, res) in targets.iter().zip(results) {
         match res {
             Ok(values) => {
-                results.push(values);
+                reports.push(values);
             },
             Err(err) => {
-                println!("Error {:?}", err);
+                let error = MultipingError::from(&err);
+                eprintln!("Error: {}", error);
+                failure = failure.or(Some(error.exit_code()));
+                failed.push(PingReport { destination: target.label(), error: Some(error), ..Default::default() });
             },
         }
     }
-    hubmsg.abort();
-    println!("\n");
-    print_results(results);
+    if let Some(max_loss) = args.trace_on_loss {
+        trace_lossy(&mut reports, max_loss, ping_options(&args.probe).timeout, args.quiet).await;
+    }
+    if let Some(key) = args.sort_by {
+        output::sort_reports(&mut reports, key);
+    }
+    // Streamed runs already wrote their summaries.
+    if args.stream.is_none() {
+        match args.top.or(args.bottom) {
+            Some(count) => write_report(args, targets, &output::rank(&reports, count, args.bottom.is_some()), &failed, &groups, fleet.as_ref())?,
+            None => write_report(args, targets, &reports, &failed, &groups, None)?,
+        }
+    }
+    if let Some(path) = &args.report_html {
+        let mut file = BufWriter::new(File::create(path)?);
+        html::write_html(&reports, &mut file)?;
+        file.flush()?;
+    }
+    send_to_sink(args, &reports).await?;
+    for report in &reports {
+        for violation in thresholds.violations(report) {
+            eprintln!("{}: {}", report.destination, violation);
+            healthy = false;
+        }
+    }
+    if let Some(path) = &args.baseline {
+        let current: Vec<Metrics> = reports.iter().map(Metrics::from).collect();
+        for change in compare::compare(&compare::read_metrics(path)?, &current, args.max_regression) {
+            if change.regression {
+                eprintln!("{}: regression: {}", change.target, change.describe());
+                healthy = false;
+            }
+        }
+    }
+    // Nagios takes the state from the exit status.
+    if args.format == Some(Format::Nagios) {
+        let (state, _) = nagios::check(&[&reports[..], &failed[..]].concat(), args.warning, args.critical);
+        return Ok(ExitCode::from(state.code()));
+    }
+    Ok(match failure {
+        Some(code) => ExitCode::from(code),
+        None if healthy => ExitCode::SUCCESS,
+        None => ExitCode::FAILURE,
+    })
+}
+
+fn ping_options(args: &ProbeArgs) -> PingOptions {
+    let defaults = PingOptions::default();
+    PingOptions {
+        count: args.count.unwrap_or(defaults.count),
+        warmup: args.warmup,
+        timeout: args.timeout.unwrap_or(defaults.timeout),
+        stall_timeout: args.stall_timeout,
+        interval: args.interval.unwrap_or(defaults.interval),
+        jitter: args.jitter,
+        adaptive: args.adaptive,
+        preload: args.preload,
+        stagger: args.stagger,
+        size: args.size.unwrap_or(defaults.size),
+        backend: args.backend,
+        ping_flavor: args.ping_flavor,
+        ping_path: args.ping_path.clone(),
+        ping_args: args.ping_args.as_deref().map_or(Vec::new(), |extra| extra.split_whitespace().map(String::from).collect()),
+        probe: args.probe,
+        port: args.port,
+        http_method: args.http_method,
+        dns_query: args.dns_query.clone(),
+        dns_record: args.dns_record,
+        ip_version: ip_version(args),
+        source_ip: args.source_ip,
+        interface: args.interface.clone(),
+        dscp: args.qos,
+        ttl: args.ttl,
+        max_concurrent: args.max_concurrent,
+        rdns: args.rdns,
+        via: args.via.clone(),
+        ..defaults
+    }
+}
+
+async fn launch_dashboard(args: PingArgs, opts: PingOptions, targets: Vec<Target>) -> io::Result<ExitCode> {
+    let (sender, receiver) = mpsc::channel(10);
+    let runner = tokio::spawn(PingRunner::run_with_sender(targets.clone(), opts.clone(), sender));
+    if !tui::run_dashboard(&targets, &opts, receiver).await? {
+        // Probes running on blocking threads would keep the runtime alive.
+        std::process::exit(0);
+    }
+    finish(&args, &targets, runner.await?).await
+}
+
+async fn launch_stream(args: PingArgs, opts: PingOptions, targets: Vec<Target>, format: StreamFormat) -> io::Result<ExitCode> {
+    let mut stream = open_stream(format, args.output.as_deref(), &args.rotation)?;
+    if let Some(run) = &args.run {
+        stream.write_other(&Event::Run { run })?;
+    }
+    let (sender, receiver) = mpsc::channel(10);
+    let mut receiver = interim_statistics(&targets, receiver);
+    let printer = tokio::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            stream.write_event(&event)?;
+        }
+        io::Result::Ok(())
+    });
+    let results = PingRunner::run_with_sender(targets.clone(), opts, sender).await;
+    printer.await??;
+    finish(&args, &targets, results).await
+}
+
+// The stream to --output, rotated as asked, or else to standard output.
+fn open_stream(format: StreamFormat, output: Option<&Path>, args: &RotationArgs) -> io::Result<Stream> {
+    let Some(path) = output else {
+        return Ok(Stream::stdout(format));
+    };
+    let rotation = Rotation { max_size: args.rotate_size, max_age: args.rotate_every, keep: args.rotate_keep };
+    let file = RotatingFile::open(path, args.append, rotation, format.header())?;
+    Ok(Stream::file(format, file))
+}
+
+// Passes the events on, and prints the statistics so far on SIGUSR1, or on
+// Enter when standard input is a terminal, as ping does.
+fn interim_statistics(targets: &[Target], mut receiver: mpsc::Receiver<PingEvent>) -> mpsc::Receiver<PingEvent> {
+    let (sender, forwarded) = mpsc::channel(10);
+    let (asked, mut asks) = mpsc::channel(1);
+    // A thread, unlike a blocking task, does not hold off the exit while it
+    // waits for a line.
+    if io::stdin().is_terminal() {
+        std::thread::spawn(move || {
+            for _ in io::stdin().lines() {
+                if asked.blocking_send(()).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    let mut tally = Tally::new(targets);
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        let mut signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1()).ok();
+        #[cfg(not(unix))]
+        let mut signal = None;
+        loop {
+            tokio::select! {
+                event = receiver.recv() => {
+                    let Some(event) = event else {
+                        return;
+                    };
+                    tally.record(&event);
+                    if sender.send(event).await.is_err() {
+                        return;
+                    }
+                },
+                Some(()) = asks.recv() => tally.print(),
+                _ = signalled(&mut signal) => tally.print(),
+            }
+        }
+    });
+    forwarded
+}
+
+// Unlimited runs end on Ctrl-C with their report; a second one quits at once.
+fn stop_on_interrupt() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            multiping::stop();
+        }
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+}
+
+async fn launch_workers(args: PingArgs, targets: Vec<Target>) -> io::Result<ExitCode> {
+    // Only the system ping has output to record.
+    if args.record.is_some() && (args.probe.backend != Backend::System || args.probe.probe != Probe::Icmp || args.pmtu) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--record only works with --backend system and ICMP probes"));
+    }
+    let opts = PingOptions { pmtu: args.pmtu, record: args.record.clone(), replay: args.replay.clone(), ..ping_options(&args.probe) };
+    if targets.iter().any(|target| target.options(&opts).count == 0) {
+        stop_on_interrupt();
+    }
+    if args.tui {
+        return launch_dashboard(args, opts, targets).await;
+    }
+    if let Some(format) = args.stream {
+        return launch_stream(args, opts, targets, format).await;
+    }
+    let (sender, receiver) = mpsc::channel(10);
+    let mut receiver = interim_statistics(&targets, receiver);
+    let display = {
+        let (targets, opts) = (targets.clone(), opts.clone());
+        let (quiet, verbose, progressive) = (args.quiet, args.verbose, args.progressive);
+        tokio::spawn(async move {
+            match verbose {
+                _ if quiet => while receiver.recv().await.is_some() {},
+                _ if progressive => progress::show_finished(receiver).await,
+                0 => progress::show_progress(&targets, &opts, receiver).await,
+                level => progress::show_lines(&targets, &opts, receiver, level > 1).await,
+            }
+        })
+    };
+    let results = PingRunner::run_with_sender(targets.clone(), opts, sender).await;
+    display.await?;
+    finish(&args, &targets, results).await
+}
+
+// Puts the targets on standard input in place of the - among --targets.
+// This happens once, at the start: standard input is used up after that, so
+// reloads can only go back to the files.
+fn read_stdin_targets(args: &mut TargetArgs) -> io::Result<()> {
+    if !args.targets.iter().any(|target| target.host == "-") {
+        return Ok(());
+    }
+    let read = targets::read_targets(io::stdin().lock())?;
+    let mut given = Vec::new();
+    for target in std::mem::take(&mut args.targets) {
+        if target.host == "-" {
+            given.extend(read.iter().cloned());
+        } else {
+            given.push(target);
+        }
+    }
+    args.targets = given;
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> io::Result<()> {
-    let args = CliArgs::from_args();
-    launch_workers(args).await?;
+async fn collect_targets(args: &TargetArgs, probe: &ProbeArgs) -> io::Result<Vec<Target>> {
+    let mut collected = args.targets.clone();
+    if let Some(path) = &args.targets_file {
+        collected.extend(targets::read_targets_file(path)?);
+    }
+    collected.extend(args.config_targets.iter().cloned());
+    if !args.from_k8s.is_empty() {
+        let cluster = kube::Cluster::new(args.k8s_api.as_deref())?;
+        for selector in &args.from_k8s {
+            collected.extend(cluster.discover(selector, args.k8s_kind).await?);
+        }
+    }
+    if args.from_docker {
+        collected.extend(docker::discover(args.docker_host.as_deref(), &args.docker_label).await?);
+    }
+    let collected = targets::expand_targets(collected, args.max_hosts)?;
+    let (collected, invalid) = targets::validate_targets(collected, &ping_options(probe));
+    if !invalid.is_empty() && !args.skip_invalid {
+        let invalid: Vec<String> = invalid.iter().map(|(target, reason)| format!("{} ({})", target.host, reason)).collect();
+        let message = format!("invalid targets: {} (--skip-invalid pings the others)", invalid.join(", "));
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+    for (target, reason) in &invalid {
+        eprintln!("Skipping {}: {}", target.host, reason);
+    }
+    let (mut collected, repeated) = targets::dedupe_targets(collected);
+    for target in &repeated {
+        eprintln!("{}: given more than once, pinged once", target.label());
+    }
+    if collected.is_empty() && !invalid.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "none of the targets is valid"));
+    }
+    if collected.is_empty() && !args.from_k8s.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "nothing in the cluster matches --from-k8s"));
+    }
+    if collected.is_empty() && args.from_docker {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no running containers with an address to ping"));
+    }
+    if collected.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no targets given (use --targets, --targets-file, --config, --from-k8s, or --from-docker)"));
+    }
+    // fping gives up on a probe once the next one is due, so it cannot wait
+    // any longer than that; only the default timeout is cut to fit.
+    if probe.backend == Backend::Fping && probe.probe == Probe::Icmp {
+        let opts = ping_options(probe);
+        let waits_longer = |target: &&Target| {
+            let options = target.options(&opts);
+            (probe.timeout.is_some() || target.timeout.is_some()) && options.timeout as f32 > options.interval
+        };
+        if let Some(target) = collected.iter().find(waits_longer) {
+            let options = target.options(&opts);
+            let message = format!("{}: a timeout of {}s is longer than the {}s interval, past which fping does not wait", target.label(), options.timeout, options.interval);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+    }
+    if args.all_ips {
+        collected = targets::expand_addresses(collected, &ping_options(probe)).await;
+    }
+    if !probe.interfaces.is_empty() {
+        collected = targets::per_interface(collected, &probe.interfaces);
+    }
+    Ok(collected)
+}
+
+// Fills in the settings the command line left unspecified from the config
+// file, and returns the file for the settings only some commands have.
+fn load_config(targets: &mut TargetArgs, probe: &mut ProbeArgs, thresholds: &mut ThresholdArgs) -> io::Result<Option<Config>> {
+    let Some(path) = &targets.config else {
+        return Ok(None);
+    };
+    let mut config = Config::load(path)?;
+    probe.count = probe.count.or(config.count);
+    probe.timeout = probe.timeout.or(config.timeout);
+    probe.interval = probe.interval.or(config.interval);
+    probe.size = probe.size.or(config.size);
+    thresholds.fail_on_loss = thresholds.fail_on_loss.or(config.thresholds.max_loss);
+    thresholds.fail_on_avg = thresholds.fail_on_avg.or(config.thresholds.max_avg);
+    targets.config_targets = std::mem::take(&mut config.targets);
+    targets.config_targets.extend(std::mem::take(&mut config.groups).into_values().flatten());
+    Ok(Some(config))
+}
+
+async fn ping(mut args: PingArgs) -> io::Result<ExitCode> {
+    read_stdin_targets(&mut args.targets)?;
+    if let Some(config) = load_config(&mut args.targets, &mut args.probe, &mut args.thresholds)? {
+        args.format = args.format.or(config.format);
+        args.output = args.output.take().or(config.output);
+    }
+    if args.template.is_none() && args.output.as_ref().is_some_and(|path| path.extension().is_some_and(|extension| extension == "parquet")) {
+        args.format = args.format.or(Some(Format::Parquet));
+    }
+    if args.format == Some(Format::Parquet) && args.output.is_none() && io::stdout().is_terminal() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the parquet format is binary; give --output, or redirect standard output"));
+    }
+    if args.metadata {
+        args.run = Some(Box::new(RunInfo::start(args.probe.backend)));
+    }
+    let targets = collect_targets(&args.targets, &args.probe).await?;
+    launch_workers(args, targets).await
+}
+
+// Rounds have to end, so they need a count.
+fn round_options(args: &ProbeArgs, targets: &[Target]) -> io::Result<PingOptions> {
+    let opts = ping_options(args);
+    if targets.iter().any(|target| target.options(&opts).count == 0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "a count of 0 only works with ping, not in rounds"));
+    }
+    Ok(opts)
+}
+
+// The settings the config file makes, over the command line as given.
+async fn reload_settings(mut targets: TargetArgs, mut probe: ProbeArgs, mut thresholds: ThresholdArgs) -> io::Result<Settings> {
+    let config = load_config(&mut targets, &mut probe, &mut thresholds)?.unwrap_or_default();
+    let collected = collect_targets(&targets, &probe).await?;
+    let opts = round_options(&probe, &collected)?;
+    Ok(Settings {
+        targets: collected,
+        opts,
+        thresholds: self::thresholds(&thresholds),
+        email: config.email,
+        pagerduty: config.pagerduty,
+        silences: config.silences,
+    })
+}
+
+// Waits for the signal, or forever where there is none.
+#[cfg(unix)]
+async fn signalled(signal: &mut Option<tokio::signal::unix::Signal>) {
+    match signal {
+        Some(signal) => {
+            signal.recv().await;
+        },
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+async fn signalled(_: &mut Option<()>) {
+    std::future::pending().await
+}
+
+// Waits for the next tick, or forever without a ticker.
+async fn ticked(ticker: &mut Option<tokio::time::Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        },
+        None => std::future::pending().await,
+    }
+}
+
+// Reloads the config file, if there is one, when it changes or on SIGHUP,
+// and sends the settings it makes now. A file that no longer loads keeps
+// the settings as they were. The --from-k8s targets are looked up again
+// every --k8s-refresh seconds, and the settings sent when they changed.
+fn watch_config(targets: &TargetArgs, probe: &ProbeArgs, thresholds: &ThresholdArgs) -> Option<mpsc::Receiver<Settings>> {
+    let path = targets.config.clone();
+    let refresh = (!targets.from_k8s.is_empty()).then(|| Duration::from_secs(targets.k8s_refresh));
+    if path.is_none() && refresh.is_none() {
+        return None;
+    }
+    let (targets, probe, thresholds) = (targets.clone(), probe.clone(), thresholds.clone());
+    let (sender, receiver) = mpsc::channel(1);
+    let modified = |path: &Option<PathBuf>| path.as_ref().and_then(|path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok());
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        let mut signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+        #[cfg(not(unix))]
+        let mut signal = None;
+        let mut last = modified(&path);
+        let mut poll = tokio::time::interval(CONFIG_POLL);
+        let mut discovery = refresh.map(|refresh| tokio::time::interval_at(tokio::time::Instant::now() + refresh, refresh));
+        let mut current: Option<Vec<Target>> = None;
+        loop {
+            let rediscovered = tokio::select! {
+                _ = signalled(&mut signal) => false,
+                _ = poll.tick() => {
+                    let now = modified(&path);
+                    if now == last {
+                        continue;
+                    }
+                    last = now;
+                    false
+                },
+                _ = ticked(&mut discovery) => true,
+            };
+            let reloaded = match (&path, rediscovered) {
+                (Some(path), false) => path.display().to_string(),
+                _ => "the --from-k8s targets".to_string(),
+            };
+            let settings = match reload_settings(targets.clone(), probe.clone(), thresholds.clone()).await {
+                Ok(settings) => settings,
+                Err(err) => {
+                    eprintln!("Cannot reload {}: {}", reloaded, err);
+                    continue;
+                },
+            };
+            if !rediscovered {
+                eprintln!("Reloaded {}", reloaded);
+            } else if let Some(current) = &current {
+                if *current == settings.targets {
+                    continue;
+                }
+                eprintln!("The --from-k8s targets changed: {} now", settings.targets.len());
+            }
+            current = Some(settings.targets.clone());
+            if sender.send(settings).await.is_err() {
+                return;
+            }
+        }
+    });
+    Some(receiver)
+}
+
+async fn run_monitor(mut args: MonitorArgs) -> io::Result<ExitCode> {
+    read_stdin_targets(&mut args.targets)?;
+    let reload = watch_config(&args.targets, &args.probe, &args.thresholds);
+    let config = load_config(&mut args.targets, &mut args.probe, &mut args.thresholds)?;
+    if let Some(config) = &config {
+        args.format = args.format.or(config.format);
+    }
+    let targets = collect_targets(&args.targets, &args.probe).await?;
+    let opts = round_options(&args.probe, &targets)?;
+    let mut monitor = monitor(&args.rounds, thresholds(&args.thresholds), &opts, config)?;
+    let format = args.format.unwrap_or(Format::Text);
+    if format == Format::Parquet {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the parquet format only works for ping runs"));
+    }
+    let run = args.metadata.then(|| RunInfo::start(args.probe.backend));
+    monitor.stream = args.stream.map(|format| open_stream(format, args.output.as_deref(), &args.rotation)).transpose()?;
+    if let (Some(stream), Some(run)) = (&mut monitor.stream, &run) {
+        stream.write_other(&Event::Run { run })?;
+    }
+    monitor.reload = reload;
+    #[cfg(unix)]
+    {
+        monitor.control = args.daemon.control.take();
+    }
+    monitor
+        .run(Arc::new(Mutex::new(targets)), opts, Duration::from_secs(args.period), |_, results, _| {
+            // The monitor wrote the round to its stream.
+            if args.stream.is_some() {
+                return;
+            }
+            let reports: Vec<PingReport> = results.iter().filter_map(|result| result.as_ref().ok().cloned()).collect();
+            let mut stdout = io::stdout().lock();
+            let written = match (&run, format) {
+                (Some(run), Format::Json) => output::write_grouped_json(&Envelope { run: Some(run), targets: &reports, groups: &[] }, &mut stdout),
+                (Some(run), Format::Yaml) => output::write_yaml(&Envelope { run: Some(run), targets: &reports, groups: &[] }, &mut stdout),
+                _ => output::write_results(format, &reports, &mut stdout),
+            };
+            let _ = written.and_then(|_| writeln!(stdout));
+        })
+        .await;
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn serve(mut args: ServeArgs) -> io::Result<ExitCode> {
+    read_stdin_targets(&mut args.targets)?;
+    let reload = watch_config(&args.targets, &args.probe, &args.thresholds);
+    let config = load_config(&mut args.targets, &mut args.probe, &mut args.thresholds)?;
+    let targets = collect_targets(&args.targets, &args.probe).await?;
+    let opts = round_options(&args.probe, &targets)?;
+    let mut monitor = monitor(&args.rounds, thresholds(&args.thresholds), &opts, config)?;
+    monitor.reload = reload;
+    #[cfg(unix)]
+    {
+        monitor.control = args.daemon.control.take();
+    }
+    prometheus::serve(args.listen, targets, opts, Duration::from_secs(args.period), args.buckets, monitor, args.api_token).await?;
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn agent(mut args: AgentArgs) -> io::Result<ExitCode> {
+    read_stdin_targets(&mut args.targets)?;
+    let reload = watch_config(&args.targets, &args.probe, &args.thresholds);
+    let config = load_config(&mut args.targets, &mut args.probe, &mut args.thresholds)?;
+    let targets = collect_targets(&args.targets, &args.probe).await?;
+    let opts = round_options(&args.probe, &targets)?;
+    let mut monitor = monitor(&args.rounds, thresholds(&args.thresholds), &opts, config)?;
+    monitor.reload = reload;
+    let tls = args.tls.then(|| collector::tls_connector(args.ca_cert.as_deref())).transpose()?;
+    let site = args.site.or_else(run::hostname).unwrap_or_else(|| "unknown".to_string());
+    let agent = Agent::start(args.collector, site, args.token, tls);
+    monitor.run(Arc::new(Mutex::new(targets)), opts, Duration::from_secs(args.period), |targets, results, _| agent.send(targets, results)).await;
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn run_collector(args: CollectorArgs) -> io::Result<ExitCode> {
+    let format = args.format.unwrap_or(Format::Text);
+    if !matches!(format, Format::Text | Format::Json | Format::Yaml) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the collector only writes text, json, or yaml"));
+    }
+    let tls = match (&args.tls_cert, &args.tls_key) {
+        (Some(certificate), Some(key)) => Some(collector::tls_acceptor(certificate, key)?),
+        _ => None,
+    };
+    let listener = tokio::net::TcpListener::bind(args.listen).await?;
+    let collector = Collector::new(args.token);
+    tokio::spawn(collector.clone().listen(listener, tls));
+    let mut ticker = tokio::time::interval(Duration::from_secs(args.period));
+    // The first tick is right away, before any agent could report.
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let sites = collector.sites();
+        if sites.is_empty() {
+            continue;
+        }
+        let mut stdout = io::stdout().lock();
+        let written = match format {
+            Format::Json => serde_json::to_writer_pretty(&mut stdout, &sites).map_err(io::Error::from),
+            Format::Yaml => output::write_yaml(&sites, &mut stdout),
+            _ => output::write_matrix(&collector.matrix(), None, &mut stdout),
+        };
+        written.and_then(|_| writeln!(stdout))?;
+    }
+}
+
+fn report(args: ReportArgs) -> io::Result<ExitCode> {
+    let db = Database::open(&args.db)?;
+    let histories = db.history(args.target.as_deref(), args.since.as_deref(), args.until.as_deref())?;
+    output::write_history(args.format, &histories, &mut io::stdout())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn compare_reports(args: CompareArgs) -> io::Result<ExitCode> {
+    let changes = compare::compare(&compare::read_metrics(&args.baseline)?, &compare::read_metrics(&args.current)?, args.max_regression);
+    output::write_changes(args.format, &changes, &mut io::stdout())?;
+    let regressed = changes.iter().any(|change| change.regression);
+    Ok(if regressed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+#[cfg(unix)]
+fn ctl(args: CtlArgs) -> io::Result<ExitCode> {
+    let command = match &args.command {
+        CtlCommand::Status => "status".to_string(),
+        CtlCommand::Add { target } => format!("add {}", target),
+        CtlCommand::Remove { target } => format!("remove {}", target),
+        CtlCommand::Stop => "stop".to_string(),
+    };
+    let socket = match &args.socket {
+        Some(socket) => socket.clone(),
+        None => control::default_path(control::SOCKET)?,
+    };
+    println!("{}", control::send(&socket, &command)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+// Binds the control socket, then forks with --daemon. Forking leaves every
+// thread but the one forking behind, so this comes before the runtime.
+#[cfg(unix)]
+fn start_daemon(args: &mut DaemonArgs) -> io::Result<()> {
+    let socket = match (&args.control_socket, args.daemon) {
+        (Some(socket), _) => socket.clone(),
+        (None, true) => control::default_path(control::SOCKET)?,
+        (None, false) => return Ok(()),
+    };
+    let mut control = Control::bind(&socket)?;
+    if args.daemon {
+        let pid_file = match &args.pid_file {
+            Some(pid_file) => pid_file.clone(),
+            None => control::default_path(control::PID_FILE)?,
+        };
+        control::daemonize(&pid_file)?;
+        control.pid_file = Some(pid_file);
+    }
+    args.control = Some(control);
     Ok(())
 }
+
+// The command line this process was started with, less the flag asking for
+// the unit, run from the current directory.
+#[cfg(target_os = "linux")]
+fn systemd_unit() -> io::Result<String> {
+    let mut command = vec![std::env::current_exe()?.to_string_lossy().into_owned()];
+    command.extend(std::env::args().skip(1).filter(|arg| arg != "--generate-systemd-unit"));
+    let subcommand = command.iter().skip(1).find(|arg| *arg == "monitor" || *arg == "serve").cloned().unwrap_or_default();
+    Ok(systemd::unit(&format!("multiping {}", subcommand), &command, &std::env::current_dir()?))
+}
+
+// Logs are off unless asked for, so they stay out of the progress bars.
+// Only multiping's own events are logged, not those of the crates it uses.
+fn init_logging(cli: &Cli) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    if cli.log_journald {
+        let filter = Targets::new().with_target("multiping", cli.log_level.unwrap_or(LevelFilter::INFO));
+        tracing_subscriber::registry().with(systemd::Journald::new()?.with_filter(filter)).init();
+        return Ok(());
+    }
+    if let Some(path) = &cli.log_file {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        let filter = Targets::new().with_target("multiping", cli.log_level.unwrap_or(LevelFilter::INFO));
+        let layer = tracing_subscriber::fmt::layer().json().with_writer(std::sync::Mutex::new(file));
+        tracing_subscriber::registry().with(layer.with_filter(filter)).init();
+    } else if let Some(level) = cli.log_level {
+        let layer = tracing_subscriber::fmt::layer().with_writer(io::stderr);
+        tracing_subscriber::registry().with(layer.with_filter(Targets::new().with_target("multiping", level))).init();
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<ExitCode> {
+    let mut cli = Cli::parse();
+    init_logging(&cli)?;
+    #[cfg(unix)]
+    if let Some(Command::Monitor(MonitorArgs { daemon, .. }) | Command::Serve(ServeArgs { daemon, .. })) = &mut cli.command {
+        #[cfg(target_os = "linux")]
+        if daemon.generate_systemd_unit {
+            print!("{}", systemd_unit()?);
+            return Ok(ExitCode::SUCCESS);
+        }
+        start_daemon(daemon)?;
+    }
+    tokio::runtime::Runtime::new()?.block_on(run(cli))
+}
+
+async fn run(cli: Cli) -> io::Result<ExitCode> {
+    match cli.command {
+        None => ping(cli.ping).await,
+        Some(Command::Ping(args)) => ping(args).await,
+        Some(Command::Monitor(args)) => run_monitor(args).await,
+        Some(Command::Serve(args)) => serve(args).await,
+        Some(Command::Agent(args)) => agent(args).await,
+        Some(Command::Collector(args)) => run_collector(args).await,
+        Some(Command::Report(args)) => report(args),
+        Some(Command::Compare(args)) => compare_reports(args),
+        #[cfg(unix)]
+        Some(Command::Ctl(args)) => ctl(args),
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "multiping", &mut io::stdout());
+            Ok(ExitCode::SUCCESS)
+        },
+    }
+}
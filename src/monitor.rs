@@ -0,0 +1,519 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::MissedTickBehavior;
+use crate::alert::{Alert, Webhook};
+#[cfg(unix)]
+use crate::control::Control;
+use crate::db::Database;
+use crate::email::Email;
+use crate::hooks::Hooks;
+use crate::pagerduty::PagerDuty;
+use crate::report::{Hop, PingReport};
+use crate::silence::Silence;
+use crate::stream::{Event, Stream};
+#[cfg(target_os = "linux")]
+use crate::systemd;
+use crate::targets::Target;
+use crate::thresholds::Thresholds;
+use crate::trace;
+use crate::{EventKind, PingEvent, PingOptions, PingRunner};
+
+/// Where a target stands across rounds of continuous pinging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum State {
+    #[default]
+    Up,
+    /// Failing, but not for long enough to count as down.
+    Degraded,
+    Down,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            State::Up => "up",
+            State::Degraded => "degraded",
+            State::Down => "down",
+        })
+    }
+}
+
+/// How many consecutive rounds it takes to change a target's state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hysteresis {
+    /// Failing rounds before a target is down.
+    pub down_after: u32,
+    /// Healthy rounds before a down target is up again.
+    pub up_after: u32,
+}
+
+impl Default for Hysteresis {
+    fn default() -> Self {
+        Hysteresis { down_after: 3, up_after: 2 }
+    }
+}
+
+/// How often a target may go down or come back up before it counts as
+/// flapping, and stops being alerted on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flapping {
+    /// State changes allowed within the window.
+    pub changes: u32,
+    pub window: Duration,
+}
+
+/// A target changing state after a round.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub target: String,
+    pub from: State,
+    pub to: State,
+    /// What was wrong with the target in the round, if anything.
+    pub alert: Option<Alert>,
+    pub loss_percent: Option<f32>,
+    /// Average round trip time in the round, in milliseconds.
+    pub avg: Option<f32>,
+    /// The target changed state too often lately to be alerted on.
+    pub flapping: bool,
+    /// The target stopped flapping, and stays in the state it went to
+    /// while it was; `from` and `to` are the same.
+    pub settled: bool,
+}
+
+#[derive(Debug, Default)]
+struct TargetState {
+    state: State,
+    // Consecutive rounds going against the current state.
+    streak: u32,
+    // When the target went down or came back up, within the flapping window.
+    changes: VecDeque<Instant>,
+    // Whether the last change happened while flapping, and was not alerted on.
+    suppressed: bool,
+}
+
+/// Tracks the state of every target from the results of successive rounds.
+/// A round fails when the target could not be pinged, answered no probe,
+/// or crossed a threshold. With `flapping`, it also tells which targets
+/// change state too often.
+#[derive(Debug, Default)]
+pub struct StateTracker {
+    hysteresis: Hysteresis,
+    flapping: Option<Flapping>,
+    thresholds: Thresholds,
+    targets: HashMap<String, TargetState>,
+}
+
+impl StateTracker {
+    pub fn new(hysteresis: Hysteresis, flapping: Option<Flapping>, thresholds: Thresholds) -> StateTracker {
+        StateTracker { hysteresis, flapping, thresholds, targets: HashMap::new() }
+    }
+
+    /// Where `target` stood after the last round it was in.
+    pub fn state(&self, target: &str) -> Option<State> {
+        self.targets.get(target).map(|current| current.state)
+    }
+
+    /// How many times `target` went down or came back up within the
+    /// flapping window, when flapping is watched for.
+    pub fn flaps(&self, target: &str) -> Option<u32> {
+        self.flapping?;
+        Some(self.targets.get(target).map_or(0, |current| current.changes.len() as u32))
+    }
+
+    /// Takes in a round of `results` for `targets` and returns the targets
+    /// whose state changed.
+    pub fn update(&mut self, targets: &[Target], results: &[io::Result<PingReport>]) -> Vec<Transition> {
+        let mut transitions = Vec::new();
+        let now = Instant::now();
+        for (target, result) in targets.iter().zip(results) {
+            let label = target.label();
+            let alert = Alert::check(&label, result, &self.thresholds);
+            let current = self.targets.entry(label.clone()).or_default();
+            let from = current.state;
+            let failing = alert.is_some();
+            current.streak = match (current.state, failing) {
+                (State::Up, false) | (State::Down, true) => 0,
+                (State::Degraded, false) => {
+                    current.state = State::Up;
+                    0
+                },
+                _ => current.streak + 1,
+            };
+            if failing && current.streak >= self.hysteresis.down_after.max(1) {
+                current.state = State::Down;
+                current.streak = 0;
+            } else if failing && current.state == State::Up {
+                current.state = State::Degraded;
+            } else if !failing && current.state == State::Down && current.streak >= self.hysteresis.up_after.max(1) {
+                current.state = State::Up;
+                current.streak = 0;
+            }
+            if let Some(flapping) = self.flapping {
+                while current.changes.front().is_some_and(|&at| now.duration_since(at) > flapping.window) {
+                    current.changes.pop_front();
+                }
+                // Going from up to degraded and back is not a flap.
+                if (from == State::Down) != (current.state == State::Down) {
+                    current.changes.push_back(now);
+                }
+            }
+            let flapping = self.flapping.is_some_and(|flapping| current.changes.len() as u32 > flapping.changes);
+            // A target that went down while flapping, and is still down once
+            // it stops, is alerted on then.
+            let settled = current.state == from && current.suppressed && !flapping && current.state == State::Down && alert.is_some();
+            if current.state == from && !settled {
+                continue;
+            }
+            current.suppressed = flapping;
+            let report = result.as_ref().ok();
+            transitions.push(Transition {
+                target: label,
+                from,
+                to: current.state,
+                alert,
+                loss_percent: report.and_then(|report| report.packets.as_ref()).map(|packets| packets.loss_percent),
+                avg: report.and_then(|report| report.trips.as_ref()).map(|trips| trips.avg),
+                flapping,
+                settled,
+            });
+        }
+        transitions
+    }
+}
+
+/// A target whose path changed between two traces. Hops are given by the
+/// router that answered, if any did.
+#[derive(Debug, Clone)]
+pub struct PathChange {
+    pub target: String,
+    pub previous: Vec<Option<String>>,
+    pub current: Vec<Option<String>>,
+}
+
+/// Traces the targets every few rounds and remembers their paths, to tell
+/// when the routing to them changes.
+#[derive(Debug)]
+pub struct PathTracker {
+    /// Rounds between traces.
+    every: u32,
+    /// Seconds traceroute waits for each probe.
+    timeout: u32,
+    rounds: u32,
+    paths: HashMap<String, Vec<Option<String>>>,
+}
+
+impl PathTracker {
+    pub fn new(every: u32, timeout: u32) -> PathTracker {
+        PathTracker { every: every.max(1), timeout, rounds: 0, paths: HashMap::new() }
+    }
+
+    // Counts a round and tells whether its targets get traced; the first is.
+    fn due(&mut self) -> bool {
+        self.rounds += 1;
+        (self.rounds - 1).is_multiple_of(self.every)
+    }
+
+    /// Records the path of `target` and returns the change from the path
+    /// recorded before, if there was one. Hops that did not answer in either
+    /// trace do not count as changes.
+    pub fn update(&mut self, target: &str, hops: &[Hop]) -> Option<PathChange> {
+        let current: Vec<Option<String>> = hops.iter().map(|hop| hop.host.clone()).collect();
+        let previous = self.paths.insert(target.to_string(), current.clone())?;
+        let changed = previous.len() != current.len()
+            || previous.iter().zip(&current).any(|pair| matches!(pair, (Some(before), Some(now)) if before != now));
+        changed.then(|| PathChange { target: target.to_string(), previous, current })
+    }
+
+    async fn trace(&mut self, results: &[io::Result<PingReport>]) -> Vec<PathChange> {
+        let traces: Vec<_> = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .filter_map(|report| Some((report.destination.clone(), tokio::spawn(trace::trace(report.address?, self.timeout)))))
+            .collect();
+        let mut changes = Vec::new();
+        for (target, trace) in traces {
+            match trace.await.unwrap_or_else(|err| Err(err.into())) {
+                Ok(hops) => changes.extend(self.update(&target, &hops)),
+                Err(err) => eprintln!("Cannot trace {}: {}", target, err),
+            }
+        }
+        changes
+    }
+}
+
+fn describe_path(hops: &[Option<String>]) -> String {
+    let hops: Vec<&str> = hops.iter().map(|hop| hop.as_deref().unwrap_or("*")).collect();
+    hops.join(" > ")
+}
+
+/// What reloading the config file changes in a monitor, from the next round
+/// on. The targets' states carry over.
+#[derive(Debug)]
+pub struct Settings {
+    pub targets: Vec<Target>,
+    pub opts: PingOptions,
+    pub thresholds: Thresholds,
+    pub email: Option<Email>,
+    pub pagerduty: Option<PagerDuty>,
+    pub silences: Vec<Silence>,
+}
+
+// The targets of a reload, less those removed since the last one, and
+// with those added since: changes made while running outlast reloads.
+fn reloaded(configured: &[Target], current: &[Target], targets: Vec<Target>) -> Vec<Target> {
+    let labels = |targets: &[Target]| targets.iter().map(Target::label).collect::<HashSet<_>>();
+    let (before, now) = (labels(configured), labels(current));
+    let mut targets: Vec<Target> = targets.into_iter().filter(|target| now.contains(&target.label()) || !before.contains(&target.label())).collect();
+    let kept = labels(&targets);
+    targets.extend(current.iter().filter(|target| !before.contains(&target.label()) && !kept.contains(&target.label())).cloned());
+    targets
+}
+
+/// Watches the rounds of continuous pinging: logs state changes on standard
+/// error, alerts `webhook` when a target goes down, mails `email` a summary
+/// of the targets that went down in a round, opens and resolves incidents
+/// on `pagerduty` as targets go down and back up, runs the `hooks`, and
+/// stores the rounds in `db`. None of that but the logging and storing
+/// happens for targets under one of the `silences`, or for targets that are
+/// flapping. With `paths`, it also traces the targets and logs the changes
+/// in their paths. The summaries of every round, and the path changes, go
+/// to `stream` when given. Every event of the rounds, path changes
+/// included, goes to `events` for whoever listens. Settings that come from
+/// `reload` replace the current ones.
+#[derive(Debug, Default)]
+pub struct Monitor {
+    pub tracker: StateTracker,
+    pub webhook: Option<Webhook>,
+    pub email: Option<Email>,
+    pub pagerduty: Option<PagerDuty>,
+    pub silences: Vec<Silence>,
+    pub hooks: Option<Hooks>,
+    pub db: Option<Database>,
+    pub paths: Option<PathTracker>,
+    pub stream: Option<Stream>,
+    pub events: Option<broadcast::Sender<PingEvent>>,
+    pub reload: Option<mpsc::Receiver<Settings>>,
+    /// Taken up by `run`, which answers its commands from then on.
+    #[cfg(unix)]
+    pub control: Option<Control>,
+}
+
+impl Monitor {
+    /// Pings `targets` every `period` until the process is stopped, taking
+    /// in any change to them at the start of a round. Every round goes to
+    /// `round`, then to `each` with the states it left the targets in.
+    pub async fn run<F>(&mut self, targets: Arc<Mutex<Vec<Target>>>, mut opts: PingOptions, period: Duration, mut each: F)
+    where
+        F: FnMut(&[Target], &[io::Result<PingReport>], &StateTracker),
+    {
+        let mut ticker = tokio::time::interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        #[cfg(unix)]
+        let board = match self.control.take().map(|control| control.start(targets.clone(), opts.clone())).transpose() {
+            Ok(board) => board,
+            Err(err) => {
+                eprintln!("Cannot answer on the control socket: {}", err);
+                None
+            },
+        };
+        #[cfg(target_os = "linux")]
+        systemd::ready();
+        // The targets the last reload left, to tell those added and removed since.
+        let mut configured = targets.lock().unwrap().clone();
+        loop {
+            ticker.tick().await;
+            while let Some(settings) = self.reload.as_mut().and_then(|reload| reload.try_recv().ok()) {
+                let mut current = targets.lock().unwrap();
+                *current = reloaded(&configured, &current, settings.targets.clone());
+                configured = settings.targets;
+                opts = settings.opts;
+                self.tracker.thresholds = settings.thresholds;
+                self.email = settings.email;
+                self.pagerduty = settings.pagerduty;
+                self.silences = settings.silences;
+            }
+            let targets = targets.lock().unwrap().clone();
+            let mut results = match &self.events {
+                Some(events) => {
+                    let (sender, mut receiver) = mpsc::channel(10);
+                    let events = events.clone();
+                    // Nobody listening is no reason to hold the round up.
+                    let forward = tokio::spawn(async move {
+                        while let Some(event) = receiver.recv().await {
+                            let _ = events.send(event);
+                        }
+                    });
+                    let results = PingRunner::run_with_sender(targets.clone(), opts.clone(), sender).await;
+                    let _ = forward.await;
+                    results
+                },
+                None => PingRunner::run(targets.clone(), opts.clone()).await,
+            };
+            self.round(&targets, &results).await;
+            for (target, result) in targets.iter().zip(&mut results) {
+                if let Ok(report) = result {
+                    report.flaps = self.tracker.flaps(&target.label());
+                }
+            }
+            #[cfg(unix)]
+            if let Some(board) = &board {
+                board.record(&targets, &results, &self.tracker);
+            }
+            if let Some(Err(err)) = self.stream.as_mut().map(|stream| stream.write_round(&targets, &results)) {
+                eprintln!("Cannot write the stream: {}", err);
+            }
+            each(&targets, &results, &self.tracker);
+        }
+    }
+
+    pub async fn round(&mut self, targets: &[Target], results: &[io::Result<PingReport>]) {
+        if let Some(db) = &mut self.db {
+            if let Err(err) = db.record(targets, results) {
+                eprintln!("Cannot store the round: {}", err);
+            }
+        }
+        let mut down = Vec::new();
+        let now = SystemTime::now();
+        for transition in self.tracker.update(targets, results) {
+            // Silences hold back new alerts, but incidents are still closed.
+            if let (Some(pagerduty), State::Down, State::Up) = (&self.pagerduty, transition.from, transition.to) {
+                if let Err(err) = pagerduty.resolve(&transition.target).await {
+                    eprintln!("Cannot resolve the incident for {}: {}", transition.target, err);
+                }
+            }
+            if self.silences.iter().any(|silence| silence.covers(&transition.target, now)) {
+                eprintln!("{}: {} -> {} (silenced)", transition.target, transition.from, transition.to);
+                continue;
+            }
+            if transition.flapping {
+                eprintln!("{}: {} -> {} (flapping)", transition.target, transition.from, transition.to);
+                continue;
+            }
+            if transition.settled {
+                eprintln!("{}: {} (no longer flapping)", transition.target, transition.to);
+            } else {
+                eprintln!("{}: {} -> {}", transition.target, transition.from, transition.to);
+            }
+            if let Some(hooks) = &self.hooks {
+                hooks.run(&transition);
+            }
+            let (Some(alert), State::Down) = (transition.alert, transition.to) else {
+                continue;
+            };
+            if let Some(webhook) = &self.webhook {
+                if let Err(err) = webhook.send(&alert).await {
+                    eprintln!("Cannot send alert for {}: {}", alert.target, err);
+                }
+            }
+            if let Some(pagerduty) = &self.pagerduty {
+                if let Err(err) = pagerduty.trigger(&alert).await {
+                    eprintln!("Cannot open an incident for {}: {}", alert.target, err);
+                }
+            }
+            down.push(alert);
+        }
+        if let (Some(email), false) = (&self.email, down.is_empty()) {
+            if let Err(err) = email.send(&down).await {
+                eprintln!("Cannot mail the alerts: {}", err);
+            }
+        }
+        let Some(paths) = &mut self.paths else {
+            return;
+        };
+        if !paths.due() {
+            return;
+        }
+        for change in paths.trace(results).await {
+            eprintln!("{}: path changed from {} to {}", change.target, describe_path(&change.previous), describe_path(&change.current));
+            let event = PingEvent::new(&change.target, EventKind::PathChanged { previous: change.previous, current: change.current });
+            if let (Some(stream), Some(written)) = (&mut self.stream, Event::from_event(&event)) {
+                if let Err(err) = stream.write_other(&written) {
+                    eprintln!("Cannot write the stream: {}", err);
+                }
+            }
+            if let Some(events) = &self.events {
+                let _ = events.send(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::PacketStatistics;
+
+    // Takes target `a` through a round, answering when `up`, and returns
+    // the change of state if there was one.
+    fn round(tracker: &mut StateTracker, up: bool) -> Option<Transition> {
+        let report = PingReport {
+            destination: "a".to_string(),
+            packets: Some(PacketStatistics { transmitted: 1, received: up as u32, loss_percent: if up { 0.0 } else { 100.0 } }),
+            ..Default::default()
+        };
+        tracker.update(&[Target::new("a")], &[Ok(report)]).pop()
+    }
+
+    fn states(tracker: &mut StateTracker, rounds: &[bool]) -> Vec<Option<State>> {
+        rounds.iter().map(|&up| round(tracker, up).map(|transition| transition.to)).collect()
+    }
+
+    #[test]
+    fn targets_go_down_and_up_after_enough_rounds() {
+        let mut tracker = StateTracker::new(Hysteresis::default(), None, Thresholds::default());
+        assert_eq!(states(&mut tracker, &[true, false, false, false, true, true]), [
+            None,
+            Some(State::Degraded),
+            None,
+            Some(State::Down),
+            None,
+            Some(State::Up),
+        ]);
+        assert_eq!(tracker.state("a"), Some(State::Up));
+    }
+
+    #[test]
+    fn degraded_targets_recover_at_once() {
+        let mut tracker = StateTracker::new(Hysteresis::default(), None, Thresholds::default());
+        assert_eq!(states(&mut tracker, &[false, true, false, false, true]), [
+            Some(State::Degraded),
+            Some(State::Up),
+            Some(State::Degraded),
+            None,
+            Some(State::Up),
+        ]);
+    }
+
+    #[test]
+    fn flapping_targets_are_alerted_on_once_they_settle() {
+        let flapping = Flapping { changes: 2, window: Duration::from_millis(200) };
+        let mut tracker = StateTracker::new(Hysteresis { down_after: 1, up_after: 1 }, Some(flapping), Thresholds::default());
+        let flags: Vec<_> = [false, true, false]
+            .into_iter()
+            .map(|up| round(&mut tracker, up).map(|transition| transition.flapping))
+            .collect();
+        assert_eq!(flags, [Some(false), Some(false), Some(true)]);
+        assert_eq!(tracker.flaps("a"), Some(3));
+        assert!(round(&mut tracker, false).is_none());
+        std::thread::sleep(Duration::from_millis(300));
+        let transition = round(&mut tracker, false).unwrap();
+        assert!(transition.settled && !transition.flapping);
+        assert_eq!((transition.from, transition.to), (State::Down, State::Down));
+        assert_eq!(tracker.flaps("a"), Some(0));
+        assert!(round(&mut tracker, false).is_none());
+    }
+
+    #[test]
+    fn changes_made_while_running_outlast_reloads() {
+        let targets = |hosts: &[&str]| hosts.iter().map(|&host| Target::new(host)).collect::<Vec<_>>();
+        let hosts = |targets: Vec<Target>| targets.into_iter().map(|target| target.host).collect::<Vec<_>>();
+        let configured = targets(&["a", "b", "c"]);
+        let current = targets(&["a", "c", "added"]);
+        assert_eq!(hosts(reloaded(&configured, &current, targets(&["a", "b", "d"]))), ["a", "d", "added"]);
+        assert_eq!(hosts(reloaded(&configured, &current, targets(&["added", "b"]))), ["added"]);
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::error::MultipingError;
+use crate::report::PingReport;
+
+/// Round trip (milliseconds) and packet loss (percent) from which a target
+/// is in a state, written `RTA,PL%` as for check_ping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub rta: f32,
+    pub loss: f32,
+}
+
+impl FromStr for Limits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rta, loss) = s.split_once(',').ok_or_else(|| format!("expected RTA,PL% limits, found '{}'", s))?;
+        let parse = |value: &str| value.trim().trim_end_matches('%').parse::<f32>().map_err(|_| format!("invalid limit '{}'", value));
+        Ok(Limits { rta: parse(rta)?, loss: parse(loss)? })
+    }
+}
+
+/// The limits check_ping's documentation suggests, for when none are given.
+pub const WARNING: Limits = Limits { rta: 100.0, loss: 20.0 };
+pub const CRITICAL: Limits = Limits { rta: 500.0, loss: 60.0 };
+
+/// A plugin result, ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum State {
+    Ok,
+    Warning,
+    Unknown,
+    Critical,
+}
+
+impl State {
+    /// The exit status that tells Nagios the state.
+    pub fn code(self) -> u8 {
+        match self {
+            State::Ok => 0,
+            State::Warning => 1,
+            State::Critical => 2,
+            State::Unknown => 3,
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            State::Ok => "OK",
+            State::Warning => "WARNING",
+            State::Critical => "CRITICAL",
+            State::Unknown => "UNKNOWN",
+        })
+    }
+}
+
+// Like check_ping, a target that does not resolve is critical; one that
+// could not be pinged for other reasons is unknown.
+fn state(report: &PingReport, warning: Limits, critical: Limits) -> State {
+    match &report.error {
+        Some(MultipingError::Unresolvable(_) | MultipingError::Timeout(_)) => return State::Critical,
+        Some(_) => return State::Unknown,
+        None => (),
+    }
+    let loss = report.packets.as_ref().map_or(100.0, |packets| packets.loss_percent);
+    let rta = report.trips.as_ref().map(|trips| trips.avg);
+    let reaches = |limits: Limits| loss >= limits.loss || rta.is_some_and(|rta| rta >= limits.rta);
+    if reaches(critical) {
+        State::Critical
+    } else if reaches(warning) {
+        State::Warning
+    } else {
+        State::Ok
+    }
+}
+
+fn describe(report: &PingReport) -> String {
+    // The errors already name their target.
+    if let Some(error) = &report.error {
+        return error.to_string();
+    }
+    let loss = report.packets.as_ref().map_or(100.0, |packets| packets.loss_percent);
+    match &report.trips {
+        Some(trips) => format!("{}: Packet loss = {}%, RTA = {} ms", report.destination, loss, trips.avg),
+        None => format!("{}: Packet loss = {}%", report.destination, loss),
+    }
+}
+
+// Labels with spaces or quotes have to be quoted, with quotes doubled.
+fn label(target: &str, metric: &str) -> String {
+    format!("'{}_{}'", target.replace('\'', "''"), metric)
+}
+
+/// Checks every target and returns the worst state along with the plugin
+/// output line: the targets that are not OK, then `rta` and `pl` perfdata
+/// for every target.
+pub fn check(results: &[PingReport], warning: Limits, critical: Limits) -> (State, String) {
+    let states: Vec<State> = results.iter().map(|report| state(report, warning, critical)).collect();
+    let worst = states.iter().copied().max().unwrap_or(State::Unknown);
+    let problems: Vec<String> = results
+        .iter()
+        .zip(&states)
+        .filter(|(_, state)| **state != State::Ok)
+        .map(|(report, _)| describe(report))
+        .collect();
+    let summary = if results.is_empty() {
+        "no targets".to_string()
+    } else if problems.is_empty() {
+        format!("{} of {} targets OK", results.len(), results.len())
+    } else {
+        problems.join("; ")
+    };
+    let mut perfdata = Vec::new();
+    for report in results.iter().filter(|report| report.error.is_none()) {
+        if let Some(trips) = &report.trips {
+            perfdata.push(format!("{}={}ms;{};{};0", label(&report.destination, "rta"), trips.avg, warning.rta, critical.rta));
+        }
+        let loss = report.packets.as_ref().map_or(100.0, |packets| packets.loss_percent);
+        perfdata.push(format!("{}={}%;{};{};0", label(&report.destination, "pl"), loss, warning.loss, critical.loss));
+    }
+    (worst, format!("PING {} - {}|{}", worst, summary, perfdata.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{PacketStatistics, RoundTripStatistics};
+
+    fn report(destination: &str, loss_percent: f32, avg: Option<f32>) -> PingReport {
+        PingReport {
+            destination: destination.to_string(),
+            packets: Some(PacketStatistics { transmitted: 10, received: 10 - (loss_percent / 10.0) as u32, loss_percent }),
+            trips: avg.map(|avg| RoundTripStatistics { min: avg, avg, max: avg, stddev: 0.0 }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!("200,40%".parse(), Ok(Limits { rta: 200.0, loss: 40.0 }));
+        assert_eq!(" 50.5 , 5 ".parse(), Ok(Limits { rta: 50.5, loss: 5.0 }));
+        assert_eq!("200".parse::<Limits>(), Err("expected RTA,PL% limits, found '200'".to_string()));
+        assert!("fast,40%".parse::<Limits>().is_err());
+    }
+
+    #[test]
+    fn states_go_by_the_worst_target() {
+        let (state, output) = check(&[report("a", 0.0, Some(10.0)), report("b", 0.0, Some(20.0))], WARNING, CRITICAL);
+        assert_eq!((state, state.code()), (State::Ok, 0));
+        assert_eq!(output, "PING OK - 2 of 2 targets OK|'a_rta'=10ms;100;500;0 'a_pl'=0%;20;60;0 'b_rta'=20ms;100;500;0 'b_pl'=0%;20;60;0");
+        let (state, output) = check(&[report("a", 0.0, Some(150.0)), report("b", 60.0, Some(20.0))], WARNING, CRITICAL);
+        assert_eq!(state, State::Critical);
+        assert!(output.starts_with("PING CRITICAL - a: Packet loss = 0%, RTA = 150 ms; b: Packet loss = 60%, RTA = 20 ms|"));
+        assert_eq!(check(&[], WARNING, CRITICAL).0, State::Unknown);
+    }
+
+    #[test]
+    fn errors_are_critical_or_unknown() {
+        let failed = |error| PingReport { destination: "a".to_string(), error: Some(error), ..Default::default() };
+        assert_eq!(check(&[failed(MultipingError::Unresolvable("a: no address".to_string()))], WARNING, CRITICAL).0, State::Critical);
+        let (state, output) = check(&[failed(MultipingError::PermissionDenied("a: no raw sockets".to_string()))], WARNING, CRITICAL);
+        assert_eq!((state, state.code()), (State::Unknown, 3));
+        // Targets that could not be pinged have no perfdata.
+        assert!(output.ends_with('|'));
+    }
+
+    #[test]
+    fn labels_quote_quotes() {
+        assert_eq!(label("o'brien", "rta"), "'o''brien_rta'");
+    }
+}
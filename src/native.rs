@@ -0,0 +1,498 @@
+use std::io;
+use std::collections::HashMap;
+use std::mem;
+use std::net::{IpAddr, SocketAddr};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use tokio::sync::mpsc;
+use crate::PingOptions;
+use crate::event::{EventKind, PingEvent};
+use crate::report::{PingInfo, PingReport, RunClock};
+use crate::stats::{keep, packet_statistics, round_ms, round_trip_statistics};
+
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const ICMP_HEADER_SIZE: usize = 8;
+// The largest payloads that fit in an IPv4 datagram and an IPv6 packet.
+const MAX_PAYLOAD_V4: usize = 65507;
+const MAX_PAYLOAD_V6: usize = 65527;
+
+static NEXT_IDENTIFIER: AtomicU16 = AtomicU16::new(0);
+
+struct Reply {
+    seq: u16,
+    bytes: usize,
+    ttl: u32,
+    received_at: Instant,
+}
+
+struct Prober {
+    socket: Socket,
+    destination: SockAddr,
+    address: IpAddr,
+    identifier: u16,
+    raw: bool,
+    size: usize,
+}
+
+impl Prober {
+    fn open(address: IpAddr, size: usize) -> io::Result<Prober> {
+        let max = if address.is_ipv6() { MAX_PAYLOAD_V6 } else { MAX_PAYLOAD_V4 };
+        if size > max {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("packet size {} is larger than {}", size, max)));
+        }
+        // An unprivileged datagram socket is preferred; raw sockets need
+        // CAP_NET_RAW or root, and receive every ICMP packet on the host.
+        let (domain, protocol) = match address {
+            IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+            IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+        };
+        let (socket, raw) = match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+            Ok(socket) => (socket, false),
+            Err(_) => (Socket::new(domain, Type::RAW, Some(protocol))?, true),
+        };
+        set_recv_ttl(&socket, address.is_ipv6())?;
+        let identifier = (std::process::id() as u16)
+            .wrapping_add(NEXT_IDENTIFIER.fetch_add(1, Ordering::Relaxed));
+        tracing::debug!(%address, raw, identifier, "ICMP socket opened");
+        Ok(Prober {
+            socket,
+            destination: SocketAddr::new(address, 0).into(),
+            address,
+            identifier,
+            raw,
+            size,
+        })
+    }
+
+    fn send(&self, seq: u16) -> io::Result<()> {
+        self.send_sized(seq, self.size)
+    }
+
+    fn send_sized(&self, seq: u16, size: usize) -> io::Result<()> {
+        let mut packet = vec![0u8; ICMP_HEADER_SIZE + size];
+        packet[0] = if self.address.is_ipv6() { ICMPV6_ECHO_REQUEST } else { ICMP_ECHO_REQUEST };
+        packet[4..6].copy_from_slice(&self.identifier.to_be_bytes());
+        packet[6..8].copy_from_slice(&seq.to_be_bytes());
+        for (i, byte) in packet[ICMP_HEADER_SIZE..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        // The kernel fills in ICMPv6 checksums, as they cover the IPv6 pseudo-header.
+        if self.address.is_ipv4() {
+            let sum = checksum(&packet);
+            packet[2..4].copy_from_slice(&sum.to_be_bytes());
+        }
+        self.socket.send_to(&packet, &self.destination)?;
+        Ok(())
+    }
+
+    fn receive(&self, deadline: Instant) -> io::Result<Option<Reply>> {
+        // Room for the payload, the ICMP header, and an IPv4 header with options.
+        let mut buf = vec![0u8; (self.size + ICMP_HEADER_SIZE + 60).max(1500)];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
+            let (len, ttl, source) = match recv_with_ttl(&self.socket, &mut buf) {
+                Ok(received) => received,
+                Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(None),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if self.raw && source != Some(self.address) {
+                continue;
+            }
+            let mut packet = &buf[..len];
+            let mut ttl = ttl;
+            // IPv4 raw sockets (and datagram sockets on macOS) hand us the IP header too.
+            if self.address.is_ipv4() && !packet.is_empty() && packet[0] >> 4 == 4 {
+                let header_len = ((packet[0] & 0x0f) as usize) * 4;
+                if packet.len() < header_len {
+                    continue;
+                }
+                ttl = ttl.or(Some(packet[8] as u32));
+                packet = &packet[header_len..];
+            }
+            let reply_type = if self.address.is_ipv6() { ICMPV6_ECHO_REPLY } else { ICMP_ECHO_REPLY };
+            if packet.len() < ICMP_HEADER_SIZE || packet[0] != reply_type {
+                continue;
+            }
+            let identifier = u16::from_be_bytes([packet[4], packet[5]]);
+            let reply_seq = u16::from_be_bytes([packet[6], packet[7]]);
+            // The kernel rewrites the identifier of datagram ICMP sockets.
+            if self.raw && identifier != self.identifier {
+                continue;
+            }
+            return Ok(Some(Reply {
+                seq: reply_seq,
+                bytes: packet.len(),
+                ttl: ttl.unwrap_or(0),
+                received_at: Instant::now(),
+            }));
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for chunk in data.chunks(2) {
+        let word = match chunk {
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            [high] => u16::from_be_bytes([*high, 0]),
+            _ => 0,
+        };
+        sum += word as u32;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn set_recv_ttl(socket: &Socket, ipv6: bool) -> io::Result<()> {
+    if ipv6 {
+        set_int_option(socket, libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT, 1)
+    } else {
+        set_int_option(socket, libc::IPPROTO_IP, libc::IP_RECVTTL, 1)
+    }
+}
+
+// Sets DF on outgoing packets and keeps the kernel from fragmenting them.
+#[cfg(target_os = "linux")]
+fn set_dont_fragment(socket: &Socket, ipv6: bool) -> io::Result<()> {
+    if ipv6 {
+        set_int_option(socket, libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER, libc::IPV6_PMTUDISC_DO)
+    } else {
+        set_int_option(socket, libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, libc::IP_PMTUDISC_DO)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_dont_fragment(_socket: &Socket, _ipv6: bool) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "path MTU discovery is only supported on Linux"))
+}
+
+// Sends from the source address and through the interface asked for, if any.
+fn bind_source(socket: &Socket, target: &str, opts: &PingOptions) -> io::Result<()> {
+    if let Some(source) = opts.source_ip {
+        socket
+            .bind(&SocketAddr::new(source, 0).into())
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: cannot send from {} ({})", target, source, err)))?;
+    }
+    if let Some(interface) = &opts.interface {
+        bind_device(socket, interface)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: cannot send through {} ({})", target, interface, err)))?;
+    }
+    Ok(())
+}
+
+// Marks outgoing packets with the DSCP and gives them the TTL asked for, if any.
+fn set_marking(socket: &Socket, ipv6: bool, opts: &PingOptions) -> io::Result<()> {
+    if let Some(dscp) = opts.dscp {
+        if ipv6 {
+            set_int_option(socket, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, dscp.tos() as libc::c_int)?;
+        } else {
+            set_int_option(socket, libc::IPPROTO_IP, libc::IP_TOS, dscp.tos() as libc::c_int)?;
+        }
+    }
+    match opts.ttl {
+        Some(ttl) if ipv6 => socket.set_unicast_hops_v6(ttl),
+        Some(ttl) => socket.set_ttl_v4(ttl),
+        None => Ok(()),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(socket: &Socket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_device(_socket: &Socket, _interface: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "choosing an interface is only supported on Linux"))
+}
+
+fn set_int_option(socket: &Socket, level: libc::c_int, option: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            option,
+            &value as *const _ as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn recv_with_ttl(socket: &Socket, buf: &mut [u8]) -> io::Result<(usize, Option<u32>, Option<IpAddr>)> {
+    let mut source: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut control = [0u8; 64];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = &mut source as *mut _ as *mut libc::c_void;
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+    let len = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut ttl = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            let header = &*cmsg;
+            let is_ttl = (header.cmsg_level == libc::IPPROTO_IP
+                && (header.cmsg_type == libc::IP_TTL || header.cmsg_type == libc::IP_RECVTTL))
+                || (header.cmsg_level == libc::IPPROTO_IPV6 && header.cmsg_type == libc::IPV6_HOPLIMIT);
+            if is_ttl {
+                let data = libc::CMSG_DATA(cmsg);
+                let data_len = header.cmsg_len as usize - (data as usize - cmsg as usize);
+                ttl = Some(if data_len >= mem::size_of::<libc::c_int>() {
+                    (data as *const libc::c_int).read_unaligned() as u32
+                } else {
+                    *data as u32
+                });
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok((len as usize, ttl, source_address(&source)))
+}
+
+fn source_address(storage: &libc::sockaddr_storage) -> Option<IpAddr> {
+    match storage.ss_family as libc::c_int {
+        libc::AF_INET => {
+            let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+            Some(IpAddr::from(u32::from_be(addr.sin_addr.s_addr).to_be_bytes()))
+        },
+        libc::AF_INET6 => {
+            let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+            Some(IpAddr::from(addr.sin6_addr.s6_addr))
+        },
+        _ => None,
+    }
+}
+
+pub async fn execute_ping(target: String, address: IpAddr, opts: &PingOptions, clock: RunClock, sender: mpsc::Sender<PingEvent>) -> Result<PingReport, io::Error> {
+    let opts = opts.clone();
+    let timeout = opts.reply_timeout();
+    let prober = Prober::open(address, opts.size)?;
+    bind_source(&prober.socket, &target, &opts)?;
+    set_marking(&prober.socket, address.is_ipv6(), &opts)?;
+    tokio::task::spawn_blocking(move || {
+        let _ = sender.blocking_send(PingEvent::new(&target, EventKind::Started { address }));
+        let mut pings = Vec::new();
+        let mut outstanding = HashMap::new();
+        // Answered probes, to recognize duplicate replies.
+        let mut answered = HashMap::new();
+        let mut sent = 0;
+        let mut received = 0;
+        let mut next_send = Instant::now();
+        let mut last_send = next_send;
+        // Adaptive runs keep this many probes out, sending one whenever a reply comes in.
+        let window = opts.preload.max(1) as usize;
+        loop {
+            // Like ping when interrupted, probes still out count as lost.
+            if opts.unlimited() && !opts.more_probes(sent) {
+                break;
+            }
+            // Nobody waits for the report any more, as when the target was
+            // given up on as stalled.
+            if sender.is_closed() {
+                break;
+            }
+            let now = Instant::now();
+            let due = now >= next_send;
+            let preloading = sent < opts.preload;
+            let adapting = opts.adaptive && outstanding.len() < window && now >= last_send + opts.adaptive_gap();
+            if opts.more_probes(sent) && (due || preloading || adapting) {
+                sent += 1;
+                prober.send(sent as u16)?;
+                outstanding.insert(sent as u16, (sent, now));
+                last_send = now;
+                // Unanswered adaptive probes are followed up after the interval at the latest.
+                if opts.adaptive {
+                    next_send = now + opts.next_interval();
+                } else if due {
+                    next_send += opts.next_interval();
+                }
+            }
+            // Replies later than the per-packet timeout count as lost.
+            outstanding.retain(|_, (icmp_seq, sent_at)| {
+                let waiting = now.duration_since(*sent_at) < timeout;
+                if !waiting {
+                    let _ = sender.blocking_send(PingEvent::new(&target, EventKind::Lost { icmp_seq: *icmp_seq }));
+                }
+                waiting
+            });
+            answered.retain(|_, (_, sent_at)| now.duration_since(*sent_at) < timeout);
+            if !opts.more_probes(sent) && outstanding.is_empty() {
+                break;
+            }
+            let wait_until = if opts.more_probes(sent) && sent < opts.preload {
+                now
+            } else if opts.more_probes(sent) && opts.adaptive && outstanding.len() < window {
+                next_send.min(last_send + opts.adaptive_gap())
+            } else if opts.more_probes(sent) {
+                next_send
+            } else {
+                outstanding.values().map(|(_, sent_at)| *sent_at + timeout).min().unwrap_or(now)
+            };
+            let Some(reply) = prober.receive(wait_until)? else {
+                continue;
+            };
+            let (icmp_seq, sent_at, duplicate) = match outstanding.remove(&reply.seq) {
+                Some((icmp_seq, sent_at)) => {
+                    answered.insert(reply.seq, (icmp_seq, sent_at));
+                    (icmp_seq, sent_at, false)
+                },
+                None => match answered.get(&reply.seq) {
+                    Some(&(icmp_seq, sent_at)) => (icmp_seq, sent_at, true),
+                    None => continue,
+                },
+            };
+            let time = round_ms(reply.received_at.duration_since(sent_at).as_secs_f32() * 1000.0);
+            let mut ping = PingInfo {
+                bytes_sent: reply.bytes as u32,
+                icmp_seq,
+                ttl: reply.ttl,
+                time,
+                duplicate,
+                ..Default::default()
+            };
+            clock.stamp(&mut ping);
+            let _ = sender.blocking_send(PingEvent::new(&target, EventKind::Reply(ping.clone())));
+            keep(&mut pings, ping, opts.unlimited());
+            received += !duplicate as u32;
+        }
+        let packets = packet_statistics(sent, received);
+        let trips = round_trip_statistics(&pings);
+        Ok(PingReport {
+            destination: target,
+            pings,
+            packets: Some(packets),
+            trips,
+            timed_out: false,
+            ..Default::default()
+        })
+    })
+    .await?
+}
+
+fn is_too_big(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::EMSGSIZE)
+}
+
+// Sends one probe of `size` payload bytes and waits for its reply.
+fn probe_size(prober: &Prober, seq: u16, size: usize, timeout: Duration) -> io::Result<Option<(Reply, Instant)>> {
+    let sent_at = Instant::now();
+    match prober.send_sized(seq, size) {
+        Err(err) if is_too_big(&err) => return Ok(None),
+        result => result?,
+    }
+    loop {
+        match prober.receive(sent_at + timeout) {
+            Ok(Some(reply)) if reply.seq == seq => return Ok(Some((reply, sent_at))),
+            Ok(Some(_)) => continue,
+            Ok(None) => return Ok(None),
+            Err(err) if is_too_big(&err) => return Ok(None),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Binary-searches the largest payload that reaches `target` with the
+/// don't-fragment bit set, and reports it as the path MTU. Every probe
+/// that gets an answer is kept as a ping. The probes too large to get
+/// through are lost by design, so they count towards no loss.
+pub async fn discover_mtu(target: String, address: IpAddr, opts: &PingOptions, clock: RunClock, sender: mpsc::Sender<PingEvent>) -> Result<PingReport, io::Error> {
+    let timeout = opts.reply_timeout();
+    let (max, ip_header_size) = if address.is_ipv6() { (MAX_PAYLOAD_V6, 40) } else { (MAX_PAYLOAD_V4, 20) };
+    let prober = Prober::open(address, max)?;
+    bind_source(&prober.socket, &target, opts)?;
+    set_marking(&prober.socket, address.is_ipv6(), opts)?;
+    set_dont_fragment(&prober.socket, address.is_ipv6())?;
+    tokio::task::spawn_blocking(move || {
+        let _ = sender.blocking_send(PingEvent::new(&target, EventKind::Started { address }));
+        let mut pings = Vec::new();
+        let mut sent = 0;
+        let mut largest = None;
+        let (mut low, mut high) = (0, max);
+        while low <= high {
+            let size = (low + high) / 2;
+            sent += 1;
+            match probe_size(&prober, sent as u16, size, timeout)? {
+                Some((reply, sent_at)) => {
+                    let time = round_ms(reply.received_at.duration_since(sent_at).as_secs_f32() * 1000.0);
+                    let mut ping = PingInfo {
+                        bytes_sent: reply.bytes as u32,
+                        icmp_seq: sent,
+                        ttl: reply.ttl,
+                        time,
+                        ..Default::default()
+                    };
+                    clock.stamp(&mut ping);
+                    let _ = sender.blocking_send(PingEvent::new(&target, EventKind::Reply(ping.clone())));
+                    pings.push(ping);
+                    largest = Some(size);
+                    low = size + 1;
+                },
+                None => {
+                    if size == 0 {
+                        break;
+                    }
+                    high = size - 1;
+                },
+            }
+        }
+        let mtu = largest.map(|size| (size + ICMP_HEADER_SIZE + ip_header_size) as u32);
+        let trips = round_trip_statistics(&pings);
+        Ok(PingReport {
+            destination: target,
+            pings,
+            trips,
+            timed_out: false,
+            mtu,
+            ..Default::default()
+        })
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        // The example of RFC 1071.
+        assert_eq!(checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]), 0x220d);
+        // An odd byte counts as the high half of a word.
+        assert_eq!(checksum(&[0x01]), !0x0100);
+        assert_eq!(checksum(&[]), 0xffff);
+    }
+
+    #[test]
+    fn packets_with_their_checksum_add_up() {
+        let mut packet = vec![ICMP_ECHO_REQUEST, 0, 0, 0, 0x12, 0x34, 0, 1, 0, 1, 2, 3, 4];
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+        assert_eq!(checksum(&packet), 0);
+    }
+}
@@ -0,0 +1,678 @@
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, DataType, DoubleType, FloatType, Int32Type, Int64Type};
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::parser::parse_message_type;
+use prettytable::{format, Cell, Row, Table};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use crate::compare::Change;
+use crate::db::History;
+use crate::groups::{FleetSummary, GroupSummary};
+use crate::{junit, nagios};
+use crate::report::{self, PingErrorKind, PingInfo, PingReport, SequenceStatistics};
+use crate::run::RunInfo;
+use crate::thresholds::Thresholds;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    /// The same as `Json`, in YAML.
+    Yaml,
+    Csv,
+    /// A Nagios plugin status line with perfdata.
+    Nagios,
+    /// A JUnit XML report with a test case per target.
+    Junit,
+    /// The rows of `Csv`, as a Parquet file.
+    Parquet,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "yaml" => Ok(Format::Yaml),
+            "csv" => Ok(Format::Csv),
+            "nagios" => Ok(Format::Nagios),
+            "junit" => Ok(Format::Junit),
+            "parquet" => Ok(Format::Parquet),
+            _ => Err(format!("unknown format '{}' (expected text, json, yaml, csv, nagios, junit, or parquet)", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Format {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Report order; everything but `Name` puts the worst targets first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    Name,
+    Loss,
+    Avg,
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(SortBy::Name),
+            "loss" => Ok(SortBy::Loss),
+            "avg" => Ok(SortBy::Avg),
+            _ => Err(format!("unknown sort key '{}' (expected name, loss, or avg)", s)),
+        }
+    }
+}
+
+fn loss(report: &PingReport) -> f32 {
+    report.packets.as_ref().map_or(100.0, |packets| packets.loss_percent)
+}
+
+// Targets without any reply sort as the slowest.
+fn avg(report: &PingReport) -> f32 {
+    report.trips.as_ref().map_or(f32::INFINITY, |trips| trips.avg)
+}
+
+pub fn sort_reports(reports: &mut [PingReport], key: SortBy) {
+    match key {
+        SortBy::Name => reports.sort_by(|a, b| a.destination.cmp(&b.destination)),
+        SortBy::Loss => reports.sort_by(|a, b| loss(b).total_cmp(&loss(a)).then_with(|| avg(b).total_cmp(&avg(a)))),
+        SortBy::Avg => reports.sort_by(|a, b| avg(b).total_cmp(&avg(a)).then_with(|| loss(b).total_cmp(&loss(a)))),
+    }
+}
+
+/// The `count` fastest targets by average round trip, fastest first, or
+/// with `slowest` the `count` slowest, slowest first. Targets without any
+/// reply are the slowest.
+pub fn rank(reports: &[PingReport], count: usize, slowest: bool) -> Vec<PingReport> {
+    let mut ranked = reports.to_vec();
+    sort_reports(&mut ranked, SortBy::Avg);
+    if !slowest {
+        ranked.reverse();
+    }
+    ranked.truncate(count);
+    ranked
+}
+
+pub fn write_results<W: Write>(format: Format, results: &[PingReport], writer: &mut W) -> io::Result<()> {
+    match format {
+        Format::Text => write_text(results, writer),
+        Format::Json => write_json(results, writer),
+        Format::Yaml => write_yaml(results, writer),
+        Format::Csv => write_csv(results, writer),
+        Format::Nagios => writeln!(writer, "{}", nagios::check(results, nagios::WARNING, nagios::CRITICAL).1),
+        Format::Junit => junit::write(results, &Thresholds::default(), writer),
+        Format::Parquet => write_parquet(results, None, writer),
+    }
+}
+
+/// Values at or above `warn` are shown in yellow, and at or above `crit` in red.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub warn: f32,
+    pub crit: f32,
+}
+
+impl FromStr for Levels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (warn, crit) = s.split_once(',').ok_or_else(|| format!("expected WARN,CRIT levels, found '{}'", s))?;
+        let parse = |value: &str| value.trim().parse::<f32>().map_err(|_| format!("invalid level '{}'", value));
+        Ok(Levels { warn: parse(warn)?, crit: parse(crit)? })
+    }
+}
+
+/// Levels for coloring the loss (percent) and round trip (milliseconds) cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Colors {
+    pub loss: Levels,
+    pub rtt: Levels,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            loss: Levels { warn: 1.0, crit: 10.0 },
+            rtt: Levels { warn: 100.0, crit: 250.0 },
+        }
+    }
+}
+
+fn number_cell<T: ToString>(value: Option<T>) -> Cell {
+    Cell::new(&value.map_or("-".to_string(), |value| value.to_string())).style_spec("r")
+}
+
+fn colored_cell(text: Option<String>, value: f32, levels: Option<Levels>) -> Cell {
+    let cell = number_cell(text);
+    match levels {
+        Some(levels) if value >= levels.crit => cell.style_spec("rFr"),
+        Some(levels) if value >= levels.warn => cell.style_spec("rFy"),
+        _ => cell,
+    }
+}
+
+/// Writes a summary table with one row per target.
+pub fn write_text<W: Write>(results: &[PingReport], writer: &mut W) -> io::Result<()> {
+    write_table(results, None, writer)
+}
+
+/// Like `write_text`, coloring the loss and latency cells past `colors`
+/// when `writer` is a terminal that supports it.
+pub fn write_table<W: Write>(results: &[PingReport], colors: Option<&Colors>, writer: &mut W) -> io::Result<()> {
+    let loss_levels = colors.map(|colors| colors.loss);
+    let rtt_levels = colors.map(|colors| colors.rtt);
+    let rtt_cell = |time: Option<f32>| colored_cell(time.map(|time| time.to_string()), time.unwrap_or(0.0), rtt_levels);
+    let with_mtu = results.iter().any(|item| item.mtu.is_some());
+    let with_hops = results.iter().any(|item| item.hops.is_some());
+    // Addresses only add information for targets given by name.
+    let with_address = results
+        .iter()
+        .any(|item| item.address.is_some_and(|address| address.to_string() != item.destination));
+    let with_hostname = results.iter().any(|item| item.hostname.is_some());
+    let with_source = results.iter().any(|item| item.source.is_some());
+    // Probes are only worth a column when the targets were not all probed alike.
+    let with_probe = results.iter().any(|item| item.probe.is_some() && item.probe != results[0].probe);
+    let with_mac = results.iter().any(|item| item.mac.is_some());
+    let with_expiry = results.iter().any(|item| item.cert_expiry_days.is_some());
+    let with_flaps = results.iter().any(|item| item.flaps.is_some());
+    let with_errors = results.iter().any(|item| !item.errors.is_empty());
+    let with_sequence = results.iter().any(|item| item.sequence.as_ref().is_some_and(|sequence| !sequence.is_clean()));
+    let mut table = Table::new();
+    let mut titles = vec!["Target"];
+    if with_address {
+        titles.push("Address");
+    }
+    if with_hostname {
+        titles.push("Hostname");
+    }
+    if with_probe {
+        titles.push("Probe");
+    }
+    if with_source {
+        titles.push("Source");
+    }
+    if with_mac {
+        titles.push("MAC");
+    }
+    titles.extend(["Sent", "Recv", "Loss", "Min", "Avg", "Max", "P95", "EWMA"]);
+    if with_hops {
+        titles.push("Hops");
+    }
+    if with_expiry {
+        titles.push("Cert days");
+    }
+    if with_flaps {
+        titles.push("Flaps");
+    }
+    if with_errors {
+        titles.push("Errors");
+    }
+    if with_sequence {
+        titles.push("Sequence");
+    }
+    if with_mtu {
+        titles.push("MTU");
+    }
+    table.set_titles(Row::new(titles.into_iter().map(Cell::new).collect()));
+    for item in results {
+        let destination = if item.timed_out {
+            format!("{} (timed out)", item.destination)
+        } else {
+            item.destination.clone()
+        };
+        let packets = item.packets.as_ref();
+        let trips = item.trips.as_ref();
+        let mut cells = vec![Cell::new(&destination)];
+        if with_address {
+            cells.push(Cell::new(&item.address.map_or("-".to_string(), |address| address.to_string())));
+        }
+        if with_hostname {
+            cells.push(Cell::new(item.hostname.as_deref().unwrap_or("-")));
+        }
+        if with_probe {
+            cells.push(Cell::new(item.probe.as_deref().unwrap_or("-")));
+        }
+        if with_source {
+            cells.push(Cell::new(item.source.as_deref().unwrap_or("-")));
+        }
+        if with_mac {
+            cells.push(Cell::new(item.mac.as_deref().unwrap_or("-")));
+        }
+        cells.extend([
+            number_cell(packets.map(|packets| packets.transmitted)),
+            number_cell(packets.map(|packets| packets.received)),
+            colored_cell(packets.map(|packets| format!("{}%", packets.loss_percent)), loss(item), loss_levels),
+            rtt_cell(trips.map(|trips| trips.min)),
+            rtt_cell(trips.map(|trips| trips.avg)),
+            rtt_cell(trips.map(|trips| trips.max)),
+            rtt_cell(item.samples.as_ref().map(|samples| samples.p95)),
+            ewma_cell(item),
+        ]);
+        if with_hops {
+            cells.push(number_cell(item.hops));
+        }
+        if with_expiry {
+            cells.push(number_cell(item.cert_expiry_days));
+        }
+        if with_flaps {
+            cells.push(number_cell(item.flaps));
+        }
+        if with_errors {
+            cells.push(Cell::new(&error_summary(item)));
+        }
+        if with_sequence {
+            cells.push(Cell::new(&item.sequence.as_ref().map_or("-".to_string(), sequence_summary)));
+        }
+        if with_mtu {
+            cells.push(number_cell(item.mtu));
+        }
+        table.add_row(Row::new(cells));
+    }
+    print_table(table, colors.is_some(), writer)
+}
+
+// Counts of the ICMP errors of a target, e.g. `3 unreachable`.
+fn error_summary(report: &PingReport) -> String {
+    let counts = [
+        (report.error_count(PingErrorKind::Unreachable), "unreachable"),
+        (report.error_count(PingErrorKind::TimeExceeded), "time exceeded"),
+    ];
+    let parts: Vec<String> = counts.iter().filter(|(count, _)| *count > 0).map(|(count, kind)| format!("{} {}", count, kind)).collect();
+    if parts.is_empty() { "-".to_string() } else { parts.join(", ") }
+}
+
+// Sequence problems of a target, e.g. `2 duplicates, 1 reordered`.
+fn sequence_summary(sequence: &SequenceStatistics) -> String {
+    let counts = [(sequence.gaps, "gaps"), (sequence.duplicates, "duplicates"), (sequence.reordered, "reordered")];
+    let parts: Vec<String> = counts.iter().filter(|(count, _)| *count > 0).map(|(count, kind)| format!("{} {}", count, kind)).collect();
+    if parts.is_empty() { "-".to_string() } else { parts.join(", ") }
+}
+
+// The moving average, with an arrow for where it is heading.
+fn ewma_cell(item: &PingReport) -> Cell {
+    let text = item.samples.as_ref().map_or("-".to_string(), |samples| format!("{} {}", samples.ewma, samples.trend.arrow()));
+    Cell::new(&text).style_spec("r")
+}
+
+/// Every target pinged through each of several interfaces, or from each of
+/// the sites of a collector.
+pub struct Matrix {
+    /// The interfaces, or the sites, that head the columns.
+    pub interfaces: Vec<String>,
+    pub rows: Vec<MatrixRow>,
+}
+
+/// A target's reports from each interface, in the order of the interfaces.
+pub struct MatrixRow {
+    pub target: String,
+    pub reports: Vec<PingReport>,
+}
+
+// The interface with the least loss, then the fastest replies. None of them
+// is best when no replies came back at all.
+fn best_interface<'a>(interfaces: &'a [String], reports: &[PingReport]) -> Option<&'a str> {
+    interfaces
+        .iter()
+        .zip(reports)
+        .filter(|(_, report)| report.error.is_none() && loss(report) < 100.0)
+        .min_by(|(_, a), (_, b)| loss(a).total_cmp(&loss(b)).then_with(|| avg(a).total_cmp(&avg(b))))
+        .map(|(interface, _)| interface.as_str())
+}
+
+/// Writes a row per target with its loss and average round trip through
+/// each interface, and the interface that did best, colored like
+/// `write_table`.
+pub fn write_matrix<W: Write>(matrix: &Matrix, colors: Option<&Colors>, writer: &mut W) -> io::Result<()> {
+    let interfaces = &matrix.interfaces;
+    let loss_levels = colors.map(|colors| colors.loss);
+    let rtt_levels = colors.map(|colors| colors.rtt);
+    let mut titles = vec!["Target".to_string()];
+    for interface in interfaces {
+        titles.push(format!("{} Loss", interface));
+        titles.push(format!("{} Avg", interface));
+    }
+    titles.push("Best".to_string());
+    let mut table = Table::new();
+    table.set_titles(Row::new(titles.iter().map(|title| Cell::new(title)).collect()));
+    for row in &matrix.rows {
+        let mut cells = vec![Cell::new(&row.target)];
+        for report in &row.reports {
+            if report.error.is_some() {
+                cells.extend([number_cell(Some("failed")), number_cell(None::<f32>)]);
+                continue;
+            }
+            let avg = report.trips.as_ref().map(|trips| trips.avg);
+            cells.push(colored_cell(report.packets.as_ref().map(|packets| format!("{}%", packets.loss_percent)), loss(report), loss_levels));
+            cells.push(colored_cell(avg.map(|avg| avg.to_string()), avg.unwrap_or(0.0), rtt_levels));
+        }
+        cells.push(Cell::new(best_interface(interfaces, &row.reports).unwrap_or("-")));
+        table.add_row(Row::new(cells));
+    }
+    print_table(table, colors.is_some(), writer)
+}
+
+/// Writes the path to every target that was traced, one table per target.
+pub fn write_traces<W: Write>(results: &[PingReport], writer: &mut W) -> io::Result<()> {
+    for item in results {
+        let Some(hops) = &item.trace else {
+            continue;
+        };
+        writeln!(writer, "\nPath to {}:", item.destination)?;
+        let mut table = Table::new();
+        table.set_titles(Row::new(["Hop", "Host", "Sent", "Loss", "Best", "Avg", "Worst"].into_iter().map(Cell::new).collect()));
+        for hop in hops {
+            table.add_row(Row::new(vec![
+                number_cell(Some(hop.hop)),
+                Cell::new(hop.host.as_deref().unwrap_or("???")),
+                number_cell(Some(hop.sent)),
+                number_cell(Some(format!("{}%", hop.loss_percent))),
+                number_cell(hop.best),
+                number_cell(hop.avg),
+                number_cell(hop.worst),
+            ]));
+        }
+        print_table(table, false, writer)?;
+    }
+    Ok(())
+}
+
+/// Writes a table with one row per group, colored like `write_table`.
+pub fn write_groups<W: Write>(groups: &[GroupSummary], colors: Option<&Colors>, writer: &mut W) -> io::Result<()> {
+    let loss_levels = colors.map(|colors| colors.loss);
+    let rtt_levels = colors.map(|colors| colors.rtt);
+    let mut table = Table::new();
+    table.set_titles(Row::new(["Group", "Targets", "Up", "Available", "Worst loss", "Mean avg"].into_iter().map(Cell::new).collect()));
+    for group in groups {
+        table.add_row(Row::new(vec![
+            Cell::new(&group.group),
+            number_cell(Some(group.targets)),
+            number_cell(Some(group.reachable)),
+            number_cell(Some(format!("{}%", group.availability))),
+            colored_cell(Some(format!("{}%", group.worst_loss)), group.worst_loss, loss_levels),
+            colored_cell(group.mean_avg.map(|avg| avg.to_string()), group.mean_avg.unwrap_or(0.0), rtt_levels),
+        ]));
+    }
+    print_table(table, colors.is_some(), writer)
+}
+
+/// Writes a line with the aggregates of the whole run.
+pub fn write_fleet<W: Write>(fleet: &FleetSummary, writer: &mut W) -> io::Result<()> {
+    let failed = if fleet.failed > 0 { format!(" ({} failed)", fleet.failed) } else { String::new() };
+    let mut parts = vec![format!("{}% loss overall ({}/{} received)", fleet.loss_percent, fleet.received, fleet.transmitted)];
+    if let Some(avg) = fleet.mean_avg {
+        parts.push(format!("mean avg {} ms", avg));
+    }
+    if let (Some((fastest, low)), Some((slowest, high))) = (&fleet.fastest, &fleet.slowest) {
+        parts.push(format!("fastest {} ({} ms), slowest {} ({} ms)", fastest, low, slowest, high));
+    }
+    writeln!(writer, "{} targets{}: {}", fleet.targets, failed, parts.join(", "))
+}
+
+fn print_table<W: Write>(mut table: Table, colored: bool, writer: &mut W) -> io::Result<()> {
+    table.set_format(format::FormatBuilder::new().column_separator(' ').padding(0, 1).build());
+    match colored.then(|| term::TerminfoTerminal::new(&mut *writer)).flatten() {
+        Some(mut terminal) => table.print_term(&mut terminal)?,
+        None => table.print(writer)?,
+    };
+    Ok(())
+}
+
+pub fn write_json<W: Write>(results: &[PingReport], writer: &mut W) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *writer, results)?;
+    writeln!(writer)
+}
+
+/// What the JSON report of runs with target groups, or with the details of
+/// the run, wraps the reports in: `{"run": {...}, "targets": [...], "groups":
+/// [...]}`.
+#[derive(Serialize)]
+pub struct Envelope<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<&'a RunInfo>,
+    pub targets: &'a [PingReport],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub groups: &'a [GroupSummary],
+}
+
+pub fn write_grouped_json<W: Write>(envelope: &Envelope<'_>, writer: &mut W) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *writer, envelope)?;
+    writeln!(writer)
+}
+
+pub fn write_yaml<W: Write, T: Serialize + ?Sized>(value: &T, writer: &mut W) -> io::Result<()> {
+    serde_yaml_ng::to_writer(writer, value).map_err(io::Error::other)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The first line of `write_csv`.
+pub const CSV_HEADER: &str = "target,icmp_seq,ttl,bytes,rtt_ms,timestamp,offset,status";
+
+/// The row of `write_csv` for a reply from `target`.
+pub fn csv_row(target: &str, ping: &PingInfo) -> String {
+    let status = ping.status.map_or(String::new(), |status| status.to_string());
+    format!("{},{},{},{},{},{},{:.6},{}", csv_field(target), ping.icmp_seq, ping.ttl, ping.bytes_sent, ping.time, ping.timestamp, ping.offset, status)
+}
+
+/// Writes one row per reply received.
+pub fn write_csv<W: Write>(results: &[PingReport], writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for item in results {
+        for ping in &item.pings {
+            writeln!(writer, "{}", csv_row(&item.destination, ping))?;
+        }
+    }
+    Ok(())
+}
+
+const SAMPLE_SCHEMA: &str = "
+    message sample {
+        required binary target (STRING);
+        required int32 icmp_seq (INTEGER(32, false));
+        required int32 ttl (INTEGER(32, false));
+        required int32 bytes (INTEGER(32, false));
+        required float rtt_ms;
+        optional int64 timestamp (TIMESTAMP(MICROS, true));
+        required double offset;
+        optional int32 status (INTEGER(16, false));
+    }
+";
+
+/// Writes the rows of `write_csv` as a Parquet file, with the timestamps as
+/// such, for DuckDB, Spark and the like. The details of the `run` go in the
+/// file's metadata, as JSON under `multiping.run`.
+pub fn write_parquet<W: Write>(results: &[PingReport], run: Option<&RunInfo>, writer: &mut W) -> io::Result<()> {
+    let samples: Vec<(&str, &PingInfo)> = results.iter().flat_map(|item| item.pings.iter().map(|ping| (item.destination.as_str(), ping))).collect();
+    let schema = Arc::new(parse_message_type(SAMPLE_SCHEMA)?);
+    let metadata = run.map(|run| serde_json::to_string(run).map(|run| vec![KeyValue::new("multiping.run".to_string(), run)])).transpose()?;
+    let properties = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).set_key_value_metadata(metadata).build());
+    // Put together in memory, as the file writer wants to own its sink.
+    let mut file = SerializedFileWriter::new(Vec::new(), schema, properties)?;
+    let mut group = file.next_row_group()?;
+    let column = |f: fn(&PingInfo) -> u32| samples.iter().map(|(_, ping)| Some(f(ping) as i32)).collect::<Vec<_>>();
+    write_column::<ByteArrayType>(&mut group, samples.iter().map(|(target, _)| Some(ByteArray::from(target.as_bytes().to_vec()))).collect())?;
+    write_column::<Int32Type>(&mut group, column(|ping| ping.icmp_seq))?;
+    write_column::<Int32Type>(&mut group, column(|ping| ping.ttl))?;
+    write_column::<Int32Type>(&mut group, column(|ping| ping.bytes_sent))?;
+    write_column::<FloatType>(&mut group, samples.iter().map(|(_, ping)| Some(ping.time)).collect())?;
+    write_column::<Int64Type>(&mut group, samples.iter().map(|(_, ping)| report::unix_nanos(&ping.timestamp).map(|nanos| (nanos / 1000) as i64)).collect())?;
+    write_column::<DoubleType>(&mut group, samples.iter().map(|(_, ping)| Some(ping.offset)).collect())?;
+    write_column::<Int32Type>(&mut group, samples.iter().map(|(_, ping)| ping.status.map(i32::from)).collect())?;
+    group.close()?;
+    writer.write_all(&file.into_inner()?)
+}
+
+// Writes the next column of `group`, where None is a null.
+fn write_column<T: DataType>(group: &mut SerializedRowGroupWriter<'_, Vec<u8>>, values: Vec<Option<T::T>>) -> io::Result<()> {
+    let mut column = group.next_column()?.expect("a column for every field of the schema");
+    let levels: Vec<i16> = values.iter().map(|value| i16::from(value.is_some())).collect();
+    let values: Vec<T::T> = values.into_iter().flatten().collect();
+    column.typed::<T>().write_batch(&values, Some(&levels), None)?;
+    column.close()?;
+    Ok(())
+}
+
+// Availability shows the nines that matter, as in 99.95%.
+fn round_percent(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
+
+// A duration in its two largest units, as in 2h 5m or 45s.
+fn format_seconds(seconds: u64) -> String {
+    let units = [(86400, "d"), (3600, "h"), (60, "m"), (1, "s")];
+    let parts: Vec<String> = units
+        .iter()
+        .scan(seconds, |left, &(size, unit)| {
+            let amount = *left / size;
+            *left %= size;
+            Some((amount, unit))
+        })
+        .skip_while(|(amount, _)| *amount == 0)
+        .take(2)
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, unit)| format!("{}{}", amount, unit))
+        .collect();
+    if parts.is_empty() { "0s".to_string() } else { parts.join(" ") }
+}
+
+/// Writes the statistics of past runs, one row or object per target.
+pub fn write_history<W: Write>(format: Format, histories: &[History], writer: &mut W) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            let mut table = Table::new();
+            let titles = [
+                "Target", "Runs", "Errors", "Sent", "Recv", "Loss", "Min", "Avg", "Max", "P95", "Avail", "Outages", "MTTR", "Longest",
+                "First run", "Last run",
+            ];
+            table.set_titles(Row::new(titles.into_iter().map(Cell::new).collect()));
+            for history in histories {
+                table.add_row(Row::new(vec![
+                    Cell::new(&history.target),
+                    number_cell(Some(history.runs)),
+                    number_cell(Some(history.errors)),
+                    number_cell(Some(history.sent)),
+                    number_cell(Some(history.received)),
+                    number_cell(Some(format!("{}%", history.loss_percent))),
+                    number_cell(history.min),
+                    number_cell(history.avg),
+                    number_cell(history.max),
+                    number_cell(history.p95),
+                    number_cell(Some(format!("{}%", round_percent(history.availability)))),
+                    number_cell(Some(history.outages)),
+                    number_cell(history.mttr.map(format_seconds)),
+                    number_cell(history.longest_outage.map(format_seconds)),
+                    Cell::new(&history.first),
+                    Cell::new(&history.last),
+                ]));
+            }
+            print_table(table, false, writer)
+        },
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *writer, histories)?;
+            writeln!(writer)
+        },
+        Format::Yaml => write_yaml(histories, writer),
+        Format::Csv => {
+            writeln!(writer, "target,runs,errors,sent,received,loss_percent,min,avg,max,p95,availability,outages,mttr,longest_outage,first,last")?;
+            let optional = |value: Option<f32>| value.map_or(String::new(), |value| value.to_string());
+            let seconds = |value: Option<u64>| value.map_or(String::new(), |value| value.to_string());
+            for history in histories {
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                    csv_field(&history.target), history.runs, history.errors, history.sent, history.received, history.loss_percent,
+                    optional(history.min), optional(history.avg), optional(history.max), optional(history.p95),
+                    history.availability, history.outages, seconds(history.mttr), seconds(history.longest_outage), history.first, history.last
+                )?;
+            }
+            Ok(())
+        },
+        Format::Nagios | Format::Junit | Format::Parquet => Err(unsupported(format)),
+    }
+}
+
+// Plugin output, test reports and samples only make sense for the results
+// of a run.
+fn unsupported(format: Format) -> io::Error {
+    let name = format!("{:?}", format).to_lowercase();
+    io::Error::new(io::ErrorKind::InvalidInput, format!("the {} format only works for ping runs", name))
+}
+
+/// Writes how every metric changed between two runs.
+pub fn write_changes<W: Write>(format: Format, changes: &[Change], writer: &mut W) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            let mut table = Table::new();
+            let titles = ["Target", "Metric", "Baseline", "Current", "Change", ""];
+            table.set_titles(Row::new(titles.into_iter().map(Cell::new).collect()));
+            for change in changes {
+                let unit = if change.metric == "loss" { " pt" } else { "%" };
+                table.add_row(Row::new(vec![
+                    Cell::new(&change.target),
+                    Cell::new(change.metric),
+                    number_cell(Some(change.baseline)),
+                    number_cell(Some(change.current)),
+                    number_cell(Some(format!("{:+}{}", change.change, unit))),
+                    Cell::new(if change.regression { "regression" } else { "" }),
+                ]));
+            }
+            print_table(table, false, writer)
+        },
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *writer, changes)?;
+            writeln!(writer)
+        },
+        Format::Yaml => write_yaml(changes, writer),
+        Format::Csv => {
+            writeln!(writer, "target,metric,baseline,current,change,regression")?;
+            for change in changes {
+                writeln!(writer, "{},{},{},{},{},{}", csv_field(&change.target), change.metric, change.baseline, change.current, change.change, change.regression)?;
+            }
+            Ok(())
+        },
+        Format::Nagios | Format::Junit | Format::Parquet => Err(unsupported(format)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        assert_eq!("1,10".parse(), Ok(Levels { warn: 1.0, crit: 10.0 }));
+        assert_eq!(" 100 , 250.5".parse(), Ok(Levels { warn: 100.0, crit: 250.5 }));
+        assert_eq!("100".parse::<Levels>(), Err("expected WARN,CRIT levels, found '100'".to_string()));
+        assert_eq!("100,high".parse::<Levels>(), Err("invalid level 'high'".to_string()));
+    }
+
+    #[test]
+    fn cells_turn_yellow_then_red() {
+        let levels = Some(Levels { warn: 100.0, crit: 250.0 });
+        let cell = |value: f32| colored_cell(Some(value.to_string()), value, levels);
+        assert_eq!(cell(99.9), Cell::new("99.9").style_spec("r"));
+        assert_eq!(cell(100.0), Cell::new("100").style_spec("rFy"));
+        assert_eq!(cell(250.0), Cell::new("250").style_spec("rFr"));
+        assert_eq!(colored_cell(Some("300".to_string()), 300.0, None), Cell::new("300").style_spec("r"));
+    }
+}
@@ -0,0 +1,112 @@
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+use serde::{de, Deserialize, Deserializer};
+use crate::alert::Alert;
+use crate::http;
+
+const EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+/// How urgent the incidents PagerDuty opens are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Severity {
+    #[default]
+    Critical,
+    Error,
+    Warning,
+    Info,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "critical" => Ok(Severity::Critical),
+            "error" => Ok(Severity::Error),
+            "warning" => Ok(Severity::Warning),
+            "info" => Ok(Severity::Info),
+            _ => Err(format!("unknown severity '{}' (expected critical, error, warning, or info)", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Severity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Severity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Severity::Critical => "critical",
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        }
+    }
+}
+
+/// A PagerDuty service to open an incident on for every target that goes
+/// down, and to resolve it on when the target is back up.
+///
+/// ```toml
+/// [pagerduty]
+/// routing_key = "..."
+/// severity = "error"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PagerDuty {
+    /// The integration key of the service's Events API v2 integration.
+    pub routing_key: String,
+    #[serde(default)]
+    pub severity: Severity,
+    /// Where events go, for the EU service region or a proxy.
+    pub url: Option<String>,
+}
+
+impl PagerDuty {
+    /// Opens an incident for the target of `alert`.
+    pub async fn trigger(&self, alert: &Alert) -> io::Result<()> {
+        self.send(serde_json::json!({
+            "routing_key": self.routing_key,
+            "event_action": "trigger",
+            "dedup_key": dedup_key(&alert.target),
+            "payload": {
+                "summary": format!("{} is down: {}", alert.target, alert.reason),
+                "source": alert.target,
+                "severity": self.severity.as_str(),
+                "custom_details": alert,
+            },
+        }))
+        .await
+    }
+
+    /// Resolves the incident opened for `target`.
+    pub async fn resolve(&self, target: &str) -> io::Result<()> {
+        self.send(serde_json::json!({
+            "routing_key": self.routing_key,
+            "event_action": "resolve",
+            "dedup_key": dedup_key(target),
+        }))
+        .await
+    }
+
+    async fn send(&self, event: serde_json::Value) -> io::Result<()> {
+        let headers = [("Content-Type", "application/json")];
+        let url = self.url.as_deref().unwrap_or(EVENTS_URL);
+        let status = http::post(url, &headers, &event.to_string(), Duration::from_secs(10)).await?;
+        if !(200..300).contains(&status) {
+            return Err(io::Error::other(format!("PagerDuty answered with status {}", status)));
+        }
+        Ok(())
+    }
+}
+
+// Every event about a target goes to the same incident, so a target that
+// is down stays one incident however many times it is reported.
+fn dedup_key(target: &str) -> String {
+    format!("multiping/{}", target)
+}
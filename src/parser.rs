@@ -0,0 +1,549 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+use regex::{Captures, Regex};
+use crate::PingFlavor;
+use crate::report::{Hop, PacketStatistics, PingError, PingErrorKind, PingInfo, RoundTripStatistics};
+use crate::stats::round_ms;
+
+// Compiles the pattern the first time it is used, rather than on every line.
+macro_rules! regex {
+    ($pattern:literal) => {{
+        static REGEX: OnceLock<Regex> = OnceLock::new();
+        REGEX.get_or_init(|| Regex::new($pattern).unwrap())
+    }};
+}
+
+// Localized output may use a decimal comma.
+fn number<T: FromStr>(value: &str) -> Option<T> {
+    value.replace(',', ".").parse().ok()
+}
+
+/// What a line of ping output tells.
+#[derive(Debug)]
+pub(crate) enum PingLine {
+    Reply(PingInfo),
+    /// Probe `icmp_seq` got no answer in time.
+    Lost(u32),
+    Error(PingError),
+    Packets(PacketStatistics),
+    Trips(RoundTripStatistics),
+}
+
+/// Reads the output of one ping implementation, a line at a time. Probes
+/// are numbered from 1, as iputils does, whatever the implementation prints.
+pub(crate) trait PingParser: Send {
+    /// What `line` tells, or `None` for lines the parser has no use for.
+    fn parse(&mut self, line: &str) -> Option<PingLine>;
+}
+
+pub(crate) fn ping_parser(flavor: PingFlavor) -> Box<dyn PingParser> {
+    match flavor {
+        PingFlavor::Auto => Box::new(Detect { candidates: DETECTED.to_vec(), parser: None }),
+        PingFlavor::Iputils => Box::new(Iputils),
+        PingFlavor::Busybox => Box::new(Busybox),
+        PingFlavor::Bsd => Box::new(Bsd),
+        PingFlavor::Solaris => Box::new(Solaris),
+        PingFlavor::Windows => Box::new(Windows::default()),
+    }
+}
+
+fn first_match<'a>(patterns: &[&Regex], line: &'a str) -> Option<Captures<'a>> {
+    patterns.iter().find_map(|pattern| pattern.captures(line))
+}
+
+// A reply from the `bytes`, `icmp_seq`, `time`, and, where the pattern has
+// them, `ttl` and `duplicate` groups; `first` is the number of the first probe.
+fn reply(captures: &Captures, first: u32) -> Option<PingLine> {
+    Some(PingLine::Reply(PingInfo {
+        bytes_sent: captures["bytes"].parse().ok()?,
+        icmp_seq: (captures["icmp_seq"].parse::<u32>().ok()? + 1).checked_sub(first)?,
+        ttl: captures.name("ttl").map_or(Some(0), |ttl| ttl.as_str().parse().ok())?,
+        time: number(&captures["time"])?,
+        duplicate: captures.name("duplicate").is_some(),
+        ..Default::default()
+    }))
+}
+
+// Transmitted, received, and loss, in that order.
+fn packets(captures: &Captures) -> Option<PingLine> {
+    Some(PingLine::Packets(PacketStatistics {
+        transmitted: captures[1].parse().ok()?,
+        received: captures[2].parse().ok()?,
+        loss_percent: number(&captures[3])?,
+    }))
+}
+
+// Minimum, average, maximum, and standard deviation, in that order.
+fn trips(captures: &Captures) -> Option<PingLine> {
+    Some(PingLine::Trips(RoundTripStatistics {
+        min: number(&captures[1])?,
+        avg: number(&captures[2])?,
+        max: number(&captures[3])?,
+        stddev: number(&captures[4])?,
+    }))
+}
+
+fn error_kind(message: &str) -> Option<PingErrorKind> {
+    let message = message.to_lowercase();
+    if message.contains("unreachable") {
+        Some(PingErrorKind::Unreachable)
+    } else if message.contains("exceeded") || message.contains("expired") {
+        Some(PingErrorKind::TimeExceeded)
+    } else {
+        None
+    }
+}
+
+// ICMP errors as iputils (`From 10.0.0.1 icmp_seq=1 Destination Host
+// Unreachable`) and BSD ping (`92 bytes from 10.0.0.1: Time to live exceeded`)
+// print them.
+fn parse_error_line(line: &str) -> Option<PingLine> {
+    let re = regex!(r"^(?:From|\d+ bytes from) (?P<from>\S+?)(?: \(.*?\))?:?(?: icmp_seq=(?P<icmp_seq>\d+))? (?P<message>.+)$");
+    let captures = re.captures(line.trim())?;
+    let message = captures["message"].trim();
+    Some(PingLine::Error(PingError {
+        icmp_seq: captures.name("icmp_seq").and_then(|icmp_seq| icmp_seq.as_str().parse().ok()),
+        from: captures["from"].to_string(),
+        kind: error_kind(message)?,
+        message: message.to_string(),
+    }))
+}
+
+// The summaries of every ping but iputils and Windows:
+// `5 packets transmitted, 5 packets received, 0.0% packet loss`.
+fn parse_bsd_statistics(line: &str) -> Option<PingLine> {
+    let re = regex!(r"^(\d+) packets transmitted, (\d+) packets received,(?: \+\d+ \w+,)* ([\d.]+)% packet loss");
+    packets(&re.captures(line.trim())?)
+}
+
+/// Linux's ping: `64 bytes from 10.0.0.1: icmp_seq=1 ttl=64 time=0.05 ms`.
+/// The second pattern of each line is a fallback for translated output,
+/// which keeps the layout and the untranslated keys (icmp_seq, ttl) but not
+/// the words around them.
+struct Iputils;
+
+impl PingParser for Iputils {
+    fn parse(&mut self, line: &str) -> Option<PingLine> {
+        let replies = [
+            regex!(r"(?P<bytes>\d+) bytes from .*?[:,] icmp_seq=(?P<icmp_seq>\d+) (?:ttl|hlim)=(?P<ttl>\d+) time=(?P<time>[\d.]+) ms(?P<duplicate> \(DUP!\))?$"),
+            regex!(r"^(?P<bytes>\d+) .*? icmp_seq=(?P<icmp_seq>\d+) (?:ttl|hlim)=(?P<ttl>\d+) \w+=(?P<time>[\d.,]+) ?ms(?P<duplicate> \(DUP!\))?$"),
+        ];
+        if let Some(captures) = first_match(&replies, line) {
+            return reply(&captures, 1);
+        }
+        // What ping -O prints for a probe that got no reply in time.
+        if let Some(captures) = regex!(r"no answer yet for icmp_seq=(\d+)").captures(line) {
+            return Some(PingLine::Lost(captures[1].parse().ok()?));
+        }
+        if let Some(error) = parse_error_line(line) {
+            return Some(error);
+        }
+        let statistics = [
+            regex!(r"^(\d+) packets transmitted, (\d+) received,(?: \+\d+ \w+,)* ([\d.]+)% packet loss"),
+            regex!(r"^(\d+) [^,\d]+, (\d+) [^,\d]+, (?:\+\d+ [^,\d]+, )*([\d.,]+) ?% "),
+        ];
+        if let Some(captures) = first_match(&statistics, line) {
+            return packets(&captures);
+        }
+        let round_trips = [
+            regex!(r"^.*?min/avg/max/mdev = ([\d.]+)/([\d.]+)/([\d.]+)/([\d.]+) ms$"),
+            regex!(r"^.*?/.*? = ([\d.,]+)/([\d.,]+)/([\d.,]+)/([\d.,]+) ?ms$"),
+        ];
+        trips(&first_match(&round_trips, line)?)
+    }
+}
+
+/// BusyBox: `64 bytes from 10.0.0.1: seq=0 ttl=64 time=0.051 ms`. Its
+/// summary has no standard deviation, so the round trip statistics are left
+/// to the samples.
+struct Busybox;
+
+impl PingParser for Busybox {
+    fn parse(&mut self, line: &str) -> Option<PingLine> {
+        let re = regex!(r"^(?P<bytes>\d+) bytes from .*?: seq=(?P<icmp_seq>\d+) ttl=(?P<ttl>\d+) time=(?P<time>[\d.]+) ms(?P<duplicate> \(DUP!\))?$");
+        match re.captures(line.trim()) {
+            Some(captures) => reply(&captures, 0),
+            None => parse_bsd_statistics(line),
+        }
+    }
+}
+
+/// macOS and the BSDs: `64 bytes from 10.0.0.1: icmp_seq=0 ttl=64 time=0.05
+/// ms`, and `Request timeout for icmp_seq 0` for lost probes.
+struct Bsd;
+
+impl PingParser for Bsd {
+    fn parse(&mut self, line: &str) -> Option<PingLine> {
+        let line = line.trim();
+        let re = regex!(r"^(?P<bytes>\d+) bytes from .*?[:,] icmp_seq=(?P<icmp_seq>\d+) (?:ttl|hlim)=(?P<ttl>\d+) time=(?P<time>[\d.]+) ms(?P<duplicate> \(DUP!\))?$");
+        if let Some(captures) = re.captures(line) {
+            return reply(&captures, 0);
+        }
+        if let Some(captures) = regex!(r"^Request timeout for icmp_seq (\d+)$").captures(line) {
+            return Some(PingLine::Lost(captures[1].parse::<u32>().ok()? + 1));
+        }
+        if let Some(error) = parse_error_line(line) {
+            return Some(error);
+        }
+        if let Some(statistics) = parse_bsd_statistics(line) {
+            return Some(statistics);
+        }
+        let re = regex!(r"^round-trip min/avg/max/(?:stddev|std-dev) = ([\d.]+)/([\d.]+)/([\d.]+)/([\d.]+) ms$");
+        trips(&re.captures(line)?)
+    }
+}
+
+/// Solaris and illumos `ping -s`: `64 bytes from host (10.0.0.1):
+/// icmp_seq=0. time=0.560 ms`, without the TTL.
+struct Solaris;
+
+impl PingParser for Solaris {
+    fn parse(&mut self, line: &str) -> Option<PingLine> {
+        let line = line.trim();
+        let re = regex!(r"^(?P<bytes>\d+) bytes from .*?: icmp_seq=(?P<icmp_seq>\d+)\. time=(?P<time>[\d.]+) ms$");
+        if let Some(captures) = re.captures(line) {
+            return reply(&captures, 0);
+        }
+        if let Some(statistics) = parse_bsd_statistics(line) {
+            return Some(statistics);
+        }
+        let re = regex!(r"^round-trip \(ms\)\s+min/avg/max/stddev = ([\d.]+)/([\d.]+)/([\d.]+)/([\d.]+)$");
+        trips(&re.captures(line)?)
+    }
+}
+
+/// Windows: `Reply from 10.0.0.1: bytes=32 time=1ms TTL=57`. Replies carry
+/// no sequence number, so the parser numbers them, along with the errors
+/// and timeouts that take their place.
+#[derive(Default)]
+struct Windows {
+    probes: u32,
+}
+
+impl PingParser for Windows {
+    fn parse(&mut self, line: &str) -> Option<PingLine> {
+        let line = line.trim();
+        let replies = [
+            regex!(r"^Reply from (?P<source>.*?): bytes=(?P<bytes>\d+) time[=<](?P<time>[\d.]+)ms TTL=(?P<ttl>\d+)"),
+            regex!(r"^.*? ?: \w+=(?P<bytes>\d+) \w+[=<](?P<time>[\d.,]+) ?ms TTL=(?P<ttl>\d+)"),
+        ];
+        if let Some(captures) = first_match(&replies, line) {
+            let ping = PingInfo {
+                bytes_sent: captures["bytes"].parse().ok()?,
+                icmp_seq: self.probes + 1,
+                ttl: captures["ttl"].parse().ok()?,
+                time: number(&captures["time"])?,
+                ..Default::default()
+            };
+            self.probes += 1;
+            return Some(PingLine::Reply(ping));
+        }
+        if line == "Request timed out." {
+            self.probes += 1;
+            return Some(PingLine::Lost(self.probes));
+        }
+        // Errors come as replies: `Reply from 10.0.0.1: TTL expired in transit.`
+        if let Some(captures) = regex!(r"^Reply from (?P<from>\S+?): (?P<message>[^=]+?)\.?$").captures(line) {
+            let message = &captures["message"];
+            let error = PingError {
+                icmp_seq: Some(self.probes + 1),
+                from: captures["from"].to_string(),
+                kind: error_kind(message)?,
+                message: message.to_string(),
+            };
+            self.probes += 1;
+            return Some(PingLine::Error(error));
+        }
+        let statistics = [
+            regex!(r"Sent = (\d+), Received = (\d+), Lost = \d+ \((\d+)% loss\)"),
+            regex!(r"= (\d+), [^=,]+ = (\d+), [^=,]+ = \d+ \((\d+) ?% "),
+        ];
+        packets(&first_match(&statistics, line)?)
+    }
+}
+
+// The implementations tried, in order, on lines more than one understands.
+const DETECTED: [PingFlavor; 5] = [PingFlavor::Iputils, PingFlavor::Bsd, PingFlavor::Busybox, PingFlavor::Solaris, PingFlavor::Windows];
+
+// The implementations that print `line` as the header of their output.
+fn header_flavors(line: &str) -> Option<&'static [PingFlavor]> {
+    let headers: [(&Regex, &'static [PingFlavor]); 5] = [
+        (regex!(r"^PING .*? \d+\(\d+\) bytes of data\.$"), &[PingFlavor::Iputils]),
+        (regex!(r"^PING \S+\(\S+\) \d+ data bytes$"), &[PingFlavor::Iputils]),
+        (regex!(r"^PING \S+ \(\S+\): \d+ data bytes$"), &[PingFlavor::Bsd, PingFlavor::Busybox]),
+        (regex!(r"^PING \S+: \d+ data bytes$"), &[PingFlavor::Solaris]),
+        (regex!(r"^Pinging .* with \d+ bytes of data:$"), &[PingFlavor::Windows]),
+    ];
+    let line = line.trim();
+    headers.into_iter().find(|(pattern, _)| pattern.is_match(line)).map(|(_, flavors)| flavors)
+}
+
+/// Tells the implementation from its output: the header narrows it down,
+/// and the first line one of the remaining implementations understands
+/// settles it.
+struct Detect {
+    candidates: Vec<PingFlavor>,
+    parser: Option<Box<dyn PingParser>>,
+}
+
+impl PingParser for Detect {
+    fn parse(&mut self, line: &str) -> Option<PingLine> {
+        if let Some(parser) = &mut self.parser {
+            return parser.parse(line);
+        }
+        if let Some(flavors) = header_flavors(line) {
+            self.candidates = flavors.to_vec();
+            return None;
+        }
+        for &flavor in &self.candidates {
+            let mut parser = ping_parser(flavor);
+            if let Some(parsed) = parser.parse(line) {
+                tracing::debug!(?flavor, "detected the ping flavor");
+                self.parser = Some(parser);
+                return Some(parsed);
+            }
+        }
+        None
+    }
+}
+
+// fping starts every line about a target with the target as it was given,
+// and numbers probes from 0: `10.0.0.1 : [0], 64 bytes, 0.52 ms (0.52 avg,
+// 0% loss)`, or `10.0.0.1 : duplicate for [0], 64 bytes, 0.61 ms`.
+pub fn parse_fping_line(line: &str) -> Option<(String, PingInfo)> {
+    let re = regex!(r"^(?P<host>\S+)\s+: (?P<duplicate>duplicate for )?\[(?P<seq>\d+)\], (?P<bytes>\d+) bytes, (?P<time>[\d.]+) ms");
+    let captures = re.captures(line.trim())?;
+    let ping = PingInfo {
+        bytes_sent: captures["bytes"].parse().ok()?,
+        icmp_seq: captures["seq"].parse::<u32>().ok()? + 1,
+        time: number(&captures["time"])?,
+        duplicate: captures.name("duplicate").is_some(),
+        ..Default::default()
+    };
+    Some((captures["host"].to_string(), ping))
+}
+
+// Newer fping reports lost probes: `10.0.0.1 : [2], timed out (0.52 avg, 33% loss)`.
+pub fn parse_fping_timeout_line(line: &str) -> Option<(String, u32)> {
+    let re = regex!(r"^(\S+)\s+: \[(\d+)\], timed out");
+    let captures = re.captures(line.trim())?;
+    Some((captures[1].to_string(), captures[2].parse::<u32>().ok()? + 1))
+}
+
+// `ICMP Host Unreachable from 10.0.0.254 for ICMP Echo sent to 10.0.0.1`
+pub fn parse_fping_error_line(line: &str) -> Option<(String, PingError)> {
+    let re = regex!(r"^ICMP (?P<message>.+?) from (?P<from>\S+) for ICMP Echo sent to (?P<host>\S+)");
+    let captures = re.captures(line.trim())?;
+    let message = &captures["message"];
+    let error = PingError {
+        icmp_seq: None,
+        from: captures["from"].to_string(),
+        kind: error_kind(message)?,
+        message: message.to_string(),
+    };
+    Some((captures["host"].to_string(), error))
+}
+
+// The summary fping prints per target when it exits, e.g.
+// `10.0.0.1 : xmt/rcv/%loss = 3/3/0%, min/avg/max = 0.41/0.52/0.61`.
+pub fn parse_fping_statistics(line: &str) -> Option<(String, PacketStatistics)> {
+    let re = regex!(r"^(\S+)\s+: xmt/rcv/%loss = (\d+)/(\d+)/([\d.]+)%");
+    let captures = re.captures(line.trim())?;
+    let statistics = PacketStatistics {
+        transmitted: captures[2].parse().ok()?,
+        received: captures[3].parse().ok()?,
+        loss_percent: number(&captures[4])?,
+    };
+    Some((captures[1].to_string(), statistics))
+}
+
+// A hop as `traceroute -n` prints it: ` 3  10.0.0.1  5.102 ms  *  5.311 ms`.
+// Probes answered by other routers, and annotations such as `!H`, still count.
+pub fn parse_traceroute_line(line: &str) -> Option<Hop> {
+    let (hop, rest) = line.trim().split_once(char::is_whitespace)?;
+    let hop = hop.parse().ok()?;
+    let tokens: Vec<&str> = rest.split_whitespace().collect();
+    let mut host = None;
+    let mut sent = 0;
+    let mut times: Vec<f32> = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        let token = tokens[index];
+        if token == "*" {
+            sent += 1;
+        } else if let (Some(time), Some(&"ms")) = (number::<f32>(token), tokens.get(index + 1)) {
+            sent += 1;
+            times.push(time);
+            index += 1;
+        } else if !token.starts_with('!') && host.is_none() {
+            host = Some(token.to_string());
+        }
+        index += 1;
+    }
+    if sent == 0 {
+        return None;
+    }
+    let answered = !times.is_empty();
+    Some(Hop {
+        hop,
+        host,
+        sent,
+        loss_percent: ((sent - times.len() as u32) as f32 / sent as f32 * 1000.0).round() / 10.0,
+        avg: answered.then(|| round_ms(times.iter().sum::<f32>() / times.len() as f32)),
+        best: answered.then(|| times.iter().cloned().fold(f32::INFINITY, f32::min)),
+        worst: answered.then(|| times.iter().cloned().fold(f32::NEG_INFINITY, f32::max)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What every line of `output` tells, written short to compare easily.
+    fn parse_all(flavor: PingFlavor, output: &str) -> Vec<String> {
+        let mut parser = ping_parser(flavor);
+        output
+            .lines()
+            .filter_map(|line| parser.parse(line))
+            .map(|parsed| match parsed {
+                PingLine::Reply(ping) => {
+                    let duplicate = if ping.duplicate { " dup" } else { "" };
+                    format!("reply {} ttl={} time={} bytes={}{}", ping.icmp_seq, ping.ttl, ping.time, ping.bytes_sent, duplicate)
+                },
+                PingLine::Lost(seq) => format!("lost {}", seq),
+                PingLine::Error(error) => format!("error {:?} {} from {} seq={:?}", error.kind, error.message, error.from, error.icmp_seq),
+                PingLine::Packets(packets) => format!("packets {}/{} {}%", packets.transmitted, packets.received, packets.loss_percent),
+                PingLine::Trips(trips) => format!("trips {}/{}/{}/{}", trips.min, trips.avg, trips.max, trips.stddev),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn iputils_translated() {
+        let output = "64 octets de 10.0.0.1 : icmp_seq=1 ttl=64 temps=0,051 ms
+3 paquets transmis, 3 reçus, 0 % paquets perdus, temps 2003 ms
+rtt min/moy/max/mdev = 0,051/0,060/0,071/0,008 ms
+";
+        assert_eq!(parse_all(PingFlavor::Iputils, output), ["reply 1 ttl=64 time=0.051 bytes=64", "packets 3/3 0%", "trips 0.051/0.06/0.071/0.008"]);
+    }
+
+    #[test]
+    fn fping() {
+        let (host, ping) = parse_fping_line("10.0.0.1 : [0], 64 bytes, 0.52 ms (0.52 avg, 0% loss)").unwrap();
+        assert_eq!((host.as_str(), ping.icmp_seq, ping.time, ping.duplicate), ("10.0.0.1", 1, 0.52, false));
+        let (_, ping) = parse_fping_line("10.0.0.1 : duplicate for [0], 64 bytes, 0.61 ms").unwrap();
+        assert!(ping.duplicate);
+        assert_eq!(parse_fping_timeout_line("10.0.0.1 : [2], timed out (0.52 avg, 33% loss)"), Some(("10.0.0.1".to_string(), 3)));
+        let (host, error) = parse_fping_error_line("ICMP Host Unreachable from 10.0.0.254 for ICMP Echo sent to 10.0.0.1").unwrap();
+        assert_eq!((host.as_str(), error.kind, error.from.as_str()), ("10.0.0.1", PingErrorKind::Unreachable, "10.0.0.254"));
+        let (host, packets) = parse_fping_statistics("10.0.0.1 : xmt/rcv/%loss = 3/2/33%, min/avg/max = 0.41/0.52/0.61").unwrap();
+        assert_eq!((host.as_str(), packets.transmitted, packets.received, packets.loss_percent), ("10.0.0.1", 3, 2, 33.0));
+    }
+
+    #[test]
+    fn traceroute() {
+        let hop = parse_traceroute_line(" 3  10.0.0.1  5.102 ms  *  5.311 ms").unwrap();
+        assert_eq!((hop.hop, hop.host.as_deref(), hop.sent, hop.best, hop.worst), (3, Some("10.0.0.1"), 3, Some(5.102), Some(5.311)));
+        assert_eq!(hop.loss_percent, 33.3);
+        let hop = parse_traceroute_line(" 4  * * *").unwrap();
+        assert_eq!((hop.host, hop.sent, hop.avg, hop.loss_percent), (None, 3, None, 100.0));
+        assert!(parse_traceroute_line("traceroute to 10.0.0.1 (10.0.0.1), 30 hops max").is_none());
+    }
+
+    const IPUTILS: &str = "PING 10.0.0.1 (10.0.0.1) 56(84) bytes of data.
+64 bytes from 10.0.0.1: icmp_seq=1 ttl=64 time=0.051 ms
+64 bytes from 10.0.0.1: icmp_seq=1 ttl=64 time=0.090 ms (DUP!)
+no answer yet for icmp_seq=2
+From 10.0.0.254 icmp_seq=3 Destination Host Unreachable
+64 bytes from 10.0.0.1: icmp_seq=4 ttl=64 time=1.5 ms
+
+--- 10.0.0.1 ping statistics ---
+4 packets transmitted, 2 received, +1 duplicates, +1 errors, 50% packet loss, time 3004ms
+rtt min/avg/max/mdev = 0.051/0.775/1.500/0.724 ms
+";
+
+    #[test]
+    fn iputils() {
+        assert_eq!(parse_all(PingFlavor::Iputils, IPUTILS), [
+            "reply 1 ttl=64 time=0.051 bytes=64",
+            "reply 1 ttl=64 time=0.09 bytes=64 dup",
+            "lost 2",
+            "error Unreachable Destination Host Unreachable from 10.0.0.254 seq=Some(3)",
+            "reply 4 ttl=64 time=1.5 bytes=64",
+            "packets 4/2 50%",
+            "trips 0.051/0.775/1.5/0.724",
+        ]);
+    }
+
+    #[test]
+    fn busybox_numbers_probes_from_one() {
+        let output = "PING 10.0.0.1 (10.0.0.1): 56 data bytes
+64 bytes from 10.0.0.1: seq=0 ttl=64 time=0.051 ms
+64 bytes from 10.0.0.1: seq=1 ttl=64 time=0.062 ms
+
+--- 10.0.0.1 ping statistics ---
+2 packets transmitted, 2 packets received, 0% packet loss
+round-trip min/avg/max = 0.051/0.056/0.062 ms
+";
+        assert_eq!(parse_all(PingFlavor::Busybox, output), ["reply 1 ttl=64 time=0.051 bytes=64", "reply 2 ttl=64 time=0.062 bytes=64", "packets 2/2 0%"]);
+    }
+
+    #[test]
+    fn bsd() {
+        let output = "PING 10.0.0.1 (10.0.0.1): 56 data bytes
+64 bytes from 10.0.0.1: icmp_seq=0 ttl=64 time=0.050 ms
+Request timeout for icmp_seq 1
+92 bytes from 10.0.0.254: Time to live exceeded
+
+--- 10.0.0.1 ping statistics ---
+3 packets transmitted, 1 packets received, 66.7% packet loss
+round-trip min/avg/max/stddev = 0.050/0.050/0.050/0.000 ms
+";
+        assert_eq!(parse_all(PingFlavor::Bsd, output), [
+            "reply 1 ttl=64 time=0.05 bytes=64",
+            "lost 2",
+            "error TimeExceeded Time to live exceeded from 10.0.0.254 seq=None",
+            "packets 3/1 66.7%",
+            "trips 0.05/0.05/0.05/0",
+        ]);
+    }
+
+    #[test]
+    fn solaris() {
+        let output = "PING 10.0.0.1: 56 data bytes
+64 bytes from gw (10.0.0.1): icmp_seq=0. time=0.560 ms
+----10.0.0.1 PING Statistics----
+1 packets transmitted, 1 packets received, 0% packet loss
+round-trip (ms)  min/avg/max/stddev = 0.560/0.560/0.560/0.000
+";
+        assert_eq!(parse_all(PingFlavor::Solaris, output), ["reply 1 ttl=0 time=0.56 bytes=64", "packets 1/1 0%", "trips 0.56/0.56/0.56/0"]);
+    }
+
+    #[test]
+    fn windows_numbers_replies_and_what_takes_their_place() {
+        let output = "Pinging 10.0.0.1 with 32 bytes of data:
+Reply from 10.0.0.1: bytes=32 time<1ms TTL=128
+Request timed out.
+Reply from 10.0.0.254: TTL expired in transit.
+Reply from 10.0.0.1: bytes=32 time=12ms TTL=128
+
+Ping statistics for 10.0.0.1:
+    Packets: Sent = 4, Received = 3, Lost = 1 (25% loss),
+";
+        assert_eq!(parse_all(PingFlavor::Windows, output), [
+            "reply 1 ttl=128 time=1 bytes=32",
+            "lost 2",
+            "error TimeExceeded TTL expired in transit from 10.0.0.254 seq=Some(3)",
+            "reply 4 ttl=128 time=12 bytes=32",
+            "packets 4/3 25%",
+        ]);
+    }
+
+    #[test]
+    fn auto_detects_from_the_header() {
+        assert_eq!(parse_all(PingFlavor::Auto, IPUTILS), parse_all(PingFlavor::Iputils, IPUTILS));
+        // BusyBox and BSD share a header; the first reply tells them apart.
+        let busybox = "PING 10.0.0.1 (10.0.0.1): 56 data bytes\n64 bytes from 10.0.0.1: seq=0 ttl=64 time=0.051 ms\n";
+        assert_eq!(parse_all(PingFlavor::Auto, busybox), ["reply 1 ttl=64 time=0.051 bytes=64"]);
+    }
+}
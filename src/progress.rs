@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use tokio::sync::mpsc;
+use crate::{EventKind, PingEvent, PingOptions, PingReport, Target};
+use crate::stats::{packet_statistics, round_ms};
+
+// Beyond this many targets only the overall bar is shown.
+const MAX_TARGET_BARS: usize = 16;
+
+struct TargetBar {
+    bar: Option<ProgressBar>,
+    /// Probes to send; `None` for unlimited runs.
+    count: Option<u64>,
+    position: u64,
+    done: bool,
+}
+
+/// Draws progress bars on standard error, one per target and one for the
+/// whole run, until `receiver` closes. Nothing is drawn when standard error
+/// is not a terminal.
+pub async fn show_progress(targets: &[Target], opts: &PingOptions, mut receiver: mpsc::Receiver<PingEvent>) {
+    let style = ProgressStyle::with_template("{prefix:>24.bold} [{bar:30}] {pos}/{len} {msg}")
+        .unwrap()
+        .progress_chars("=> ");
+    // Unlimited runs have no end to show, only the probes so far.
+    let unlimited = ProgressStyle::with_template("{prefix:>24.bold} {spinner} {pos} probes {msg}").unwrap();
+    let counts: Vec<Option<u64>> = targets.iter().map(|target| Some(target.options(opts).count as u64).filter(|&count| count > 0)).collect();
+    let style_for = |count: Option<u64>| if count.is_some() { style.clone() } else { unlimited.clone() };
+    let bars = MultiProgress::new();
+    let total: Option<u64> = counts.iter().copied().sum();
+    let overall = bars.add(ProgressBar::new(total.unwrap_or(0)).with_style(style_for(total)).with_prefix("all targets"));
+    let mut states = HashMap::new();
+    for (target, &count) in targets.iter().zip(&counts) {
+        let bar = (targets.len() <= MAX_TARGET_BARS)
+            .then(|| bars.add(ProgressBar::new(count.unwrap_or(0)).with_style(style_for(count)).with_prefix(target.label())));
+        states.insert(target.label(), TargetBar { bar, count, position: 0, done: false });
+    }
+    let mut finished = 0;
+    overall.set_message(format!("0/{} targets done", targets.len()));
+    while let Some(event) = receiver.recv().await {
+        let outcome = match &event.kind {
+            EventKind::Reply(_) | EventKind::Lost { .. } | EventKind::IcmpError(_) => None,
+            EventKind::Finished(_) => Some("done".to_string()),
+            EventKind::Failed(error) => Some(format!("failed: {}", error)),
+            EventKind::Started { .. } | EventKind::Statistics(_) | EventKind::PathChanged { .. } => continue,
+        };
+        let Some(state) = states.get_mut(&event.target) else {
+            continue;
+        };
+        if state.done {
+            continue;
+        }
+        // Targets that finish early, e.g. on a timeout, still complete the overall bar.
+        let advance = match outcome {
+            None => state.count.is_none_or(|count| state.position < count) as u64,
+            Some(_) => state.count.map_or(0, |count| count - state.position),
+        };
+        state.position += advance;
+        overall.inc(advance);
+        if let Some(bar) = &state.bar {
+            bar.inc(advance);
+        }
+        if let Some(message) = outcome {
+            state.done = true;
+            finished += 1;
+            overall.set_message(format!("{}/{} targets done", finished, targets.len()));
+            if let Some(bar) = &state.bar {
+                if matches!(event.kind, EventKind::Failed(_)) {
+                    bar.abandon_with_message(message);
+                } else {
+                    bar.finish_with_message(message);
+                }
+            }
+        }
+    }
+    overall.finish();
+}
+
+/// Prints a line on standard error for every probe of every target, as ping
+/// does, until `receiver` closes. With `debug`, also prints the address and
+/// options each target is pinged with, and the counts the prober reported.
+pub async fn show_lines(targets: &[Target], opts: &PingOptions, mut receiver: mpsc::Receiver<PingEvent>, debug: bool) {
+    let options: HashMap<String, PingOptions> = targets.iter().map(|target| (target.label(), target.options(opts))).collect();
+    let mut addresses = HashMap::new();
+    while let Some(event) = receiver.recv().await {
+        let target = &event.target;
+        match &event.kind {
+            EventKind::Started { address } => {
+                addresses.insert(target.clone(), *address);
+                if let (true, Some(opts)) = (debug, options.get(target)) {
+                    let (backend, probe) = (format!("{:?}", opts.backend).to_lowercase(), format!("{:?}", opts.probe).to_lowercase());
+                    eprintln!(
+                        "{}: pinging {} ({} backend, {} probes, count {}, interval {} s, timeout {} s, size {} bytes)",
+                        target, address, backend, probe, opts.count, opts.interval, opts.timeout, opts.size
+                    );
+                }
+            },
+            EventKind::Reply(ping) => {
+                let from = addresses.get(target).map_or(target.clone(), |address| address.to_string());
+                let duplicate = if ping.duplicate { " (DUP!)" } else { "" };
+                eprintln!("{}: {} bytes from {}: icmp_seq={} ttl={} time={} ms{}", target, ping.bytes_sent, from, ping.icmp_seq, ping.ttl, ping.time, duplicate);
+            },
+            EventKind::Lost { icmp_seq } => eprintln!("{}: no answer for icmp_seq={}", target, icmp_seq),
+            EventKind::IcmpError(error) => {
+                let seq = error.icmp_seq.map_or(String::new(), |seq| format!(" icmp_seq={}", seq));
+                eprintln!("{}: from {}{}: {}", target, error.from, seq, error.message);
+            },
+            EventKind::Statistics(packets) if debug => {
+                eprintln!("{}: the prober reported {} sent, {} received, {}% loss", target, packets.transmitted, packets.received, packets.loss_percent);
+            },
+            EventKind::Statistics(_) | EventKind::PathChanged { .. } => (),
+            EventKind::Finished(report) => eprintln!("{}", done(target, report)),
+            EventKind::Failed(error) => eprintln!("{}: failed: {}", target, error),
+        }
+    }
+}
+
+/// Prints a line on standard output for every target as soon as it is
+/// done, while the others go on, until `receiver` closes.
+pub async fn show_finished(mut receiver: mpsc::Receiver<PingEvent>) {
+    while let Some(event) = receiver.recv().await {
+        match &event.kind {
+            EventKind::Finished(report) => println!("{}", done(&event.target, report)),
+            EventKind::Failed(error) => println!("{}: failed: {}", event.target, error),
+            _ => (),
+        }
+    }
+}
+
+fn done(target: &str, report: &PingReport) -> String {
+    match (&report.packets, &report.trips) {
+        (Some(packets), Some(trips)) => format!(
+            "{}: done, {} sent, {} received, {}% loss, rtt min/avg/max {}/{}/{} ms",
+            target, packets.transmitted, packets.received, packets.loss_percent, trips.min, trips.avg, trips.max
+        ),
+        (Some(packets), None) => format!("{}: done, {} sent, {} received, {}% loss", target, packets.transmitted, packets.received, packets.loss_percent),
+        _ => format!("{}: done", target),
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    sent: u32,
+    received: u32,
+    min: f32,
+    max: f32,
+    total: f32,
+}
+
+/// The probes of a run so far, for a look at it while it goes on.
+pub struct Tally {
+    counts: Vec<(String, Counts)>,
+}
+
+impl Tally {
+    pub fn new(targets: &[Target]) -> Self {
+        Tally { counts: targets.iter().map(|target| (target.label(), Counts::default())).collect() }
+    }
+
+    pub fn record(&mut self, event: &PingEvent) {
+        let Some((_, counts)) = self.counts.iter_mut().find(|(target, _)| *target == event.target) else {
+            return;
+        };
+        match &event.kind {
+            EventKind::Reply(ping) if !ping.duplicate => {
+                counts.min = if counts.received == 0 { ping.time } else { counts.min.min(ping.time) };
+                counts.max = counts.max.max(ping.time);
+                counts.total += ping.time;
+                counts.sent += 1;
+                counts.received += 1;
+            },
+            EventKind::Lost { .. } | EventKind::IcmpError(_) => counts.sent += 1,
+            _ => (),
+        }
+    }
+
+    /// Prints a line per target on standard error, as ping does on SIGQUIT.
+    pub fn print(&self) {
+        for (target, counts) in &self.counts {
+            let packets = packet_statistics(counts.sent, counts.received);
+            let loss = (packets.loss_percent * 10.0).round() / 10.0;
+            if counts.received == 0 {
+                eprintln!("{}: {}/{} packets, {}% loss", target, counts.received, counts.sent, loss);
+            } else {
+                let avg = round_ms(counts.total / counts.received as f32);
+                eprintln!("{}: {}/{} packets, {}% loss, rtt min/avg/max {}/{}/{} ms", target, counts.received, counts.sent, loss, counts.min, avg, counts.max);
+            }
+        }
+    }
+}
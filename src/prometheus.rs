@@ -0,0 +1,320 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::str::FromStr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use hdrhistogram::Histogram;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use crate::api::{read_request, Api};
+use crate::monitor::Monitor;
+use crate::report::PingReport;
+use crate::rolling::{Rolling, WINDOWS};
+use crate::{PingOptions, Target};
+
+const RTT_BUCKETS: [f64; 12] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+// Events kept for listeners that fall behind.
+const EVENTS: usize = 1024;
+
+const RTT_QUANTILES: [f64; 5] = [0.5, 0.9, 0.95, 0.99, 0.999];
+
+// Round trip times are recorded in microseconds, to 3 significant digits;
+// slower replies count as this slow.
+const MAX_RTT_MICROS: u64 = 60_000_000;
+
+/// Upper bounds, in seconds, of the round trip time histogram buckets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Buckets(pub Vec<f64>);
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Buckets(RTT_BUCKETS.to_vec())
+    }
+}
+
+impl FromStr for Buckets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bounds = s
+            .split(',')
+            .map(|bound| bound.trim().parse::<f64>().ok().filter(|bound| *bound > 0.0).ok_or_else(|| format!("invalid bucket bound '{}'", bound)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !bounds.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err(format!("bucket bounds must increase, found '{}'", s));
+        }
+        Ok(Buckets(bounds))
+    }
+}
+
+fn micros(seconds: f64) -> u64 {
+    (seconds * 1_000_000.0).round() as u64
+}
+
+struct TargetMetrics {
+    /// The target's own labels, rendered as `,key="value"`.
+    labels: String,
+    up: bool,
+    loss_ratio: f64,
+    rtt_avg: Option<f64>,
+    sent: u64,
+    received: u64,
+    /// Every round trip time seen, for the buckets and the quantiles.
+    rtt: Histogram<u64>,
+    rtt_sum: f64,
+    /// The rounds of the last minutes, by when they finished.
+    rolling: Rolling,
+    flaps: Option<u32>,
+}
+
+impl Default for TargetMetrics {
+    fn default() -> Self {
+        TargetMetrics {
+            labels: String::new(),
+            up: false,
+            loss_ratio: 0.0,
+            rtt_avg: None,
+            sent: 0,
+            received: 0,
+            rtt: Histogram::new_with_max(MAX_RTT_MICROS, 3).expect("valid histogram bounds"),
+            rtt_sum: 0.0,
+            rolling: Rolling::default(),
+            flaps: None,
+        }
+    }
+}
+
+/// Per-target metrics accumulated across probing rounds.
+#[derive(Default)]
+pub struct Metrics {
+    buckets: Buckets,
+    targets: BTreeMap<String, TargetMetrics>,
+}
+
+impl Metrics {
+    pub fn new(buckets: Buckets) -> Metrics {
+        Metrics { buckets, targets: BTreeMap::new() }
+    }
+
+    pub fn record(&mut self, target: &str, labels: &BTreeMap<String, String>, result: &io::Result<PingReport>) {
+        let metrics = self.targets.entry(target.to_string()).or_default();
+        metrics.labels = labels
+            .iter()
+            .map(|(key, value)| format!(",{}=\"{}\"", label_name(key), escape(value)))
+            .collect();
+        let Ok(report) = result else {
+            metrics.up = false;
+            return;
+        };
+        if let Some(packets) = &report.packets {
+            metrics.sent += packets.transmitted as u64;
+            metrics.received += packets.received as u64;
+            metrics.loss_ratio = packets.loss_percent as f64 / 100.0;
+            metrics.up = packets.received > 0;
+        } else {
+            metrics.up = !report.pings.is_empty();
+        }
+        metrics.rtt_avg = report.trips.as_ref().map(|trips| trips.avg as f64 / 1000.0);
+        metrics.flaps = report.flaps;
+        for ping in &report.pings {
+            let seconds = ping.time as f64 / 1000.0;
+            metrics.rtt.saturating_record(micros(seconds).max(1));
+            metrics.rtt_sum += seconds;
+        }
+        if let Some(packets) = &report.packets {
+            let times: Vec<f32> = report.pings.iter().filter(|ping| !ping.duplicate).map(|ping| ping.time).collect();
+            metrics.rolling.record(Instant::now(), packets.transmitted, packets.received, &times);
+        }
+    }
+
+    /// Stops exporting `target`.
+    pub(crate) fn remove(&mut self, target: &str) {
+        self.targets.remove(target);
+    }
+
+    /// Stops exporting the targets `keep` turns down.
+    pub(crate) fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.targets.retain(|target, _| keep(target));
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP multiping_up Whether the target answered in the last round.");
+        let _ = writeln!(out, "# TYPE multiping_up gauge");
+        for (target, metrics) in &self.targets {
+            let _ = writeln!(out, "multiping_up{{target=\"{}\"{}}} {}", escape(target), metrics.labels, metrics.up as u8);
+        }
+        let _ = writeln!(out, "# HELP multiping_packet_loss_ratio Fraction of probes lost in the last round.");
+        let _ = writeln!(out, "# TYPE multiping_packet_loss_ratio gauge");
+        for (target, metrics) in &self.targets {
+            let _ = writeln!(out, "multiping_packet_loss_ratio{{target=\"{}\"{}}} {}", escape(target), metrics.labels, metrics.loss_ratio);
+        }
+        let _ = writeln!(out, "# HELP multiping_rtt_avg_seconds Average round trip time in the last round.");
+        let _ = writeln!(out, "# TYPE multiping_rtt_avg_seconds gauge");
+        for (target, metrics) in &self.targets {
+            if let Some(avg) = metrics.rtt_avg {
+                let _ = writeln!(out, "multiping_rtt_avg_seconds{{target=\"{}\"{}}} {}", escape(target), metrics.labels, avg);
+            }
+        }
+        let _ = writeln!(out, "# HELP multiping_flaps Times the target went down or came back up within the flap window.");
+        let _ = writeln!(out, "# TYPE multiping_flaps gauge");
+        for (target, metrics) in &self.targets {
+            if let Some(flaps) = metrics.flaps {
+                let _ = writeln!(out, "multiping_flaps{{target=\"{}\"{}}} {}", escape(target), metrics.labels, flaps);
+            }
+        }
+        let now = Instant::now();
+        let _ = writeln!(out, "# HELP multiping_window_loss_ratio Fraction of probes lost in the rounds of the last 1, 5, and 15 minutes.");
+        let _ = writeln!(out, "# TYPE multiping_window_loss_ratio gauge");
+        for (target, metrics) in &self.targets {
+            for (name, window) in WINDOWS {
+                if let Some(stats) = metrics.rolling.stats(now, window) {
+                    let ratio = stats.loss_percent as f64 / 100.0;
+                    let _ = writeln!(out, "multiping_window_loss_ratio{{target=\"{}\"{},window=\"{}\"}} {}", escape(target), metrics.labels, name, ratio);
+                }
+            }
+        }
+        let _ = writeln!(out, "# HELP multiping_window_rtt_avg_seconds Average round trip time in the rounds of the last 1, 5, and 15 minutes.");
+        let _ = writeln!(out, "# TYPE multiping_window_rtt_avg_seconds gauge");
+        for (target, metrics) in &self.targets {
+            for (name, window) in WINDOWS {
+                if let Some(avg) = metrics.rolling.stats(now, window).and_then(|stats| stats.avg) {
+                    let seconds = avg as f64 / 1000.0;
+                    let _ = writeln!(out, "multiping_window_rtt_avg_seconds{{target=\"{}\"{},window=\"{}\"}} {}", escape(target), metrics.labels, name, seconds);
+                }
+            }
+        }
+        let _ = writeln!(out, "# HELP multiping_packets_sent_total Probes sent.");
+        let _ = writeln!(out, "# TYPE multiping_packets_sent_total counter");
+        for (target, metrics) in &self.targets {
+            let _ = writeln!(out, "multiping_packets_sent_total{{target=\"{}\"{}}} {}", escape(target), metrics.labels, metrics.sent);
+        }
+        let _ = writeln!(out, "# HELP multiping_packets_received_total Replies received.");
+        let _ = writeln!(out, "# TYPE multiping_packets_received_total counter");
+        for (target, metrics) in &self.targets {
+            let _ = writeln!(out, "multiping_packets_received_total{{target=\"{}\"{}}} {}", escape(target), metrics.labels, metrics.received);
+        }
+        let _ = writeln!(out, "# HELP multiping_rtt_seconds Round trip times of the replies.");
+        let _ = writeln!(out, "# TYPE multiping_rtt_seconds histogram");
+        for (target, metrics) in &self.targets {
+            let target = escape(target);
+            for &bound in &self.buckets.0 {
+                let count = metrics.rtt.count_between(0, micros(bound));
+                let _ = writeln!(out, "multiping_rtt_seconds_bucket{{target=\"{}\"{},le=\"{}\"}} {}", target, metrics.labels, bound, count);
+            }
+            let _ = writeln!(out, "multiping_rtt_seconds_bucket{{target=\"{}\"{},le=\"+Inf\"}} {}", target, metrics.labels, metrics.rtt.len());
+            let _ = writeln!(out, "multiping_rtt_seconds_sum{{target=\"{}\"{}}} {}", target, metrics.labels, metrics.rtt_sum);
+            let _ = writeln!(out, "multiping_rtt_seconds_count{{target=\"{}\"{}}} {}", target, metrics.labels, metrics.rtt.len());
+        }
+        let _ = writeln!(out, "# HELP multiping_rtt_quantile_seconds Round trip time quantiles of all replies so far.");
+        let _ = writeln!(out, "# TYPE multiping_rtt_quantile_seconds gauge");
+        for (target, metrics) in &self.targets {
+            if metrics.rtt.is_empty() {
+                continue;
+            }
+            let target = escape(target);
+            for quantile in RTT_QUANTILES {
+                let seconds = metrics.rtt.value_at_quantile(quantile) as f64 / 1_000_000.0;
+                let _ = writeln!(out, "multiping_rtt_quantile_seconds{{target=\"{}\"{},quantile=\"{}\"}} {}", target, metrics.labels, quantile, seconds);
+            }
+        }
+        out
+    }
+}
+
+// Label names may only use ASCII letters, digits, and underscores.
+fn label_name(key: &str) -> String {
+    let name: String = key.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    // `target` and `le` are taken, and names must not start with a digit.
+    if matches!(name.as_str(), "target" | "le") || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("label_{}", name)
+    } else {
+        name
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+async fn handle_connection(mut stream: TcpStream, api: Arc<Api>) -> io::Result<()> {
+    let request = read_request(&mut stream).await?;
+    if request.wants_events() {
+        return api.stream_events(&request, &mut stream).await;
+    }
+    let response = api.handle(&request);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status, response.content_type, response.body.len(), response.body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Pings `targets` every `period` and serves the results on `/metrics`
+/// at `listen` until the process is stopped, with the round trip times in
+/// `buckets`. Every round is handed to `monitor` too. The same address
+/// serves the API to list, add, and remove targets, to query their status
+/// and history, and to follow the events of the rounds; changing the
+/// targets takes `token`, if given. Without one, the targets can only be
+/// changed when `listen` is a loopback address.
+pub async fn serve(listen: SocketAddr, targets: Vec<Target>, opts: PingOptions, period: Duration, buckets: Buckets, mut monitor: Monitor, token: Option<String>) -> io::Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    let db = monitor.db.as_ref().map(|db| db.path().to_path_buf());
+    let (events, _) = broadcast::channel(EVENTS);
+    monitor.events = Some(events.clone());
+    let read_only = token.is_none() && !listen.ip().is_loopback();
+    if read_only {
+        eprintln!("Serving beyond the loopback interface without --api-token: the API cannot change the targets");
+    }
+    let api = Arc::new(Api::new(targets, Metrics::new(buckets), opts.clone(), db, token, read_only, events));
+    let server_api = api.clone();
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            tokio::spawn(handle_connection(stream, server_api.clone()));
+        }
+    });
+    monitor
+        .run(api.targets.clone(), opts, period, |targets, results, tracker| {
+            api.record(targets, results, tracker);
+        })
+        .await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape(r#"a "b" \ c"#), r#"a \"b\" \\ c"#);
+        assert_eq!(escape("two\nlines"), "two\\nlines");
+    }
+
+    #[test]
+    fn label_names_are_made_valid() {
+        assert_eq!(label_name("rack"), "rack");
+        assert_eq!(label_name("app.kubernetes.io/name"), "app_kubernetes_io_name");
+        assert_eq!(label_name("target"), "label_target");
+        assert_eq!(label_name("le"), "label_le");
+        assert_eq!(label_name("1st"), "label_1st");
+    }
+
+    #[test]
+    fn buckets() {
+        assert_eq!("0.001, 0.01,1".parse(), Ok(Buckets(vec![0.001, 0.01, 1.0])));
+        assert_eq!("0.01,0".parse::<Buckets>(), Err("invalid bucket bound '0'".to_string()));
+        assert_eq!("0.01,,1".parse::<Buckets>(), Err("invalid bucket bound ''".to_string()));
+        assert_eq!("1,0.5".parse::<Buckets>(), Err("bucket bounds must increase, found '1,0.5'".to_string()));
+        assert!("0.1,0.1".parse::<Buckets>().is_err());
+    }
+}
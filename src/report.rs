@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::error::MultipingError;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PingInfo {
+    pub bytes_sent: u32,
+    pub icmp_seq: u32,
+    pub ttl: u32,
+    pub time: f32,
+    /// When the reply was received, as an RFC 3339 UTC timestamp.
+    pub timestamp: String,
+    /// Seconds from the start of the run until the reply, on a monotonic clock.
+    pub offset: f64,
+    /// Response status code, for HTTP probes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// A further reply to a probe that was already answered.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub duplicate: bool,
+    /// The reply to a warm-up probe, left out of the statistics.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub warmup: bool,
+}
+
+/// The kind of ICMP error a probe got instead of a reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PingErrorKind {
+    /// The destination host, network, or port is unreachable.
+    Unreachable,
+    /// The probe ran out of hops on the way.
+    TimeExceeded,
+}
+
+/// An ICMP error received in answer to a probe.
+#[derive(Debug, Clone, Serialize)]
+pub struct PingError {
+    /// The probe the error answers, when ping tells.
+    pub icmp_seq: Option<u32>,
+    /// The host or router that sent the error.
+    pub from: String,
+    pub kind: PingErrorKind,
+    /// The error as ping printed it, e.g. `Destination Host Unreachable`.
+    pub message: String,
+}
+
+/// A hop on the path to a target, as traced by mtr or traceroute.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hop {
+    pub hop: u32,
+    /// The router that answered, if any did.
+    pub host: Option<String>,
+    pub sent: u32,
+    pub loss_percent: f32,
+    /// Round trip times to the hop, in milliseconds.
+    pub avg: Option<f32>,
+    pub best: Option<f32>,
+    pub worst: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacketStatistics {
+    pub transmitted: u32,
+    pub received: u32,
+    pub loss_percent: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoundTripStatistics {
+    pub min: f32,
+    pub avg: f32,
+    pub max: f32,
+    pub stddev: f32,
+}
+
+/// Distribution of the round trip times, computed from the samples.
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleStatistics {
+    pub p50: f32,
+    pub p90: f32,
+    pub p95: f32,
+    pub p99: f32,
+    pub stddev: f32,
+    /// RFC 3550 interarrival jitter, in milliseconds.
+    pub jitter: f32,
+    /// Moving average of the round trip times, weighting recent replies more.
+    pub ewma: f32,
+    pub trend: Trend,
+}
+
+/// Which way the round trip times were heading by the last reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trend {
+    Improving,
+    Steady,
+    Degrading,
+}
+
+impl Trend {
+    pub fn arrow(self) -> char {
+        match self {
+            Trend::Improving => '↓',
+            Trend::Steady => '→',
+            Trend::Degrading => '↑',
+        }
+    }
+}
+
+/// How the replies deviated from one answer per probe, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SequenceStatistics {
+    /// Unanswered probes between the first and the last answered one.
+    pub gaps: u32,
+    pub duplicates: u32,
+    /// Replies that came after the reply to a later probe.
+    pub reordered: u32,
+}
+
+impl SequenceStatistics {
+    pub fn is_clean(&self) -> bool {
+        *self == SequenceStatistics::default()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PingReport {
+    pub destination: String,
+    /// The address that was pinged.
+    pub address: Option<IpAddr>,
+    /// The group the target is summarized with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// The target's labels.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Name the address points back to, when reverse lookups were asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// The source address or interface the probes were sent from, when one
+    /// was chosen.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// What the target was probed with, e.g. `icmp` or `tcp:443`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probe: Option<String>,
+    /// The DSCP the probes were marked with, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<u8>,
+    /// The target's hardware address, for ARP probes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    /// Days until the server's certificate expires, for TLS probes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_expiry_days: Option<i64>,
+    /// Milliseconds it took to resolve the target's name.
+    pub resolve_time: Option<f32>,
+    pub pings: Vec<PingInfo>,
+    /// ICMP errors received instead of replies.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<PingError>,
+    pub packets: Option<PacketStatistics>,
+    pub trips: Option<RoundTripStatistics>,
+    /// The target did not finish before its deadline and was cut short.
+    pub timed_out: bool,
+    /// Largest packet, in bytes, that reached the target unfragmented.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mtu: Option<u32>,
+    pub samples: Option<SampleStatistics>,
+    pub sequence: Option<SequenceStatistics>,
+    /// Routers between here and the target, estimated from the TTL of its replies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hops: Option<u32>,
+    /// The path to the target, for targets traced after losing packets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<Vec<Hop>>,
+    /// How often the target went down or came back up lately, in `monitor`
+    /// and `serve` with flap detection.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flaps: Option<u32>,
+    /// Why the target could not be pinged, in the reports of failed targets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<MultipingError>,
+}
+
+impl PingReport {
+    /// Replies that were not duplicates.
+    pub fn answered(&self) -> u32 {
+        self.pings.iter().filter(|ping| !ping.duplicate).count() as u32
+    }
+
+    /// How many of the errors are of `kind`.
+    pub fn error_count(&self, kind: PingErrorKind) -> usize {
+        self.errors.iter().filter(|error| error.kind == kind).count()
+    }
+}
+
+/// Shared start of a run, so sample offsets are comparable across targets.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RunClock {
+    started: Instant,
+}
+
+impl RunClock {
+    pub fn start() -> RunClock {
+        RunClock { started: Instant::now() }
+    }
+
+    pub fn stamp(&self, ping: &mut PingInfo) {
+        ping.timestamp = rfc3339(SystemTime::now());
+        ping.offset = self.started.elapsed().as_secs_f64();
+    }
+}
+
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = elapsed.as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year, month, day,
+        secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60,
+        elapsed.subsec_micros()
+    )
+}
+
+/// Nanoseconds since the Unix epoch of a timestamp written by `rfc3339`.
+pub(crate) fn unix_nanos(timestamp: &str) -> Option<i128> {
+    let field = |range: std::ops::Range<usize>| timestamp.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    let micros = field(20..26).unwrap_or(0);
+    // Days since 1970-01-01 from a civil date, the inverse of the above.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+    Some(secs as i128 * 1_000_000_000 + micros as i128 * 1000)
+}
@@ -0,0 +1,89 @@
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+use crate::IpVersion;
+use crate::error::MultipingError;
+
+async fn lookup(target: &str, timeout: Duration) -> io::Result<Vec<IpAddr>> {
+    let mut addresses: Vec<IpAddr> = tokio::time::timeout(timeout, tokio::net::lookup_host((target, 0)))
+        .await
+        .map_err(|_| MultipingError::Timeout(format!("{}: name resolution timed out", target)))?
+        .map_err(|err| MultipingError::Unresolvable(format!("{}: cannot resolve the name ({})", target, err)))?
+        .map(|addr| addr.ip())
+        .collect();
+    // The resolver repeats addresses once per socket type.
+    let mut seen = Vec::new();
+    addresses.retain(|address| {
+        let new = !seen.contains(address);
+        seen.push(*address);
+        new
+    });
+    Ok(addresses)
+}
+
+fn no_address(target: &str, version: IpVersion) -> io::Error {
+    let family = if version == IpVersion::V6 { "IPv6" } else { "IPv4" };
+    MultipingError::Unresolvable(format!("{}: no {} address", target, family)).into()
+}
+
+/// Looks up the address to probe for `target`, giving up after `timeout`.
+pub async fn resolve(target: &str, version: IpVersion, timeout: Duration) -> io::Result<IpAddr> {
+    let addresses = lookup(target, timeout).await?;
+    let address = match version {
+        IpVersion::V4 => addresses.iter().find(|ip| ip.is_ipv4()),
+        IpVersion::V6 => addresses.iter().find(|ip| ip.is_ipv6()),
+        IpVersion::Any => addresses.iter().find(|ip| ip.is_ipv4()).or(addresses.first()),
+    };
+    address.copied().ok_or_else(|| no_address(target, version))
+}
+
+/// Looks up every address of `target` in the `version` family.
+pub async fn resolve_all(target: &str, version: IpVersion, timeout: Duration) -> io::Result<Vec<IpAddr>> {
+    let mut addresses = lookup(target, timeout).await?;
+    addresses.retain(|ip| match version {
+        IpVersion::V4 => ip.is_ipv4(),
+        IpVersion::V6 => ip.is_ipv6(),
+        IpVersion::Any => true,
+    });
+    if addresses.is_empty() {
+        return Err(no_address(target, version));
+    }
+    Ok(addresses)
+}
+
+/// Looks up the name `address` points back to, giving up after `timeout`.
+pub async fn reverse(address: IpAddr, timeout: Duration) -> io::Result<String> {
+    tokio::time::timeout(timeout, tokio::task::spawn_blocking(move || name_info(address)))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("{}: reverse lookup timed out", address)))??
+}
+
+#[cfg(unix)]
+fn name_info(address: IpAddr) -> io::Result<String> {
+    use std::ffi::CStr;
+    let socket_address = socket2::SockAddr::from(std::net::SocketAddr::new(address, 0));
+    let mut host = [0 as libc::c_char; libc::NI_MAXHOST as usize];
+    // NI_NAMEREQD makes addresses without a PTR record an error instead of
+    // echoing the address back.
+    let status = unsafe {
+        libc::getnameinfo(
+            socket_address.as_ptr().cast(),
+            socket_address.len(),
+            host.as_mut_ptr(),
+            host.len() as libc::socklen_t,
+            std::ptr::null_mut(),
+            0,
+            libc::NI_NAMEREQD,
+        )
+    };
+    if status != 0 {
+        let reason = unsafe { CStr::from_ptr(libc::gai_strerror(status)) };
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{}: no reverse name ({})", address, reason.to_string_lossy())));
+    }
+    Ok(unsafe { CStr::from_ptr(host.as_ptr()) }.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn name_info(address: IpAddr) -> io::Result<String> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, format!("{}: reverse lookups are not supported on this platform", address)))
+}
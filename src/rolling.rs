@@ -0,0 +1,63 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crate::stats::round_ms;
+
+/// The windows statistics are kept over, with their names.
+pub(crate) const WINDOWS: [(&str, Duration); 3] = [
+    ("1m", Duration::from_secs(60)),
+    ("5m", Duration::from_secs(300)),
+    ("15m", Duration::from_secs(900)),
+];
+
+// Probes sent at some point, and what came back for them.
+struct Entry {
+    at: Instant,
+    sent: u32,
+    received: u32,
+    times: Vec<f32>,
+}
+
+/// Loss and latency of a window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct WindowStats {
+    pub loss_percent: f32,
+    /// Milliseconds; `None` without replies.
+    pub avg: Option<f32>,
+}
+
+/// Loss and latency of one target over sliding windows, so that short spikes
+/// show next to the long-term trend.
+#[derive(Default)]
+pub(crate) struct Rolling {
+    entries: VecDeque<Entry>,
+}
+
+impl Rolling {
+    /// Adds `sent` probes at `at`, `received` of which were answered in `times`.
+    pub fn record(&mut self, at: Instant, sent: u32, received: u32, times: &[f32]) {
+        self.entries.push_back(Entry { at, sent, received, times: times.to_vec() });
+        // Nothing is asked about beyond the longest window.
+        let longest = WINDOWS[WINDOWS.len() - 1].1;
+        while self.entries.front().is_some_and(|entry| at.duration_since(entry.at) > longest) {
+            self.entries.pop_front();
+        }
+    }
+
+    /// The statistics of the `window` up to `now`, if anything was sent then.
+    pub fn stats(&self, now: Instant, window: Duration) -> Option<WindowStats> {
+        let (mut sent, mut received, mut sum, mut replies) = (0u64, 0u64, 0.0f64, 0usize);
+        for entry in self.entries.iter().rev().take_while(|entry| now.duration_since(entry.at) <= window) {
+            sent += entry.sent as u64;
+            received += entry.received as u64;
+            sum += entry.times.iter().map(|&time| time as f64).sum::<f64>();
+            replies += entry.times.len();
+        }
+        if sent == 0 {
+            return None;
+        }
+        Some(WindowStats {
+            loss_percent: sent.saturating_sub(received) as f32 / sent as f32 * 100.0,
+            avg: (replies > 0).then(|| round_ms((sum / replies as f64) as f32)),
+        })
+    }
+}
@@ -0,0 +1,320 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use flate2::write::GzEncoder;
+
+/// When a file is moved aside for a new one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rotation {
+    /// Once writing more would take it past this many bytes.
+    pub max_size: Option<u64>,
+    /// Once it has been written to for this long.
+    pub max_age: Option<Duration>,
+    /// Files moved aside that are kept, as `PATH.1` (the newest) to `PATH.N`,
+    /// or `NAME.1.gz` to `NAME.N.gz` for `NAME.gz`.
+    pub keep: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn of(path: &Path) -> Compression {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+enum Encoder {
+    Gzip(GzEncoder<File>),
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl Encoder {
+    fn new(compression: Compression, file: File) -> io::Result<Option<Encoder>> {
+        Ok(match compression {
+            Compression::None => None,
+            Compression::Gzip => Some(Encoder::Gzip(GzEncoder::new(file, flate2::Compression::default()))),
+            Compression::Zstd => Some(Encoder::Zstd(zstd::Encoder::new(file, 0)?)),
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Encoder::Gzip(encoder) => encoder,
+            Encoder::Zstd(encoder) => encoder,
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish().map(drop),
+            Encoder::Zstd(encoder) => encoder.finish().map(drop),
+        }
+    }
+}
+
+/// A file that entries, of one or more whole lines, are written to as they
+/// come, and that is rotated between entries as `rotation` says. Every new
+/// file starts with `header`.
+///
+/// Files named `.gz` or `.zst` are compressed with gzip or zstd. What was
+/// written is flushed to the file after every entry, but the file is only
+/// whole once `finish` ends the gzip member or zstd frame, as rotating and
+/// dropping it do.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    compression: Compression,
+    // Over a handle of its own to `file`, while a member or frame is open.
+    encoder: Option<Encoder>,
+    size: u64,
+    // Whether anything but the header went in.
+    written: bool,
+    opened: Instant,
+    rotation: Rotation,
+    header: Option<&'static str>,
+}
+
+impl std::fmt::Debug for RotatingFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RotatingFile").field("path", &self.path).field("size", &self.size).finish_non_exhaustive()
+    }
+}
+
+impl RotatingFile {
+    /// Opens `path`, adding to what is there when `append` is set, and
+    /// emptying it otherwise. The age of an appended file counts from now.
+    pub fn open(path: &Path, append: bool, rotation: Rotation, header: Option<&'static str>) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).write(true).append(append).truncate(!append).open(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("cannot open {}: {}", path.display(), err)))?;
+        let size = file.metadata()?.len();
+        let mut file = RotatingFile {
+            path: path.to_path_buf(),
+            file,
+            compression: Compression::of(path),
+            encoder: None,
+            size,
+            written: size > 0,
+            opened: Instant::now(),
+            rotation,
+            header,
+        };
+        if size == 0 {
+            file.write_header()?;
+        }
+        Ok(file)
+    }
+
+    /// Writes `entry`, which ends with a line break, after rotating the file
+    /// if it is due.
+    pub fn write(&mut self, entry: &str) -> io::Result<()> {
+        if self.due(entry.len() as u64) {
+            self.rotate()?;
+        }
+        self.put(entry)?;
+        self.written = true;
+        Ok(())
+    }
+
+    /// Ends the gzip member or zstd frame, if one is open, so that the file
+    /// is whole. The next entry starts another one.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.finish()?;
+            self.size = self.file.metadata()?.len();
+        }
+        Ok(())
+    }
+
+    fn put(&mut self, text: &str) -> io::Result<()> {
+        if self.compression == Compression::None {
+            self.file.write_all(text.as_bytes())?;
+            self.size += text.len() as u64;
+            return Ok(());
+        }
+        if self.encoder.is_none() {
+            self.encoder = Encoder::new(self.compression, self.file.try_clone()?)?;
+        }
+        if let Some(encoder) = &mut self.encoder {
+            let writer = encoder.writer();
+            writer.write_all(text.as_bytes())?;
+            writer.flush()?;
+        }
+        self.size = self.file.metadata()?.len();
+        Ok(())
+    }
+
+    // How large compressed entries come out is only known once they are
+    // written, so compressed files go by the size they reached.
+    fn due(&self, length: u64) -> bool {
+        if !self.written {
+            return false;
+        }
+        let full = self.rotation.max_size.is_some_and(|max_size| match self.compression {
+            Compression::None => self.size + length > max_size,
+            Compression::Gzip | Compression::Zstd => self.size >= max_size,
+        });
+        let old = self.rotation.max_age.is_some_and(|max_age| self.opened.elapsed() >= max_age);
+        full || old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.finish()?;
+        // Numbered before the extension of compressed files, which tools go by.
+        let aside = |n: u32| match (self.compression, self.path.extension()) {
+            (Compression::Gzip | Compression::Zstd, Some(extension)) => self.path.with_extension(format!("{}.{}", n, extension.to_string_lossy())),
+            _ => PathBuf::from(format!("{}.{}", self.path.display(), n)),
+        };
+        if self.rotation.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.rotation.keep).rev() {
+                if aside(n).exists() {
+                    fs::rename(aside(n), aside(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, aside(1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        self.written = false;
+        self.opened = Instant::now();
+        self.write_header()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        match self.header {
+            Some(header) => self.put(&format!("{}\n", header)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for RotatingFile {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("Cannot finish {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+/// Parses a size such as `500K`, `10M`, or `1G`, in bytes, or a plain number
+/// of bytes.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let units = [('K', 1 << 10), ('M', 1 << 20), ('G', 1 << 30)];
+    let (amount, unit) = match units.iter().find(|(suffix, _)| value.ends_with(*suffix) || value.ends_with(suffix.to_ascii_lowercase())) {
+        Some((_, unit)) => (&value[..value.len() - 1], *unit),
+        None => (value, 1),
+    };
+    match amount.parse::<u64>() {
+        Ok(amount) if amount > 0 => Ok(amount * unit),
+        _ => Err(format!("invalid size '{}' (expected e.g. 500K, 10M, or 1G)", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An empty directory of the test's own, left behind only by failures.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("multiping-rotate-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("500K"), Ok(500 << 10));
+        assert_eq!(parse_size("10m"), Ok(10 << 20));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        for invalid in ["0", "M", "10T", "-1K", "1.5M"] {
+            assert!(parse_size(invalid).is_err(), "{} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn rotates_by_size_and_keeps_the_newest() {
+        let dir = scratch("size");
+        let path = dir.join("pings.csv");
+        let rotation = Rotation { max_size: Some(20), keep: 2, ..Default::default() };
+        let mut file = RotatingFile::open(&path, false, rotation, Some("target,time")).unwrap();
+        for entry in ["a,1\n", "b,2\n", "c,3\n", "d,4\n", "e,5\n", "f,6\n", "g,7\n"] {
+            file.write(entry).unwrap();
+        }
+        drop(file);
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+        // Every file starts with the header, and the oldest one went away.
+        assert_eq!(read(path.clone()), "target,time\ng,7\n");
+        assert_eq!(read(dir.join("pings.csv.1")), "target,time\ne,5\nf,6\n");
+        assert_eq!(read(dir.join("pings.csv.2")), "target,time\nc,3\nd,4\n");
+        assert!(!dir.join("pings.csv.3").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_fresh_file_takes_an_entry_past_the_size() {
+        let dir = scratch("whole");
+        let path = dir.join("pings.ndjson");
+        let rotation = Rotation { max_size: Some(4), keep: 0, ..Default::default() };
+        let mut file = RotatingFile::open(&path, false, rotation, None).unwrap();
+        file.write("a long line\n").unwrap();
+        file.write("another\n").unwrap();
+        drop(file);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "another\n");
+        assert!(!dir.join("pings.ndjson.1").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn appends_and_rotates_by_age() {
+        let dir = scratch("age");
+        let path = dir.join("pings.csv");
+        fs::write(&path, "old\n").unwrap();
+        let rotation = Rotation { max_age: Some(Duration::ZERO), keep: 1, ..Default::default() };
+        let mut file = RotatingFile::open(&path, true, rotation, Some("header")).unwrap();
+        file.write("new\n").unwrap();
+        drop(file);
+        assert_eq!(fs::read_to_string(dir.join("pings.csv.1")).unwrap(), "old\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "header\nnew\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compressed_files_are_numbered_before_the_extension() {
+        let dir = scratch("compressed");
+        let path = dir.join("pings.csv.gz");
+        let rotation = Rotation { max_size: Some(1), keep: 1, ..Default::default() };
+        let mut file = RotatingFile::open(&path, false, rotation, None).unwrap();
+        file.write("a,1\n").unwrap();
+        file.write("b,2\n").unwrap();
+        drop(file);
+        let unzip = |path: PathBuf| {
+            let mut text = String::new();
+            io::Read::read_to_string(&mut flate2::read::MultiGzDecoder::new(File::open(path).unwrap()), &mut text).unwrap();
+            text
+        };
+        assert_eq!(unzip(dir.join("pings.csv.1.gz")), "a,1\n");
+        assert_eq!(unzip(path), "b,2\n");
+
+        let path = dir.join("pings.ndjson.zst");
+        let mut file = RotatingFile::open(&path, false, Rotation::default(), None).unwrap();
+        file.write("{}\n").unwrap();
+        file.finish().unwrap();
+        file.write("[]\n").unwrap();
+        drop(file);
+        assert_eq!(zstd::decode_all(File::open(&path).unwrap()).unwrap(), b"{}\n[]\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::SystemTime;
+use serde::Serialize;
+use crate::report::rfc3339;
+use crate::Backend;
+
+/// Where a report came from, for telling runs apart once the reports of
+/// many machines come together.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunInfo {
+    /// A random UUID, new with every run.
+    pub id: String,
+    pub started: String,
+    /// Left out while the run goes on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    pub version: &'static str,
+    /// The command line, without the program.
+    pub args: Vec<String>,
+    /// The backend asked for, such as `auto` or `native`.
+    pub backend: String,
+}
+
+impl RunInfo {
+    /// A run starting now.
+    pub fn start(backend: Backend) -> RunInfo {
+        RunInfo {
+            id: uuid::Uuid::new_v4().to_string(),
+            started: rfc3339(SystemTime::now()),
+            finished: None,
+            hostname: hostname(),
+            version: env!("CARGO_PKG_VERSION"),
+            args: std::env::args().skip(1).collect(),
+            backend: format!("{:?}", backend).to_lowercase(),
+        }
+    }
+
+    /// The same run, finished now.
+    pub fn finish(&self) -> RunInfo {
+        RunInfo { finished: Some(rfc3339(SystemTime::now())), ..self.clone() }
+    }
+}
+
+/// The name of the machine.
+#[cfg(unix)]
+pub fn hostname() -> Option<String> {
+    let mut name = [0u8; 256];
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } != 0 {
+        return None;
+    }
+    let length = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+    String::from_utf8(name[..length].to_vec()).ok()
+}
+
+#[cfg(not(unix))]
+pub fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
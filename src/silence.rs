@@ -0,0 +1,268 @@
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{de, Deserialize, Deserializer};
+use crate::report::rfc3339;
+
+// The fields of a schedule, with the values they take.
+const FIELDS: [(&str, u32, u32); 5] = [("minute", 0, 59), ("hour", 0, 23), ("day of month", 1, 31), ("month", 1, 12), ("day of week", 0, 7)];
+
+/// Times given the way cron takes them: minute, hour, day of month, month,
+/// and day of week (0 or 7 for Sunday), each `*`, a value, a range such as
+/// `1-5`, a list of those, or any of them with a step such as `*/15`.
+/// Times are in UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    // A bit per value each field matches.
+    fields: [u64; 5],
+    // Whether the day of month and day of week were left as `*`. When only
+    // one of them was, the other decides; otherwise either matching will do.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        if parts.len() != FIELDS.len() {
+            return Err(format!("invalid schedule '{}' (expected minute, hour, day of month, month, and day of week)", s));
+        }
+        let mut fields = [0u64; 5];
+        for (field, (part, (name, min, max))) in fields.iter_mut().zip(parts.iter().zip(FIELDS)) {
+            *field = parse_field(part, min, max).ok_or_else(|| format!("invalid {} '{}' in schedule '{}'", name, part, s))?;
+        }
+        // Sunday is both 0 and 7.
+        if fields[4] & 1 << 7 != 0 {
+            fields[4] |= 1;
+        }
+        Ok(Schedule { fields, any_day: parts[2] == "*", any_weekday: parts[4] == "*" })
+    }
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut bits = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&step| step > 0)?),
+            None => (item, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((first, last)) => (first.parse().ok()?, last.parse().ok()?),
+                None => {
+                    let value = range.parse().ok()?;
+                    // A step from a single value runs to the end, as in `5/10`.
+                    (value, if item.contains('/') { max } else { value })
+                },
+            },
+        };
+        if first < min || last > max || first > last {
+            return None;
+        }
+        for value in (first..=last).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Some(bits)
+}
+
+impl Schedule {
+    /// Whether the minute `time` falls in is one of the schedule's.
+    pub fn matches(&self, time: SystemTime) -> bool {
+        let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let timestamp = rfc3339(time);
+        let field = |range: std::ops::Range<usize>| timestamp.get(range).and_then(|value| value.parse::<u32>().ok()).unwrap_or(0);
+        // 1970-01-01 was a Thursday.
+        let weekday = ((secs / 86400 + 4) % 7) as u32;
+        let values = [field(14..16), field(11..13), field(8..10), field(5..7), weekday];
+        let hit = |index: usize| self.fields[index] & 1 << values[index] != 0;
+        let day = match (self.any_day, self.any_weekday) {
+            (true, _) => hit(4),
+            (false, true) => hit(2),
+            (false, false) => hit(2) || hit(4),
+        };
+        hit(0) && hit(1) && hit(3) && day
+    }
+}
+
+/// When a silence holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Window {
+    /// From one time until another, given as RFC 3339 timestamps or the
+    /// start of one, such as `2024-05-01T22:00`.
+    Range { from: String, until: String },
+    /// For a while every time the schedule comes around.
+    Recurring { schedule: Schedule, duration: Duration },
+}
+
+/// A stretch of time in which monitoring carries on, and state changes are
+/// logged and stored, but no alert goes out about the silenced targets.
+///
+/// ```toml
+/// [[silences]]
+/// from = "2024-05-01T22:00"
+/// until = "2024-05-02T02:00"
+///
+/// [[silences]]
+/// targets = ["db1"]
+/// schedule = "0 3 * * 0"
+/// duration = "2h"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "SilenceEntry")]
+pub struct Silence {
+    /// The targets silenced, as reports name them; every target when empty.
+    pub targets: Vec<String>,
+    pub window: Window,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SilenceEntry {
+    #[serde(default)]
+    targets: Vec<String>,
+    from: Option<String>,
+    until: Option<String>,
+    schedule: Option<Schedule>,
+    duration: Option<String>,
+}
+
+impl TryFrom<SilenceEntry> for Silence {
+    type Error = String;
+
+    fn try_from(entry: SilenceEntry) -> Result<Self, Self::Error> {
+        let window = match entry {
+            SilenceEntry { from: Some(from), until: Some(until), schedule: None, duration: None, .. } => {
+                for time in [&from, &until] {
+                    let well_formed = time.len() >= 10 && time.chars().all(|c| c.is_ascii_digit() || "-:.TZ".contains(c));
+                    if !well_formed {
+                        return Err(format!("invalid time '{}' (expected e.g. 2024-05-01T12:00)", time));
+                    }
+                }
+                Window::Range { from, until }
+            },
+            SilenceEntry { from: None, until: None, schedule: Some(schedule), duration: Some(duration), .. } => {
+                Window::Recurring { schedule, duration: parse_duration(&duration)? }
+            },
+            _ => return Err("a silence needs either from and until, or a schedule and a duration".to_string()),
+        };
+        Ok(Silence { targets: entry.targets, window })
+    }
+}
+
+/// Parses a duration such as `90m`, `2h`, or `1d`.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let units = [('m', 60), ('h', 3600), ('d', 86400)];
+    for (suffix, seconds) in units {
+        if let Some(amount) = value.strip_suffix(suffix).and_then(|amount| amount.parse::<u64>().ok()) {
+            return Ok(Duration::from_secs(amount * seconds));
+        }
+    }
+    Err(format!("invalid duration '{}' (expected e.g. 90m, 2h, or 1d)", value))
+}
+
+impl Silence {
+    /// Whether alerts about `target` are held back at `now`.
+    pub fn covers(&self, target: &str, now: SystemTime) -> bool {
+        if !self.targets.is_empty() && !self.targets.iter().any(|silenced| silenced == target) {
+            return false;
+        }
+        match &self.window {
+            // Timestamps of the same shape sort by time, as in the history.
+            Window::Range { from, until } => {
+                let now = rfc3339(now);
+                from.as_str() <= now.as_str() && now.as_str() < until.as_str()
+            },
+            // Held if the schedule came around in the last `duration`.
+            Window::Recurring { schedule, duration } => (0..duration.as_secs().div_ceil(60))
+                .filter_map(|minutes| now.checked_sub(Duration::from_secs(minutes * 60)))
+                .any(|time| schedule.matches(time)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Seconds since the epoch as a time.
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    // Sunday 2024-05-05 03:30 UTC.
+    const SUNDAY: u64 = 1714879800;
+
+    // The silences of a config file.
+    fn silences(toml: &str) -> Result<Vec<Silence>, toml::de::Error> {
+        #[derive(Deserialize)]
+        struct File {
+            silences: Vec<Silence>,
+        }
+        toml::from_str::<File>(toml).map(|file| file.silences)
+    }
+
+    #[test]
+    fn schedules_match_like_cron() {
+        let schedule: Schedule = "30 3 * * 0".parse().unwrap();
+        assert!(schedule.matches(at(SUNDAY)));
+        assert!(!schedule.matches(at(SUNDAY + 60)));
+        assert!(!schedule.matches(at(SUNDAY + 86400)));
+        // Sunday is 7 as well.
+        assert!("*/15 1-5 * * 7".parse::<Schedule>().unwrap().matches(at(SUNDAY)));
+        assert!("0,30 3 5 5 *".parse::<Schedule>().unwrap().matches(at(SUNDAY)));
+        // With both days given, either will do.
+        assert!("30 3 1 * 0".parse::<Schedule>().unwrap().matches(at(SUNDAY)));
+        assert!(!"30 3 1 * 1".parse::<Schedule>().unwrap().matches(at(SUNDAY)));
+    }
+
+    #[test]
+    fn rejects_bad_schedules() {
+        assert!("* * * *".parse::<Schedule>().unwrap_err().starts_with("invalid schedule"));
+        assert_eq!("60 * * * *".parse::<Schedule>().unwrap_err(), "invalid minute '60' in schedule '60 * * * *'");
+        for invalid in ["*/0 * * * *", "5-1 * * * *", "* * 0 * *", "* * * 13 *", "* * * * 8", "x * * * *"] {
+            assert!(invalid.parse::<Schedule>().is_err(), "{} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn ranges_hold_from_the_start_until_the_end() {
+        let silence = &silences("[[silences]]\nfrom = \"2024-05-01T22:00\"\nuntil = \"2024-05-02T02:00\"\n").unwrap()[0];
+        // 23:00 and 02:00 on the nights of the range.
+        assert!(silence.covers("db1", at(1714604400)));
+        assert!(!silence.covers("db1", at(1714615200)));
+        assert!(!silence.covers("db1", at(SUNDAY)));
+    }
+
+    #[test]
+    fn recurring_silences_last_their_duration() {
+        let silence = &silences("[[silences]]\ntargets = [\"db1\"]\nschedule = \"0 3 * * 0\"\nduration = \"2h\"\n").unwrap()[0];
+        assert!(silence.covers("db1", at(SUNDAY)));
+        assert!(!silence.covers("db2", at(SUNDAY)));
+        assert!(!silence.covers("db1", at(SUNDAY + 2 * 3600)));
+        assert!(!silence.covers("db1", at(SUNDAY - 3600)));
+    }
+
+    #[test]
+    fn silences_need_one_kind_of_window() {
+        let invalid = ["from = \"2024-05-01\"", "from = \"2024-05-01\"\nuntil = \"tomorrow\"", "schedule = \"0 3 * * 0\"\nduration = \"2w\""];
+        for entry in invalid {
+            assert!(silences(&format!("[[silences]]\n{}\n", entry)).is_err(), "{} parsed", entry);
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert!(parse_duration("2").is_err());
+    }
+}
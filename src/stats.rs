@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+use crate::report::{PacketStatistics, PingInfo, PingReport, RoundTripStatistics, SampleStatistics, SequenceStatistics, Trend};
+
+/// Replies that runs with an unlimited count keep; their round trip
+/// statistics are computed over these.
+pub const ROLLING_WINDOW: usize = 1000;
+
+// Adds a reply (or an error), dropping the oldest once an unlimited run
+// fills its window.
+pub(crate) fn keep<T>(items: &mut Vec<T>, item: T, unlimited: bool) {
+    if unlimited && items.len() >= ROLLING_WINDOW {
+        items.remove(0);
+    }
+    items.push(item);
+}
+
+pub fn packet_statistics(transmitted: u32, received: u32) -> PacketStatistics {
+    let loss_percent = if transmitted == 0 {
+        0.0
+    } else {
+        transmitted.saturating_sub(received) as f32 / transmitted as f32 * 100.0
+    };
+    PacketStatistics { transmitted, received, loss_percent }
+}
+
+// Marks the replies to the first `warmup` probes and takes those probes out
+// of the packet and round trip statistics. Returns the replies left.
+pub(crate) fn leave_out_warmup(report: &mut PingReport, warmup: u32) -> Vec<PingInfo> {
+    if warmup == 0 {
+        return report.pings.clone();
+    }
+    for ping in &mut report.pings {
+        ping.warmup = ping.icmp_seq <= warmup;
+    }
+    let counted: Vec<PingInfo> = report.pings.iter().filter(|ping| !ping.warmup).cloned().collect();
+    if let Some(packets) = &report.packets {
+        let answered = report.pings.iter().filter(|ping| ping.warmup && !ping.duplicate).count() as u32;
+        report.packets = Some(packet_statistics(packets.transmitted.saturating_sub(warmup), packets.received.saturating_sub(answered)));
+    }
+    report.trips = round_trip_statistics(&counted);
+    counted
+}
+
+pub fn round_trip_statistics(pings: &[PingInfo]) -> Option<RoundTripStatistics> {
+    if pings.is_empty() {
+        return None;
+    }
+    let times: Vec<f32> = pings.iter().map(|ping| ping.time).collect();
+    let min = times.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = times.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let avg = times.iter().sum::<f32>() / times.len() as f32;
+    let variance = times.iter().map(|time| (time - avg).powi(2)).sum::<f32>() / times.len() as f32;
+    let stddev = variance.sqrt();
+    Some(RoundTripStatistics { min, avg: round_ms(avg), max, stddev: round_ms(stddev) })
+}
+
+// Weight of each reply in the moving average, as in TCP's smoothed round
+// trip time (RFC 6298).
+const EWMA_WEIGHT: f32 = 0.125;
+// How far, as a fraction of the average, the moving average has to be from
+// it to count as a trend.
+const TREND_THRESHOLD: f32 = 0.1;
+
+// The moving average once `time` is in.
+pub(crate) fn ewma(average: Option<f32>, time: f32) -> f32 {
+    match average {
+        Some(average) => average + (time - average) * EWMA_WEIGHT,
+        None => time,
+    }
+}
+
+// Recent replies slower than the average of all of them mean degrading.
+pub(crate) fn trend(ewma: f32, avg: f32) -> Trend {
+    if ewma > avg * (1.0 + TREND_THRESHOLD) {
+        Trend::Degrading
+    } else if ewma < avg * (1.0 - TREND_THRESHOLD) {
+        Trend::Improving
+    } else {
+        Trend::Steady
+    }
+}
+
+// Nearest-rank percentile of sorted values.
+pub(crate) fn percentile(sorted: &[f32], percent: f32) -> f32 {
+    let rank = (percent / 100.0 * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+pub fn sample_statistics(pings: &[PingInfo]) -> Option<SampleStatistics> {
+    let trips = round_trip_statistics(pings)?;
+    let mut ordered: Vec<&PingInfo> = pings.iter().collect();
+    ordered.sort_by_key(|ping| ping.icmp_seq);
+    let mut jitter = 0.0;
+    for pair in ordered.windows(2) {
+        jitter += ((pair[1].time - pair[0].time).abs() - jitter) / 16.0;
+    }
+    let average = ordered.iter().filter(|ping| !ping.duplicate).fold(None, |average, ping| Some(ewma(average, ping.time)));
+    let average = average.unwrap_or(trips.avg);
+    let mut times: Vec<f32> = pings.iter().map(|ping| ping.time).collect();
+    times.sort_by(f32::total_cmp);
+    Some(SampleStatistics {
+        p50: percentile(&times, 50.0),
+        p90: percentile(&times, 90.0),
+        p95: percentile(&times, 95.0),
+        p99: percentile(&times, 99.0),
+        stddev: trips.stddev,
+        jitter: round_ms(jitter),
+        ewma: round_ms(average),
+        trend: trend(average, trips.avg),
+    })
+}
+
+// Replies are taken in the order they arrived.
+pub fn sequence_statistics(pings: &[PingInfo]) -> Option<SequenceStatistics> {
+    let mut seen = HashSet::new();
+    let mut highest = 0;
+    let mut statistics = SequenceStatistics::default();
+    for ping in pings {
+        if ping.duplicate || !seen.insert(ping.icmp_seq) {
+            statistics.duplicates += 1;
+            continue;
+        }
+        if ping.icmp_seq < highest {
+            statistics.reordered += 1;
+        }
+        highest = highest.max(ping.icmp_seq);
+    }
+    let first = *seen.iter().min()?;
+    statistics.gaps = (first..=highest).filter(|icmp_seq| !seen.contains(icmp_seq)).count() as u32;
+    Some(statistics)
+}
+
+// Hosts start their replies at one of these TTLs, most at 64 or 128.
+const INITIAL_TTLS: [u32; 4] = [32, 64, 128, 255];
+
+/// Estimates how many routers the replies passed through: each takes one
+/// off the TTL, which started at the nearest usual initial TTL above the
+/// highest one seen. Probes without a TTL, like TCP connections, give none.
+pub fn estimate_hops(pings: &[PingInfo]) -> Option<u32> {
+    let ttl = pings.iter().map(|ping| ping.ttl).filter(|ttl| *ttl > 0).max()?;
+    let initial = INITIAL_TTLS.into_iter().find(|initial| *initial >= ttl)?;
+    Some(initial - ttl)
+}
+
+// Matches the microsecond precision that ping prints.
+pub fn round_ms(value: f32) -> f32 {
+    (value * 1000.0).round() / 1000.0
+}
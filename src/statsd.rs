@@ -0,0 +1,94 @@
+use std::io;
+use tokio::net::{lookup_host, UdpSocket};
+use crate::report::PingReport;
+
+// Keeps datagrams within the usual MTU, as StatsD clients do.
+const MAX_DATAGRAM: usize = 1432;
+
+// Characters with a meaning in the StatsD protocol can't be in names or tags.
+fn sanitize(value: &str) -> String {
+    value.chars().map(|c| if matches!(c, ':' | '|' | '@' | '#' | ',' | ' ' | '\n') { '_' } else { c }).collect()
+}
+
+/// Renders the reports as StatsD metrics: an `rtt` timing per reply, and
+/// `sent` and `received` counters and a `loss_percent` gauge per target.
+/// With `tags`, the target and its labels go in DogStatsD tags; otherwise
+/// the target is part of every name, as in `multiping.example_com.rtt`.
+pub fn metrics(reports: &[PingReport], tags: bool) -> Vec<String> {
+    let mut lines = Vec::new();
+    for report in reports {
+        let (prefix, suffix) = if tags {
+            let mut tags = vec![format!("target:{}", sanitize(&report.destination))];
+            tags.extend(report.labels.iter().map(|(key, value)| format!("{}:{}", sanitize(key), sanitize(value))));
+            ("multiping".to_string(), format!("|#{}", tags.join(",")))
+        } else {
+            (format!("multiping.{}", sanitize(&report.destination).replace('.', "_")), String::new())
+        };
+        for ping in &report.pings {
+            lines.push(format!("{}.rtt:{}|ms{}", prefix, ping.time, suffix));
+        }
+        if let Some(packets) = &report.packets {
+            lines.push(format!("{}.sent:{}|c{}", prefix, packets.transmitted, suffix));
+            lines.push(format!("{}.received:{}|c{}", prefix, packets.received, suffix));
+            lines.push(format!("{}.loss_percent:{}|g{}", prefix, packets.loss_percent, suffix));
+        }
+    }
+    lines
+}
+
+/// Sends the metrics over UDP to the StatsD server at `address`
+/// (`host:port`), several to a datagram.
+pub async fn send(address: &str, lines: &[String]) -> io::Result<()> {
+    let server = lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{}: no address to send StatsD metrics to", address)))?;
+    let local = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    let mut datagram = String::new();
+    for line in lines {
+        if !datagram.is_empty() && datagram.len() + 1 + line.len() > MAX_DATAGRAM {
+            socket.send(datagram.as_bytes()).await?;
+            datagram.clear();
+        }
+        if !datagram.is_empty() {
+            datagram.push('\n');
+        }
+        datagram.push_str(line);
+    }
+    if !datagram.is_empty() {
+        socket.send(datagram.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::PacketStatistics;
+
+    fn report() -> PingReport {
+        PingReport {
+            destination: "web.example.com".to_string(),
+            labels: [("site".to_string(), "a:b".to_string())].into(),
+            packets: Some(PacketStatistics { transmitted: 4, received: 3, loss_percent: 25.0 }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn protocol_characters_are_replaced() {
+        assert_eq!(sanitize("a:b|c@d#e,f g"), "a_b_c_d_e_f_g");
+    }
+
+    #[test]
+    fn targets_go_in_names_or_tags() {
+        assert_eq!(metrics(&[report()], false), [
+            "multiping.web_example_com.sent:4|c",
+            "multiping.web_example_com.received:3|c",
+            "multiping.web_example_com.loss_percent:25|g",
+        ]);
+        assert_eq!(metrics(&[report()], true)[0], "multiping.sent:4|c|#target:web.example.com,site:a_b");
+    }
+}
@@ -0,0 +1,214 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::SystemTime;
+use serde::Serialize;
+use crate::output::{self, CSV_HEADER};
+use crate::report::{rfc3339, PingErrorKind, PingReport};
+use crate::rotate::RotatingFile;
+use crate::run::RunInfo;
+use crate::targets::Target;
+use crate::{EventKind, MultipingError, PingEvent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    Ndjson,
+    /// The rows of `--format csv`, as the replies come.
+    Csv,
+}
+
+impl StreamFormat {
+    /// The line every stream in this format starts with.
+    pub fn header(self) -> Option<&'static str> {
+        match self {
+            StreamFormat::Ndjson => None,
+            StreamFormat::Csv => Some(CSV_HEADER),
+        }
+    }
+}
+
+impl FromStr for StreamFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" => Ok(StreamFormat::Ndjson),
+            "csv" => Ok(StreamFormat::Csv),
+            _ => Err(format!("unknown stream format '{}' (expected ndjson or csv)", s)),
+        }
+    }
+}
+
+/// Something that happened during a run, as written to the event stream.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Reply {
+        target: &'a str,
+        icmp_seq: u32,
+        ttl: u32,
+        bytes: u32,
+        time: f32,
+        timestamp: String,
+    },
+    Timeout {
+        target: &'a str,
+        icmp_seq: u32,
+        timestamp: String,
+    },
+    IcmpError {
+        target: &'a str,
+        icmp_seq: Option<u32>,
+        from: &'a str,
+        kind: PingErrorKind,
+        message: &'a str,
+        timestamp: String,
+    },
+    Summary {
+        #[serde(flatten)]
+        report: &'a PingReport,
+    },
+    Error {
+        target: &'a str,
+        #[serde(flatten)]
+        error: &'a MultipingError,
+    },
+    /// The details of the run, first thing, with `--metadata`.
+    Run {
+        #[serde(flatten)]
+        run: &'a RunInfo,
+    },
+    /// The hops to a target differ from the previous trace, in monitor mode.
+    PathChange {
+        target: &'a str,
+        previous: &'a [Option<String>],
+        current: &'a [Option<String>],
+        timestamp: String,
+    },
+}
+
+impl Event<'_> {
+    /// The stream event for an event sent by `PingRunner::run_with_sender`,
+    /// if it is one that gets written.
+    pub fn from_event(event: &PingEvent) -> Option<Event<'_>> {
+        let target = event.target.as_str();
+        match &event.kind {
+            EventKind::Reply(ping) => Some(Event::Reply {
+                target,
+                icmp_seq: ping.icmp_seq,
+                ttl: ping.ttl,
+                bytes: ping.bytes_sent,
+                time: ping.time,
+                timestamp: ping.timestamp.clone(),
+            }),
+            EventKind::Lost { icmp_seq } => Some(Event::Timeout {
+                target,
+                icmp_seq: *icmp_seq,
+                timestamp: rfc3339(SystemTime::now()),
+            }),
+            EventKind::IcmpError(error) => Some(Event::IcmpError {
+                target,
+                icmp_seq: error.icmp_seq,
+                from: &error.from,
+                kind: error.kind,
+                message: &error.message,
+                timestamp: rfc3339(SystemTime::now()),
+            }),
+            EventKind::Finished(report) => Some(Event::Summary { report }),
+            EventKind::Failed(error) => Some(Event::Error { target, error }),
+            EventKind::PathChanged { previous, current } => Some(Event::PathChange {
+                target,
+                previous,
+                current,
+                timestamp: rfc3339(SystemTime::now()),
+            }),
+            EventKind::Started { .. } | EventKind::Statistics(_) => None,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Where a stream of events is written as they happen: standard output, or
+/// a file.
+#[derive(Debug)]
+pub struct Stream {
+    pub format: StreamFormat,
+    file: Option<RotatingFile>,
+}
+
+impl Stream {
+    pub fn stdout(format: StreamFormat) -> Stream {
+        if let Some(header) = format.header() {
+            println!("{}", header);
+        }
+        Stream { format, file: None }
+    }
+
+    /// A stream to `file`, which should have been opened with the header of
+    /// `format`.
+    pub fn file(format: StreamFormat, file: RotatingFile) -> Stream {
+        Stream { format, file: Some(file) }
+    }
+
+    /// Writes an event sent by `PingRunner::run_with_sender`, if it is one
+    /// that gets written: every one in ndjson, replies in csv.
+    pub fn write_event(&mut self, event: &PingEvent) -> io::Result<()> {
+        let line = match (self.format, &event.kind) {
+            (StreamFormat::Ndjson, _) => Event::from_event(event).map(|event| event.to_json()),
+            (StreamFormat::Csv, EventKind::Reply(ping)) => Some(output::csv_row(&event.target, ping)),
+            (StreamFormat::Csv, _) => None,
+        };
+        match line {
+            Some(line) => self.write(&[line]),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the summaries of a round, or its replies in csv.
+    pub fn write_round(&mut self, targets: &[Target], results: &[io::Result<PingReport>]) -> io::Result<()> {
+        let lines: Vec<String> = match self.format {
+            StreamFormat::Ndjson => targets
+                .iter()
+                .zip(results)
+                .map(|(target, result)| match result {
+                    Ok(report) => Event::Summary { report }.to_json(),
+                    Err(err) => Event::Error { target: &target.label(), error: &MultipingError::from(err) }.to_json(),
+                })
+                .collect(),
+            StreamFormat::Csv => results
+                .iter()
+                .flatten()
+                .flat_map(|report| report.pings.iter().map(|ping| output::csv_row(&report.destination, ping)))
+                .collect(),
+        };
+        self.write(&lines)?;
+        // Whole after every round, for whenever the monitor is stopped.
+        match &mut self.file {
+            Some(file) => file.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes an event that is not about a single ping, which only ndjson
+    /// has room for.
+    pub fn write_other(&mut self, event: &Event<'_>) -> io::Result<()> {
+        match self.format {
+            StreamFormat::Ndjson => self.write(&[event.to_json()]),
+            StreamFormat::Csv => Ok(()),
+        }
+    }
+
+    // As one entry, so that a file is never rotated halfway through.
+    fn write(&mut self, lines: &[String]) -> io::Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let entry: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+        match &mut self.file {
+            Some(file) => file.write(&entry),
+            None => io::stdout().write_all(entry.as_bytes()),
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::process::Command;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+#[cfg(target_os = "linux")]
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::process::Child;
+use tokio::time::Instant;
+use crate::parser::{ping_parser, PingLine};
+use crate::{stopped, IpVersion, PingFlavor, PingOptions, SshHost};
+use crate::error::MultipingError;
+use crate::event::{EventKind, PingEvent};
+use crate::report::{PingReport, RunClock};
+use crate::stats::{keep, packet_statistics, round_trip_statistics};
+
+// The program to run, and the option that picks the IP version, if any.
+// BSD and macOS ping only speaks IPv4; IPv6 needs the separate ping6 binary.
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+fn ping_program(version: IpVersion) -> (&'static str, Option<&'static str>) {
+    match version {
+        IpVersion::V6 => ("ping6", None),
+        _ => ("ping", None),
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", windows)))]
+fn ping_program(version: IpVersion) -> (&'static str, Option<&'static str>) {
+    match version {
+        IpVersion::Any => ("ping", None),
+        IpVersion::V4 => ("ping", Some("-4")),
+        IpVersion::V6 => ("ping", Some("-6")),
+    }
+}
+
+// Linux ping takes the reply timeout in seconds, the BSDs in milliseconds.
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+fn wait_argument(timeout: u32) -> u64 {
+    timeout as u64 * 1000
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", windows)))]
+fn wait_argument(timeout: u32) -> u64 {
+    timeout as u64
+}
+
+// Linux ping takes an address or an interface name for -I. The others can
+// only be given a source address, with -S.
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", windows)))]
+fn source_arguments(opts: &PingOptions) -> io::Result<Vec<String>> {
+    Ok(opts.source().map(|source| vec!["-I".to_string(), source]).unwrap_or_default())
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", windows))]
+fn source_arguments(opts: &PingOptions) -> io::Result<Vec<String>> {
+    if opts.source_ip.is_none() && opts.interface.is_some() {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "the system ping cannot send from an interface, only from a source address"));
+    }
+    Ok(opts.source_ip.map(|address| vec!["-S".to_string(), address.to_string()]).unwrap_or_default())
+}
+
+// The options that set the type of service byte and the time to live.
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", windows)))]
+const TOS_OPTION: &str = "-Q";
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", windows)))]
+const TTL_OPTION: &str = "-t";
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+const TOS_OPTION: &str = "-z";
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+const TTL_OPTION: &str = "-m";
+
+// Whether ping gets -O, which makes iputils say when a probe got no reply
+// in time; without it, lost probes only show in the statistics at the end.
+// BusyBox ping, which Linux systems such as Alpine have instead, does not
+// take it, so unless the flavor says iputils, the local ping must not be
+// BusyBox. What runs through ssh cannot be looked at. Each ping is looked
+// at once, rather than every time it is started.
+#[cfg(target_os = "linux")]
+fn report_lost(opts: &PingOptions, program: &str) -> bool {
+    static LOOKED_AT: OnceLock<Mutex<HashMap<PathBuf, bool>>> = OnceLock::new();
+    match opts.ping_flavor {
+        PingFlavor::Iputils => true,
+        PingFlavor::Auto if opts.via.is_none() => {
+            let mut looked_at = LOOKED_AT.get_or_init(Mutex::default).lock().unwrap();
+            let ping = opts.ping_path.clone().unwrap_or_else(|| PathBuf::from(program));
+            *looked_at.entry(ping).or_insert_with(|| {
+                let path = opts.ping_path.clone().or_else(|| {
+                    let path = std::env::var_os("PATH")?;
+                    std::env::split_paths(&path).map(|dir| dir.join(program)).find(|path| path.is_file())
+                });
+                path.and_then(|path| std::fs::canonicalize(path).ok())
+                    .is_some_and(|path| path.file_name().is_some_and(|name| !name.to_string_lossy().starts_with("busybox")))
+            })
+        },
+        _ => false,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn report_lost(opts: &PingOptions, _: &str) -> bool {
+    opts.ping_flavor == PingFlavor::Iputils
+}
+
+// How often unlimited runs check whether they were stopped.
+pub(crate) const STOP_POLL: Duration = Duration::from_millis(200);
+
+// Arguments go to ping as they are, never through a shell. The target is
+// the address it resolved to, which ping cannot take for an option.
+#[cfg(not(windows))]
+fn ping_command(address: &str, opts: &PingOptions) -> io::Result<Command> {
+    let (program, version) = ping_program(opts.ip_version);
+    let mut child = match &opts.ping_path {
+        Some(path) => Command::new(path),
+        None => Command::new(program),
+    };
+    child.args(version);
+    if !opts.unlimited() {
+        child.arg("-c").arg(opts.count.to_string());
+    }
+    child.arg("-i").arg(opts.interval.to_string());
+    child.arg("-s").arg(opts.size.to_string());
+    child.arg("-W").arg(wait_argument(opts.timeout).to_string());
+    if report_lost(opts, program) {
+        child.arg("-O");
+    }
+    child.args(source_arguments(opts)?);
+    if let Some(dscp) = opts.dscp {
+        child.arg(TOS_OPTION).arg(dscp.tos().to_string());
+    }
+    if let Some(ttl) = opts.ttl {
+        child.arg(TTL_OPTION).arg(ttl.to_string());
+    }
+    child.args(&opts.ping_args);
+    child.arg(address);
+    tracing::debug!(command = ?child.as_std(), "ping command");
+    // The parser expects the untranslated output.
+    child.env("LC_ALL", "C");
+    Ok(child)
+}
+
+// Windows ping has no interval option and always waits a second between
+// probes, and ignores the type of service it is given.
+#[cfg(windows)]
+fn ping_command(address: &str, opts: &PingOptions) -> io::Result<Command> {
+    if opts.dscp.is_some() {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "Windows ping cannot mark probes with a DSCP"));
+    }
+    let mut child = match &opts.ping_path {
+        Some(path) => Command::new(path),
+        None => Command::new("ping"),
+    };
+    if opts.unlimited() {
+        child.arg("-t");
+    } else {
+        child.arg("-n").arg(opts.count.to_string());
+    }
+    child.arg("-w").arg((opts.timeout as u64 * 1000).to_string());
+    child.arg("-l").arg(opts.size.to_string());
+    if let Some(ttl) = opts.ttl {
+        child.arg("-i").arg(ttl.to_string());
+    }
+    match opts.ip_version {
+        IpVersion::Any => (),
+        IpVersion::V4 => { child.arg("-4"); },
+        IpVersion::V6 => { child.arg("-6"); },
+    }
+    child.args(source_arguments(opts)?);
+    child.args(&opts.ping_args);
+    child.arg(address);
+    Ok(child)
+}
+
+// The same ping, run on `host` by ssh, which hands the remote shell one
+// line: the environment and arguments of `ping`, each quoted. ssh never
+// asks for a password, which there would be no one to type.
+fn ssh_command(host: &SshHost, ping: &Command) -> Command {
+    let ping = ping.as_std();
+    let environment = ping.get_envs().filter_map(|(name, value)| Some(format!("{}={}", name.to_string_lossy(), shell_quote(&value?.to_string_lossy()))));
+    let program = std::iter::once(ping.get_program()).chain(ping.get_args()).map(|arg| shell_quote(&arg.to_string_lossy()));
+    let line: Vec<String> = environment.chain(program).collect();
+    let mut child = Command::new("ssh");
+    child.args(["-T", "-o", "BatchMode=yes"]);
+    if let Some(port) = host.port {
+        child.arg("-p").arg(port.to_string());
+    }
+    if let Some(user) = &host.user {
+        child.arg("-l").arg(user);
+    }
+    child.arg("--").arg(&host.host).arg(line.join(" "));
+    tracing::debug!(command = ?child.as_std(), "ssh command");
+    child
+}
+
+// Leaves words that mean the same to a POSIX shell as they are, and puts
+// the rest in single quotes.
+fn shell_quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/' | ':' | '@' | '%' | '+' | ',');
+    if !word.is_empty() && word.chars().all(plain) {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+// Asks ping (or fping) to stop and print its statistics, as Ctrl-C does.
+#[cfg(unix)]
+pub(crate) fn interrupt(child: &mut Child) {
+    if let Some(pid) = child.id() {
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGINT);
+        }
+    }
+}
+
+// Windows ping has nothing to signal, so it is just stopped.
+#[cfg(not(unix))]
+pub(crate) fn interrupt(child: &mut Child) {
+    let _ = child.start_kill();
+}
+
+/// Where the output of the ping of `target` is recorded in `dir`.
+pub(crate) fn recording_path(dir: &Path, target: &str) -> PathBuf {
+    let name: String = target.chars().map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-') { c } else { '_' }).collect();
+    dir.join(format!("{}.txt", name))
+}
+
+// The output of a ping that runs, or of one that was recorded.
+async fn open_output(target: &str, destination: &str, opts: &PingOptions) -> io::Result<(Option<Child>, Box<dyn AsyncBufRead + Unpin + Send>)> {
+    if let Some(dir) = &opts.replay {
+        let path = recording_path(dir, target);
+        let file = File::open(&path)
+            .await
+            .map_err(|err| MultipingError::SpawnFailed(format!("{}: cannot replay {} ({})", target, path.display(), err)))?;
+        tracing::debug!(%target, path = %path.display(), "replaying ping output");
+        return Ok((None, Box::new(BufReader::new(file))));
+    }
+    let mut command = ping_command(destination, opts)?;
+    if let Some(via) = &opts.via {
+        command = ssh_command(via, &command);
+    }
+    let mut child = command
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| MultipingError::SpawnFailed(format!("{}: cannot run ping ({})", target, err)))?;
+    tracing::debug!(%target, %destination, pid = child.id(), "ping started");
+    let stdout = child.stdout.take().ok_or_else(|| MultipingError::SpawnFailed(format!("{}: no output from ping", target)))?;
+    Ok((Some(child), Box::new(BufReader::new(stdout))))
+}
+
+async fn create_recording(dir: &Path, target: &str) -> io::Result<File> {
+    tokio::fs::create_dir_all(dir).await?;
+    let path = recording_path(dir, target);
+    File::create(&path)
+        .await
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: cannot record to {} ({})", target, path.display(), err)))
+}
+
+/// Pings `address`, or, on the machine `opts.via` names, `host` itself
+/// when it was not given as an address, so that it is looked up there.
+pub async fn execute_ping(target: String, host: &str, address: IpAddr, opts: &PingOptions, clock: RunClock, sender: mpsc::Sender<PingEvent>) -> Result<PingReport, io::Error> {
+    let (count, timeout) = (opts.count, opts.timeout);
+    // ping sends a probe per interval, then waits up to the timeout for the last reply.
+    let deadline = Instant::now() + opts.probe_interval() * count + Duration::from_secs(timeout as u64);
+    let mut recording = match &opts.record {
+        Some(dir) => Some(create_recording(dir, &target).await?),
+        None => None,
+    };
+    let destination = match opts.via {
+        Some(_) if address.is_unspecified() => host.to_string(),
+        _ => address.to_string(),
+    };
+    let (mut child, output) = open_output(&target, &destination, opts).await?;
+    let _ = sender.send(PingEvent::new(&target, EventKind::Started { address })).await;
+    let mut timed_out = false;
+    let mut interrupted = false;
+    let mut probes = 0;
+    let mut received = 0;
+    let mut pings = Vec::new();
+    let mut errors = Vec::new();
+    let mut packets = None;
+    let mut trips = None;
+    let mut lines = output.lines();
+    let mut parser = ping_parser(opts.ping_flavor);
+    loop {
+        if opts.unlimited() && stopped() && !interrupted {
+            if let Some(child) = &mut child {
+                interrupt(child);
+            }
+            interrupted = true;
+        }
+        let line = if opts.unlimited() {
+            match tokio::time::timeout(STOP_POLL, lines.next_line()).await {
+                Ok(line) => line?,
+                Err(_) => continue,
+            }
+        } else {
+            match tokio::time::timeout_at(deadline, lines.next_line()).await {
+                Ok(line) => line?,
+                Err(_) => {
+                    timed_out = true;
+                    break;
+                },
+            }
+        };
+        let Some(line) = line else {
+            break;
+        };
+        tracing::trace!(%target, %line, "ping output");
+        if let Some(recording) = &mut recording {
+            recording.write_all(format!("{}\n", line).as_bytes()).await?;
+        }
+        match parser.parse(&line) {
+            Some(PingLine::Reply(mut ping)) => {
+                probes = probes.max(ping.icmp_seq);
+                clock.stamp(&mut ping);
+                let _ = sender.send(PingEvent::new(&target, EventKind::Reply(ping.clone()))).await;
+                received += !ping.duplicate as u32;
+                keep(&mut pings, ping, opts.unlimited());
+            },
+            Some(PingLine::Lost(icmp_seq)) => {
+                probes = probes.max(icmp_seq);
+                let _ = sender.send(PingEvent::new(&target, EventKind::Lost { icmp_seq })).await;
+            },
+            Some(PingLine::Error(error)) => {
+                probes = probes.max(error.icmp_seq.unwrap_or(0));
+                let _ = sender.send(PingEvent::new(&target, EventKind::IcmpError(error.clone()))).await;
+                keep(&mut errors, error, opts.unlimited());
+            },
+            Some(PingLine::Packets(statistics)) => {
+                let _ = sender.send(PingEvent::new(&target, EventKind::Statistics(statistics.clone()))).await;
+                packets = Some(statistics);
+            },
+            Some(PingLine::Trips(statistics)) => trips = Some(statistics),
+            None if !line.trim().is_empty() => tracing::debug!(%target, %line, "unparsed ping output"),
+            None => (),
+        }
+    }
+    // Windows and BusyBox leave out some round trip statistics, so the whole
+    // set comes from the samples.
+    if trips.is_none() {
+        trips = round_trip_statistics(&pings);
+    }
+    if let Some(recording) = &mut recording {
+        recording.flush().await?;
+    }
+    if timed_out {
+        if let Some(child) = &mut child {
+            child.kill().await?;
+        }
+        let packets = packets.unwrap_or_else(|| packet_statistics(count, received));
+        return Ok(PingReport {
+            destination: target,
+            pings,
+            errors,
+            packets: Some(packets),
+            trips,
+            timed_out,
+            ..Default::default()
+        });
+    }
+    // A replay has no exit status to go by.
+    let failed = match &mut child {
+        Some(child) => {
+            let status = child.wait().await?;
+            tracing::debug!(%target, %status, "ping exited");
+            (!status.success()).then_some(status)
+        },
+        None => None,
+    };
+    // ping exits with an error when probes go unanswered, which its
+    // statistics already tell; without them, it failed to run at all.
+    let packets = match packets {
+        Some(packets) => packets,
+        None if interrupted => packet_statistics(probes, received),
+        None => return Err(match failed {
+            Some(status) => MultipingError::SpawnFailed(format!("{} failed with exit code: {}", target, status)),
+            None => MultipingError::ParseFailure(format!("{}: no statistics in the ping output", target)),
+        }.into()),
+    };
+    Ok(PingReport {
+        destination: target,
+        pings,
+        errors,
+        packets: Some(packets),
+        trips,
+        timed_out,
+        ..Default::default()
+    })
+}
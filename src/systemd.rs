@@ -0,0 +1,168 @@
+use std::env;
+use std::fmt;
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::Path;
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+const JOURNAL: &str = "/run/systemd/journal/socket";
+
+/// Sends `state`, such as `READY=1`, to the service manager that started
+/// the process, if one asked to be told. Returns whether one did.
+pub fn notify(state: &str) -> io::Result<bool> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let path = path.to_string_lossy();
+    // A leading @ is for the abstract namespace.
+    let address = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(path.as_ref())?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(true)
+}
+
+// Half the watchdog timeout the service manager set for this process, as
+// often as it should hear from it.
+fn watchdog() -> Option<Duration> {
+    let pid = env::var("WATCHDOG_PID").ok();
+    if pid.is_some_and(|pid| pid.parse() != Ok(std::process::id())) {
+        return None;
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Tells systemd, under `Type=notify`, that the targets are being pinged,
+/// and keeps its watchdog fed as long as the runtime goes on.
+pub fn ready() {
+    match notify("READY=1") {
+        Ok(true) => (),
+        Ok(false) => return,
+        Err(err) => {
+            eprintln!("Cannot notify systemd: {}", err);
+            return;
+        },
+    }
+    if let Some(every) = watchdog() {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                let _ = notify("WATCHDOG=1");
+            }
+        });
+    }
+}
+
+/// A unit file that runs `command` as a `Type=notify` service from
+/// `directory`, which relative paths in it are taken from.
+pub fn unit(description: &str, command: &[String], directory: &Path) -> String {
+    let command: Vec<String> = command.iter().map(|arg| quote(arg)).collect();
+    format!(
+        "[Unit]\n\
+         Description={}\n\
+         Wants=network-online.target\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={}\n\
+         WorkingDirectory={}\n\
+         WatchdogSec=30\n\
+         Restart=on-failure\n\
+         AmbientCapabilities=CAP_NET_RAW\n\
+         \n\
+         [Install]\n\
+         WantedBy=multi-user.target\n",
+        description,
+        command.join(" "),
+        quote(&directory.to_string_lossy()),
+    )
+}
+
+// Quotes an argument of ExecStart, where % and $ are expanded otherwise.
+fn quote(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+    if !escaped.is_empty() && !escaped.contains(|c: char| c.is_whitespace() || "\"'\\;".contains(c)) {
+        return escaped;
+    }
+    format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Sends the log to the systemd journal, with the fields of every event as
+/// journal fields (`target` as `TARGET`, and so on) next to `MESSAGE`.
+pub struct Journald {
+    socket: UnixDatagram,
+}
+
+impl Journald {
+    pub fn new() -> io::Result<Journald> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(JOURNAL).map_err(|err| io::Error::new(err.kind(), format!("cannot reach the journal at {}: {}", JOURNAL, err)))?;
+        Ok(Journald { socket })
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Journald {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let metadata = event.metadata();
+        let priority = match *metadata.level() {
+            Level::ERROR => "3",
+            Level::WARN => "4",
+            Level::INFO => "6",
+            _ => "7",
+        };
+        let mut fields = Fields(Vec::new());
+        fields.append("PRIORITY", priority);
+        fields.append("SYSLOG_IDENTIFIER", "multiping");
+        fields.append("CODE_MODULE", metadata.module_path().unwrap_or_default());
+        event.record(&mut fields);
+        let _ = self.socket.send(&fields.0);
+    }
+}
+
+// The fields of an entry, in the journal's native protocol.
+struct Fields(Vec<u8>);
+
+impl Fields {
+    fn append(&mut self, name: &str, value: &str) {
+        self.0.extend_from_slice(name.as_bytes());
+        // Values with line breaks are sent with their length instead.
+        if value.contains('\n') {
+            self.0.push(b'\n');
+            self.0.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            self.0.push(b'=');
+        }
+        self.0.extend_from_slice(value.as_bytes());
+        self.0.push(b'\n');
+    }
+
+    fn append_field(&mut self, field: &Field, value: &str) {
+        let name = match field.name() {
+            "message" => "MESSAGE".to_string(),
+            // Journal fields are upper case, and those starting with an
+            // underscore are the journal's own.
+            name => name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect::<String>().trim_start_matches('_').to_string(),
+        };
+        if !name.is_empty() {
+            self.append(&name, value);
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.append_field(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.append_field(field, &format!("{:?}", value));
+    }
+}
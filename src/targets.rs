@@ -0,0 +1,447 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::str::FromStr;
+use serde::Deserialize;
+use crate::resolve::resolve_all;
+use crate::{probe_host, PingOptions, Probe};
+
+/// A host to ping, with optional settings that override the run's options.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    pub host: String,
+    /// Friendly name shown in reports instead of `host`.
+    pub name: Option<String>,
+    /// Group whose summary the target counts towards.
+    pub group: Option<String>,
+    /// Arbitrary key/value pairs carried into the reports.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Address to ping instead of resolving `host`.
+    pub address: Option<IpAddr>,
+    pub count: Option<u32>,
+    pub timeout: Option<u32>,
+    pub interval: Option<f32>,
+    pub size: Option<usize>,
+    /// Network interface to send the probes through instead of the run's.
+    pub interface: Option<String>,
+    /// What to probe the target with instead of the run's probe.
+    pub probe: Option<Probe>,
+    pub port: Option<u16>,
+}
+
+impl Target {
+    pub fn new<S: Into<String>>(host: S) -> Target {
+        Target { host: host.into(), ..Default::default() }
+    }
+
+    /// How the target is named in reports and events. Targets probed in a
+    /// way of their own say how, so that a host probed two ways is told apart.
+    pub fn label(&self) -> String {
+        let name = self.name.as_ref().unwrap_or(&self.host);
+        let name = match self.address {
+            Some(address) => format!("{} ({})", name, address),
+            None => name.clone(),
+        };
+        let name = match self.probe_label() {
+            Some(probe) => format!("{} over {}", name, probe),
+            None => name,
+        };
+        match &self.interface {
+            Some(interface) => format!("{} via {}", name, interface),
+            None => name,
+        }
+    }
+
+    // The probe and port of the target's own, like `tcp:443`, if it has any.
+    fn probe_label(&self) -> Option<String> {
+        match (self.probe, self.port) {
+            (Some(probe @ (Probe::Tcp | Probe::Udp | Probe::Tls | Probe::Dns)), port) => {
+                Some(format!("{:?}:{}", probe, port.unwrap_or(probe.default_port())).to_lowercase())
+            },
+            (Some(probe @ (Probe::Icmp | Probe::Http | Probe::Arp)), _) => Some(format!("{:?}", probe).to_lowercase()),
+            (None, Some(port)) => Some(format!("port {}", port)),
+            (None, None) => None,
+        }
+    }
+
+    /// The options to ping this target with.
+    pub fn options(&self, base: &PingOptions) -> PingOptions {
+        let mut opts = base.clone();
+        if let Some(count) = self.count {
+            opts.count = count;
+        }
+        if let Some(timeout) = self.timeout {
+            opts.timeout = timeout;
+        }
+        if let Some(interval) = self.interval {
+            opts.interval = interval;
+        }
+        if let Some(size) = self.size {
+            opts.size = size;
+        }
+        // An interface of its own replaces the run's source, whichever it was.
+        if let Some(interface) = &self.interface {
+            opts.interface = Some(interface.clone());
+            opts.source_ip = None;
+        }
+        // The run's port is for its probe; one of the target's own goes to its usual port.
+        if let Some(probe) = self.probe {
+            opts.probe = probe;
+            opts.port = None;
+        }
+        if let Some(port) = self.port {
+            opts.port = Some(port);
+        }
+        // Warm-up probes come on top of the counted ones.
+        if opts.count > 0 {
+            opts.count += opts.warmup;
+        }
+        opts
+    }
+}
+
+// A name for `name=host`: URLs, which may contain `=` too, never look like one.
+fn is_name(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Parses `[name=]host;key=value;...`, where the keys are `count`, `timeout`,
+/// `interval`, `size`, `interface`, `probe`, `port`, `group`, and `label.<key>`.
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(';');
+        let mut host = parts.next().unwrap_or("").trim();
+        let mut name = None;
+        if let Some((alias, aliased)) = host.split_once('=').filter(|(alias, _)| is_name(alias.trim())) {
+            name = Some(alias.trim().to_string());
+            host = aliased.trim();
+        }
+        if host.is_empty() {
+            return Err(format!("missing host in target '{}'", s));
+        }
+        let mut target = Target { name, ..Target::new(host) };
+        for part in parts {
+            let Some((key, value)) = part.split_once('=') else {
+                return Err(format!("expected key=value in target '{}', found '{}'", s, part));
+            };
+            let value = value.trim();
+            let invalid = || format!("invalid {} '{}' in target '{}'", key.trim(), value, s);
+            match key.trim() {
+                "count" => target.count = Some(value.parse().map_err(|_| invalid())?),
+                "timeout" => target.timeout = Some(value.parse().map_err(|_| invalid())?),
+                "interval" => target.interval = Some(value.parse().map_err(|_| invalid())?),
+                "size" => target.size = Some(value.parse().map_err(|_| invalid())?),
+                "interface" => target.interface = Some(value.to_string()),
+                "probe" => target.probe = Some(value.parse().map_err(|_| invalid())?),
+                "port" => target.port = Some(value.parse().map_err(|_| invalid())?),
+                "group" => target.group = Some(value.to_string()),
+                key if key.starts_with("label.") && key.len() > "label.".len() => {
+                    target.labels.insert(key["label.".len()..].to_string(), value.to_string());
+                },
+                other => return Err(format!(
+                    "unknown option '{}' in target '{}' (expected count, timeout, interval, size, interface, probe, port, group, or label.<key>)",
+                    other, s
+                )),
+            }
+        }
+        Ok(target)
+    }
+}
+
+/// Reads newline-separated targets, skipping blank lines and `#` comments.
+/// Lines may carry per-target options, as in `host;count=50`.
+pub fn read_targets<R: BufRead>(reader: R) -> io::Result<Vec<Target>> {
+    let mut targets = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        targets.push(line.parse().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?);
+    }
+    Ok(targets)
+}
+
+pub fn read_targets_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<Target>> {
+    read_targets(BufReader::new(File::open(path)?))
+}
+
+fn parse_cidr(target: &str) -> Option<(IpAddr, u32)> {
+    let (address, prefix) = target.split_once('/')?;
+    let address: IpAddr = address.parse().ok()?;
+    let prefix: u32 = prefix.parse().ok()?;
+    let bits = if address.is_ipv4() { 32 } else { 128 };
+    if prefix > bits {
+        return None;
+    }
+    Some((address, prefix))
+}
+
+// Network and broadcast addresses are left out, except for point-to-point
+// (/31, /127) and single-host networks.
+fn host_range(address: IpAddr, prefix: u32) -> (u128, u128) {
+    let (value, bits) = match address {
+        IpAddr::V4(ip) => (u32::from(ip) as u128, 32),
+        IpAddr::V6(ip) => (u128::from(ip), 128),
+    };
+    let host_bits = bits - prefix;
+    let mask = if host_bits == 128 { u128::MAX } else { (1u128 << host_bits) - 1 };
+    let network = value & !mask;
+    let last = network | mask;
+    if host_bits <= 1 {
+        (network, last)
+    } else {
+        (network + 1, last - 1)
+    }
+}
+
+/// Replaces every `address/prefix` target with the host addresses of that
+/// network. Fails if those add up to more than `max_hosts`.
+pub fn expand_targets(targets: Vec<Target>, max_hosts: usize) -> io::Result<Vec<Target>> {
+    let mut expanded = Vec::new();
+    let mut hosts = 0u128;
+    for target in targets {
+        let Some((address, prefix)) = parse_cidr(&target.host) else {
+            expanded.push(target);
+            continue;
+        };
+        let (first, last) = host_range(address, prefix);
+        hosts += last - first + 1;
+        if hosts > max_hosts as u128 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} makes the network targets exceed {} hosts (see --max-hosts)", target.host, max_hosts),
+            ));
+        }
+        for value in first..=last {
+            let host = match address {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(value as u32)),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(value)),
+            };
+            // One name for the whole network would make its hosts indistinguishable.
+            expanded.push(Target { host: host.to_string(), name: None, ..target.clone() });
+        }
+    }
+    Ok(expanded)
+}
+
+// Why `host` is neither an address nor a name a host could have, if so.
+fn check_host(host: &str) -> Result<(), String> {
+    if host.parse::<IpAddr>().is_ok() {
+        return Ok(());
+    }
+    // Link-local IPv6 addresses may name their interface, as in fe80::1%eth0.
+    if let Some((address, zone)) = host.split_once('%') {
+        if address.parse::<Ipv6Addr>().is_ok() && !zone.is_empty() {
+            return Ok(());
+        }
+    }
+    let name = host.strip_suffix('.').unwrap_or(host);
+    if name.is_empty() {
+        return Err("empty host name".to_string());
+    }
+    if name.len() > 253 {
+        return Err("the name is longer than 253 characters".to_string());
+    }
+    for label in name.split('.') {
+        if label.is_empty() {
+            return Err("the name has an empty label".to_string());
+        }
+        if label.len() > 63 {
+            return Err(format!("label '{}' is longer than 63 characters", label));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(format!("label '{}' starts or ends with a hyphen", label));
+        }
+        // Underscores are not allowed in host names, but show up in DNS anyway.
+        if let Some(c) = label.chars().find(|c| !c.is_ascii_alphanumeric() && *c != '-' && *c != '_') {
+            return Err(format!("'{}' is not allowed in host names", c));
+        }
+    }
+    // Top-level domains are never numeric, so this was meant as an address.
+    if name.rsplit('.').next().is_some_and(|label| label.chars().all(|c| c.is_ascii_digit())) {
+        return Err("not a valid IP address".to_string());
+    }
+    Ok(())
+}
+
+/// Checks, before anything is pinged, that every target is an address or a
+/// name a host could have (for HTTP probes, in a valid URL). Returns the
+/// valid targets, and the others with what is wrong with them.
+pub fn validate_targets(targets: Vec<Target>, opts: &PingOptions) -> (Vec<Target>, Vec<(Target, String)>) {
+    let mut valid = Vec::new();
+    let mut invalid = Vec::new();
+    for target in targets {
+        let checked = probe_host(&target.host, &target.options(opts))
+            .map_err(|err| err.to_string())
+            .and_then(|host| check_host(&host));
+        match checked {
+            Ok(()) => valid.push(target),
+            Err(reason) => invalid.push((target, reason)),
+        }
+    }
+    (valid, invalid)
+}
+
+/// Drops every target named like an earlier one, as the reports could not
+/// tell them apart; the name includes a probe of the target's own. Returns
+/// the targets left, and the ones dropped.
+pub fn dedupe_targets(targets: Vec<Target>) -> (Vec<Target>, Vec<Target>) {
+    let mut seen = HashSet::new();
+    targets.into_iter().partition(|target| seen.insert(target.label()))
+}
+
+/// Replaces every target whose name resolves to several addresses with one
+/// target per address. Names that do not resolve are kept, so that pinging
+/// them reports the error.
+pub async fn expand_addresses(targets: Vec<Target>, opts: &PingOptions) -> Vec<Target> {
+    let lookups: Vec<_> = targets
+        .iter()
+        .map(|target| {
+            let opts = target.options(opts);
+            let host = probe_host(&target.host, &opts);
+            let pinned = target.address.is_some();
+            tokio::spawn(async move {
+                if pinned {
+                    return None;
+                }
+                resolve_all(&host.ok()?, opts.ip_version, opts.reply_timeout()).await.ok()
+            })
+        })
+        .collect();
+    let mut expanded = Vec::new();
+    for (target, lookup) in targets.into_iter().zip(lookups) {
+        match lookup.await.ok().flatten() {
+            Some(addresses) if addresses.len() > 1 => {
+                expanded.extend(addresses.into_iter().map(|address| Target { address: Some(address), ..target.clone() }));
+            },
+            _ => expanded.push(target),
+        }
+    }
+    expanded
+}
+
+/// Replaces every target with one per interface, pinged through it, so the
+/// interfaces can be compared. A target's copies follow each other, in the
+/// order of `interfaces`.
+pub fn per_interface(targets: Vec<Target>, interfaces: &[String]) -> Vec<Target> {
+    targets
+        .into_iter()
+        .flat_map(|target| {
+            interfaces
+                .iter()
+                .map(move |interface| Target { interface: Some(interface.clone()), ..target.clone() })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(targets: &[Target]) -> Vec<&str> {
+        targets.iter().map(|target| target.host.as_str()).collect()
+    }
+
+    #[test]
+    fn reads_lists_without_blanks_and_comments() {
+        let targets = read_targets("# routers\n10.0.0.1\n\n  gw=10.0.0.254;count=3  \n".as_bytes()).unwrap();
+        assert_eq!(hosts(&targets), ["10.0.0.1", "10.0.0.254"]);
+        assert_eq!(targets[1].count, Some(3));
+        assert_eq!(read_targets("10.0.0.1;count=x\n".as_bytes()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn expands_networks_without_network_and_broadcast() {
+        let targets = expand_targets(vec!["net=10.0.0.0/30;count=2".parse().unwrap(), Target::new("gw")], 16).unwrap();
+        assert_eq!(hosts(&targets), ["10.0.0.1", "10.0.0.2", "gw"]);
+        assert_eq!((targets[0].name.as_ref(), targets[0].count), (None, Some(2)));
+        assert_eq!(hosts(&expand_targets(vec![Target::new("10.0.0.0/31")], 16).unwrap()), ["10.0.0.0", "10.0.0.1"]);
+        assert_eq!(hosts(&expand_targets(vec![Target::new("2001:db8::/126")], 16).unwrap()), ["2001:db8::1", "2001:db8::2"]);
+        // A prefix too long for the address is not a network.
+        assert_eq!(hosts(&expand_targets(vec![Target::new("10.0.0.0/33")], 16).unwrap()), ["10.0.0.0/33"]);
+    }
+
+    #[test]
+    fn refuses_too_many_hosts() {
+        let err = expand_targets(vec![Target::new("10.0.0.0/24")], 100).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn parses_names_and_overrides() {
+        let target: Target = "gw=10.0.0.1;count=50;timeout=2;interval=0.5;size=1400;probe=tcp;port=22;group=dc1;label.rack=r7".parse().unwrap();
+        assert_eq!(target.name.as_deref(), Some("gw"));
+        assert_eq!(target.host, "10.0.0.1");
+        assert_eq!((target.count, target.timeout, target.interval, target.size), (Some(50), Some(2), Some(0.5), Some(1400)));
+        assert_eq!((target.probe, target.port), (Some(Probe::Tcp), Some(22)));
+        assert_eq!(target.group.as_deref(), Some("dc1"));
+        assert_eq!(target.labels.get("rack").map(String::as_str), Some("r7"));
+    }
+
+    #[test]
+    fn rejects_bad_targets() {
+        assert_eq!("".parse::<Target>().unwrap_err(), "missing host in target ''");
+        assert_eq!("10.0.0.1;count".parse::<Target>().unwrap_err(), "expected key=value in target '10.0.0.1;count', found 'count'");
+        assert_eq!("10.0.0.1;count=many".parse::<Target>().unwrap_err(), "invalid count 'many' in target '10.0.0.1;count=many'");
+        assert!("10.0.0.1;colour=red".parse::<Target>().unwrap_err().starts_with("unknown option 'colour'"));
+        assert!("10.0.0.1;label.=x".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn overrides_apply_to_the_options() {
+        let base = PingOptions { port: Some(8080), warmup: 2, ..Default::default() };
+        let target: Target = "10.0.0.1;count=5;probe=tcp".parse().unwrap();
+        let opts = target.options(&base);
+        // The run's port was for its own probe; warm-up probes come on top.
+        assert_eq!((opts.count, opts.probe, opts.port), (7, Probe::Tcp, None));
+        assert_eq!(Target::new("10.0.0.1").options(&base).port, Some(8080));
+    }
+
+    #[test]
+    fn urls_are_not_names() {
+        let target: Target = "https://example.com/?a=b".parse().unwrap();
+        assert_eq!((target.name, target.host.as_str()), (None, "https://example.com/?a=b"));
+    }
+
+    #[test]
+    fn validates_hosts() {
+        let targets = ["10.0.0.1", "fe80::1%eth0", "example.com.", "_sip.example.com", "-bad.example", "10.0.0.300", "a..b", "ex ample"];
+        let (valid, invalid) = validate_targets(targets.iter().map(|host| Target::new(*host)).collect(), &PingOptions::default());
+        assert_eq!(hosts(&valid), ["10.0.0.1", "fe80::1%eth0", "example.com.", "_sip.example.com"]);
+        let reasons: Vec<&str> = invalid.iter().map(|(_, reason)| reason.as_str()).collect();
+        assert_eq!(reasons, ["label '-bad' starts or ends with a hyphen", "not a valid IP address", "the name has an empty label", "' ' is not allowed in host names"]);
+    }
+
+    #[test]
+    fn dedupes_by_label() {
+        let targets: Vec<Target> = ["10.0.0.1", "10.0.0.1;count=3", "10.0.0.1;probe=tcp"].iter().map(|target| target.parse().unwrap()).collect();
+        let (kept, dropped) = dedupe_targets(targets);
+        assert_eq!(kept.iter().map(Target::label).collect::<Vec<_>>(), ["10.0.0.1", "10.0.0.1 over tcp:80"]);
+        assert_eq!(dropped[0].count, Some(3));
+    }
+
+    #[test]
+    fn copies_targets_per_interface_in_order() {
+        let targets = per_interface(vec![Target::new("a"), Target::new("b")], &["eth0".to_string(), "wlan0".to_string()]);
+        let labels: Vec<String> = targets.iter().map(Target::label).collect();
+        assert_eq!(labels, ["a via eth0", "a via wlan0", "b via eth0", "b via wlan0"]);
+    }
+
+    #[test]
+    fn labels_tell_how_targets_are_probed() {
+        assert_eq!(Target::new("10.0.0.1").label(), "10.0.0.1");
+        let target: Target = "gw=10.0.0.1;probe=tcp;interface=eth1".parse().unwrap();
+        assert_eq!(target.label(), "gw over tcp:80 via eth1");
+        let target = Target { address: Some("10.0.0.2".parse().unwrap()), port: Some(22), ..Target::new("db") };
+        assert_eq!(target.label(), "db (10.0.0.2) over port 22");
+    }
+}
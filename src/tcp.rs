@@ -0,0 +1,110 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use socket2::{SockRef, Socket};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use crate::PingOptions;
+use crate::event::{EventKind, PingEvent};
+use crate::report::{PingInfo, PingReport, RunClock};
+use crate::stats::{keep, packet_statistics, round_ms, round_trip_statistics};
+
+// Connects from the source address, through the interface, and with the
+// traffic class and TTL asked for, if any.
+pub(crate) async fn connect(address: SocketAddr, opts: &PingOptions) -> io::Result<TcpStream> {
+    if opts.source_ip.is_none() && opts.interface.is_none() && opts.dscp.is_none() && opts.ttl.is_none() {
+        return TcpStream::connect(address).await;
+    }
+    let socket = if address.is_ipv6() { TcpSocket::new_v6()? } else { TcpSocket::new_v4()? };
+    if let Some(source) = opts.source_ip {
+        socket.bind(SocketAddr::new(source, 0))?;
+    }
+    mark(SockRef::from(&socket), address.is_ipv6(), opts)?;
+    socket.connect(address).await
+}
+
+// Sends through the interface, and with the traffic class and TTL, asked
+// for, if any.
+pub(crate) fn mark(socket: SockRef, ipv6: bool, opts: &PingOptions) -> io::Result<()> {
+    if let Some(interface) = &opts.interface {
+        bind_device(&socket, interface)?;
+    }
+    if let Some(dscp) = opts.dscp {
+        if ipv6 {
+            set_tclass(&socket, dscp.tos() as u32)?;
+        } else {
+            socket.set_tos_v4(dscp.tos() as u32)?;
+        }
+    }
+    match opts.ttl {
+        Some(ttl) if ipv6 => socket.set_unicast_hops_v6(ttl),
+        Some(ttl) => socket.set_ttl_v4(ttl),
+        None => Ok(()),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd"))]
+fn set_tclass(socket: &Socket, tclass: u32) -> io::Result<()> {
+    socket.set_tclass_v6(tclass)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
+fn set_tclass(_socket: &Socket, _tclass: u32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "marking IPv6 probes with a DSCP is not supported here"))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(socket: &Socket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_device(_socket: &Socket, _interface: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "choosing an interface is only supported on Linux"))
+}
+
+/// Measures how long it takes to open a TCP connection to the port, once per
+/// probe. Refused and timed out connections count as lost.
+pub async fn execute_ping(target: String, address: IpAddr, opts: &PingOptions, clock: RunClock, sender: mpsc::Sender<PingEvent>) -> Result<PingReport, io::Error> {
+    let timeout = opts.reply_timeout();
+    let port = opts.port.unwrap_or(opts.probe.default_port());
+    let address = SocketAddr::new(address, port);
+    let _ = sender.send(PingEvent::new(&target, EventKind::Started { address: address.ip() })).await;
+    let mut pings = Vec::new();
+    let (mut seq, mut received) = (0, 0);
+    let mut next_probe = Instant::now();
+    while opts.more_probes(seq) {
+        seq += 1;
+        tokio::time::sleep_until(next_probe).await;
+        next_probe += opts.next_interval();
+        let started = Instant::now();
+        match tokio::time::timeout(timeout, connect(address, opts)).await {
+            Ok(Ok(_)) => {
+                let time = round_ms(started.elapsed().as_secs_f32() * 1000.0);
+                let mut ping = PingInfo {
+                    icmp_seq: seq,
+                    time,
+                    ..Default::default()
+                };
+                clock.stamp(&mut ping);
+                let _ = sender.send(PingEvent::new(&target, EventKind::Reply(ping.clone()))).await;
+                keep(&mut pings, ping, opts.unlimited());
+                received += 1;
+            },
+            // Refused connections count as lost, like timed out ones.
+            Ok(Err(_)) | Err(_) => {
+                let _ = sender.send(PingEvent::new(&target, EventKind::Lost { icmp_seq: seq })).await;
+            },
+        }
+    }
+    let packets = packet_statistics(seq, received);
+    let trips = round_trip_statistics(&pings);
+    Ok(PingReport {
+        destination: target,
+        pings,
+        packets: Some(packets),
+        trips,
+        timed_out: false,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,43 @@
+use std::io::{self, Write};
+use std::path::Path;
+use handlebars::Handlebars;
+use serde::Serialize;
+use crate::groups::GroupSummary;
+use crate::report::PingReport;
+use crate::run::RunInfo;
+
+#[derive(Serialize)]
+struct Context<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run: Option<&'a RunInfo>,
+    reports: &'a [PingReport],
+    groups: &'a [GroupSummary],
+}
+
+/// Renders the Handlebars template at `path` to `writer`. The template sees
+/// the reports as `reports`, with the fields JSON reports have, and the
+/// group summaries as `groups`, and the details of the `run`, when given, as
+/// `run`:
+///
+/// ```handlebars
+/// {{#each reports}}{{destination}}: {{packets.loss_percent}}% loss, {{trips.avg}} ms
+/// {{/each}}
+/// ```
+///
+/// Values are escaped for HTML only in templates named `.html` or `.htm`.
+pub fn render<W: Write>(path: &Path, reports: &[PingReport], groups: &[GroupSummary], run: Option<&RunInfo>, writer: &mut W) -> io::Result<()> {
+    let source = std::fs::read_to_string(path).map_err(|err| io::Error::new(err.kind(), format!("cannot read {}: {}", path.display(), err)))?;
+    let mut registry = Handlebars::new();
+    let html = path.extension().is_some_and(|extension| extension == "html" || extension == "htm");
+    if !html {
+        registry.register_escape_fn(handlebars::no_escape);
+    }
+    // Through text, as times turned straight into JSON values would show
+    // their f32 rounding error, e.g. 0.296999990940094 for 0.297.
+    let context = serde_json::to_string(&Context { run, reports, groups })?;
+    let context: serde_json::Value = serde_json::from_str(&context)?;
+    let text = registry
+        .render_template(&source, &context)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", path.display(), err)))?;
+    writer.write_all(text.as_bytes())
+}
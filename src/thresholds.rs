@@ -0,0 +1,41 @@
+use serde::Deserialize;
+use crate::report::PingReport;
+
+/// Limits a target has to stay within to be considered healthy.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds {
+    /// Highest acceptable packet loss, in percent.
+    pub max_loss: Option<f32>,
+    /// Highest acceptable average round trip time, in milliseconds.
+    pub max_avg: Option<f32>,
+}
+
+impl Thresholds {
+    /// Describes every limit `report` exceeds; empty when it is within all of them.
+    /// A target without any reply exceeds the latency limit, as it has no
+    /// latency to speak of.
+    pub fn violations(&self, report: &PingReport) -> Vec<String> {
+        let mut violations = Vec::new();
+        if let Some(max_loss) = self.max_loss {
+            let loss = report.packets.as_ref().map_or(100.0, |packets| packets.loss_percent);
+            if loss > max_loss {
+                violations.push(format!("loss {}% exceeds {}%", loss, max_loss));
+            }
+        }
+        if let Some(max_avg) = self.max_avg {
+            match &report.trips {
+                Some(trips) if trips.avg > max_avg => {
+                    violations.push(format!("average {} ms exceeds {} ms", trips.avg, max_avg));
+                },
+                Some(_) => (),
+                None => violations.push("no round trip times to compare".to_string()),
+            }
+        }
+        violations
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.max_loss.is_some() || self.max_avg.is_some()
+    }
+}
@@ -0,0 +1,130 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
+use crate::PingOptions;
+use crate::event::{EventKind, PingEvent};
+use crate::http::tls_connector;
+use crate::report::{unix_nanos, PingInfo, PingReport, RunClock};
+use crate::stats::{keep, packet_statistics, round_ms, round_trip_statistics};
+use crate::tcp::connect;
+
+// Splits a DER element into its tag, its contents, and what follows it.
+fn der(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        // Long form: the low bits count the length bytes that follow.
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0, |len, &byte| len << 8 | byte as usize);
+        (len, &rest[count..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+// When a DER certificate expires, in seconds since the Unix epoch: the end
+// of its validity, which follows the serial number, the signature
+// algorithm, and the issuer.
+fn not_after(certificate: &[u8]) -> Option<i64> {
+    let (_, certificate, _) = der(certificate)?;
+    let (_, mut fields, _) = der(certificate)?;
+    // The version is optional, and tagged [0] when there.
+    if fields.first() == Some(&0xa0) {
+        fields = der(fields)?.2;
+    }
+    for _ in 0..3 {
+        fields = der(fields)?.2;
+    }
+    let (_, validity, _) = der(fields)?;
+    let (_, _, rest) = der(validity)?;
+    let (tag, time, _) = der(rest)?;
+    let time = std::str::from_utf8(time).ok()?;
+    // UTCTime has two digit years, from 1950 to 2049; GeneralizedTime has four.
+    let time = match tag {
+        0x17 => format!("{}{}", if time.get(..2)? < "50" { "20" } else { "19" }, time),
+        0x18 => time.to_string(),
+        _ => return None,
+    };
+    let field = |range: std::ops::Range<usize>| time.get(range);
+    let timestamp = format!(
+        "{}-{}-{}T{}:{}:{}",
+        field(0..4)?, field(4..6)?, field(6..8)?, field(8..10)?, field(10..12)?, field(12..14)?
+    );
+    Some((unix_nanos(&timestamp)? / 1_000_000_000) as i64)
+}
+
+// Connects and completes a TLS handshake, returning when the server's
+// certificate expires, if it could be read.
+async fn handshake(name: &ServerName<'static>, address: SocketAddr, opts: &PingOptions, connector: &TlsConnector) -> io::Result<Option<i64>> {
+    let stream = connector.connect(name.clone(), connect(address, opts).await?).await?;
+    let (_, connection) = stream.get_ref();
+    Ok(connection.peer_certificates().and_then(|chain| not_after(chain.first()?)))
+}
+
+/// Opens a TCP connection and completes a TLS handshake per probe, and
+/// measures how long both take together. The server's certificate has to
+/// be valid for `host`; probes whose handshake fails count as lost. Reports
+/// how many days are left until the certificate expires.
+pub(crate) async fn execute_ping(host: &str, target: String, address: IpAddr, opts: &PingOptions, clock: RunClock, sender: mpsc::Sender<PingEvent>) -> Result<PingReport, io::Error> {
+    let timeout = opts.reply_timeout();
+    let port = opts.port.unwrap_or(opts.probe.default_port());
+    let address = SocketAddr::new(address, port);
+    let name = ServerName::try_from(host.to_string()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", target, err)))?;
+    let connector = tls_connector();
+    let _ = sender.send(PingEvent::new(&target, EventKind::Started { address: address.ip() })).await;
+    let mut pings = Vec::new();
+    let (mut seq, mut received) = (0, 0);
+    let mut expires = None;
+    let mut next_probe = Instant::now();
+    while opts.more_probes(seq) {
+        seq += 1;
+        tokio::time::sleep_until(next_probe).await;
+        next_probe += opts.next_interval();
+        let started = Instant::now();
+        match tokio::time::timeout(timeout, handshake(&name, address, opts, &connector)).await {
+            Ok(Ok(not_after)) => {
+                let time = round_ms(started.elapsed().as_secs_f32() * 1000.0);
+                let mut ping = PingInfo {
+                    icmp_seq: seq,
+                    time,
+                    ..Default::default()
+                };
+                clock.stamp(&mut ping);
+                let _ = sender.send(PingEvent::new(&target, EventKind::Reply(ping.clone()))).await;
+                keep(&mut pings, ping, opts.unlimited());
+                received += 1;
+                expires = not_after.or(expires);
+            },
+            Ok(Err(err)) => {
+                tracing::debug!(%target, error = %err, "TLS handshake failed");
+                let _ = sender.send(PingEvent::new(&target, EventKind::Lost { icmp_seq: seq })).await;
+            },
+            Err(_) => {
+                let _ = sender.send(PingEvent::new(&target, EventKind::Lost { icmp_seq: seq })).await;
+            },
+        }
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let packets = packet_statistics(seq, received);
+    let trips = round_trip_statistics(&pings);
+    Ok(PingReport {
+        destination: target,
+        cert_expiry_days: expires.map(|expires| (expires - now).div_euclid(86400)),
+        pings,
+        packets: Some(packets),
+        trips,
+        timed_out: false,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,95 @@
+use std::io;
+use std::net::IpAddr;
+use std::process::Stdio;
+use std::time::Duration;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::process::Command;
+use crate::error::MultipingError;
+use crate::parser::parse_traceroute_line;
+use crate::report::Hop;
+use crate::stats::round_ms;
+
+/// Probes mtr sends to every hop; traceroute sends its usual three.
+pub const TRACE_CYCLES: u32 = 10;
+
+#[derive(Deserialize)]
+struct MtrOutput {
+    report: MtrReport,
+}
+
+#[derive(Deserialize)]
+struct MtrReport {
+    hubs: Vec<MtrHub>,
+}
+
+// Older mtr versions write the hop number as a string.
+#[derive(Deserialize)]
+struct MtrHub {
+    count: Value,
+    host: String,
+    #[serde(rename = "Loss%")]
+    loss: f32,
+    #[serde(rename = "Snt")]
+    sent: u32,
+    #[serde(rename = "Avg")]
+    avg: f32,
+    #[serde(rename = "Best")]
+    best: f32,
+    #[serde(rename = "Wrst")]
+    worst: f32,
+}
+
+impl From<MtrHub> for Hop {
+    fn from(hub: MtrHub) -> Hop {
+        let answered = hub.host != "???";
+        Hop {
+            hop: hub.count.as_u64().or_else(|| hub.count.as_str()?.parse().ok()).unwrap_or(0) as u32,
+            host: answered.then_some(hub.host),
+            sent: hub.sent,
+            loss_percent: round_ms(hub.loss),
+            avg: answered.then_some(hub.avg),
+            best: answered.then_some(hub.best),
+            worst: answered.then_some(hub.worst),
+        }
+    }
+}
+
+async fn run(command: &mut Command, deadline: Duration) -> io::Result<String> {
+    command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null()).kill_on_drop(true).env("LC_ALL", "C");
+    let output = tokio::time::timeout(deadline, command.output())
+        .await
+        .map_err(|_| MultipingError::Timeout("the trace took too long".to_string()))??;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn mtr(address: IpAddr, timeout: u32) -> io::Result<Vec<Hop>> {
+    let mut command = Command::new("mtr");
+    command.args(["--json", "--no-dns", "--report-cycles"]).arg(TRACE_CYCLES.to_string());
+    command.arg(address.to_string());
+    let output = run(&mut command, Duration::from_secs((TRACE_CYCLES + timeout) as u64 * 2)).await?;
+    let output: MtrOutput = serde_json::from_str(&output)
+        .map_err(|err| MultipingError::ParseFailure(format!("cannot read the mtr report ({})", err)))?;
+    Ok(output.report.hubs.into_iter().map(Hop::from).collect())
+}
+
+async fn traceroute(address: IpAddr, timeout: u32) -> io::Result<Vec<Hop>> {
+    let mut command = Command::new("traceroute");
+    command.arg("-n").arg("-w").arg(timeout.to_string()).arg(address.to_string());
+    // traceroute probes several hops at once, so this leaves it plenty of time.
+    let output = run(&mut command, Duration::from_secs(30 * timeout as u64)).await?;
+    Ok(output.lines().filter_map(parse_traceroute_line).collect())
+}
+
+/// Traces the path to `address` with mtr, or with traceroute where mtr is
+/// not installed. `timeout` is how many seconds traceroute waits for a probe.
+pub async fn trace(address: IpAddr, timeout: u32) -> io::Result<Vec<Hop>> {
+    match mtr(address, timeout).await {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        result => return result,
+    }
+    traceroute(address, timeout).await.map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => MultipingError::SpawnFailed("neither mtr nor traceroute is installed".to_string()).into(),
+        _ => err,
+    })
+}
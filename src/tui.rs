@@ -0,0 +1,207 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::time::{Duration, Instant};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::Constraint;
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::mpsc;
+use crate::rolling::{Rolling, WINDOWS};
+use crate::stats::{ewma, trend};
+use crate::{EventKind, PingEvent, PingOptions, Target};
+
+const HISTORY_LEN: usize = 30;
+const TICK: Duration = Duration::from_millis(200);
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Default)]
+struct TargetState {
+    last: Option<f32>,
+    received: u32,
+    sent: u32,
+    count: u32,
+    jitter: f32,
+    ewma: Option<f32>,
+    /// Sum of the round trip times, for their average.
+    total: f32,
+    replies: u32,
+    history: VecDeque<f32>,
+    rolling: Rolling,
+    done: bool,
+}
+
+impl TargetState {
+    fn record(&mut self, seq: u32, time: f32) {
+        // RFC 3550 interarrival jitter estimate.
+        if let Some(last) = self.last {
+            self.jitter += ((time - last).abs() - self.jitter) / 16.0;
+        }
+        self.last = Some(time);
+        self.ewma = Some(ewma(self.ewma, time));
+        self.total += time;
+        self.replies += 1;
+        self.received += 1;
+        self.sent = self.sent.max(seq);
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(time);
+        self.rolling.record(Instant::now(), 1, 1, &[time]);
+    }
+
+    fn lose(&mut self, seq: u32) {
+        self.sent = self.sent.max(seq);
+        self.rolling.record(Instant::now(), 1, 0, &[]);
+    }
+
+    // The moving average, with an arrow for where it is heading.
+    fn trend(&self) -> String {
+        match self.ewma {
+            Some(average) => format!("{:.3} ms {}", average, trend(average, self.total / self.replies as f32).arrow()),
+            None => "-".to_string(),
+        }
+    }
+
+    // Loss and average round trip time over a window, as in `0.0% 12.3`.
+    fn window(&self, now: Instant, window: Duration) -> String {
+        match self.rolling.stats(now, window) {
+            Some(stats) => match stats.avg {
+                Some(avg) => format!("{:.1}% {:.1}", stats.loss_percent, avg),
+                None => format!("{:.1}% -", stats.loss_percent),
+            },
+            None => "-".to_string(),
+        }
+    }
+
+    fn loss_percent(&self) -> f32 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        self.sent.saturating_sub(self.received) as f32 / self.sent as f32 * 100.0
+    }
+
+    fn sparkline(&self) -> String {
+        let max = self.history.iter().cloned().fold(0.0, f32::max);
+        self.history
+            .iter()
+            .map(|time| {
+                let level = if max > 0.0 { (time / max * 7.0).round() as usize } else { 0 };
+                SPARK_LEVELS[level.min(7)]
+            })
+            .collect()
+    }
+}
+
+struct Dashboard {
+    targets: Vec<String>,
+    states: HashMap<String, TargetState>,
+}
+
+impl Dashboard {
+    fn update(&mut self, event: &PingEvent) {
+        let Some(state) = self.states.get_mut(&event.target) else {
+            return;
+        };
+        match &event.kind {
+            EventKind::Reply(ping) if ping.duplicate => (),
+            EventKind::Reply(ping) => state.record(ping.icmp_seq, ping.time),
+            EventKind::Lost { icmp_seq } => state.lose(*icmp_seq),
+            EventKind::IcmpError(error) => state.lose(error.icmp_seq.unwrap_or(0)),
+            EventKind::Finished(report) => {
+                if let Some(packets) = &report.packets {
+                    state.sent = packets.transmitted;
+                    state.received = packets.received;
+                }
+                state.done = true;
+            },
+            EventKind::Failed(_) => state.done = true,
+            EventKind::Started { .. } | EventKind::Statistics(_) | EventKind::PathChanged { .. } => (),
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let mut titles = vec!["Target", "Progress", "Last", "Loss", "Jitter", "EWMA"];
+        titles.extend(WINDOWS.iter().map(|(name, _)| *name));
+        titles.push("History");
+        let header = Row::new(titles).style(Style::default().add_modifier(Modifier::BOLD));
+        let now = Instant::now();
+        let rows = self.targets.iter().map(|target| {
+            let state = &self.states[target];
+            let progress = if state.done {
+                "done".to_string()
+            } else if state.count == 0 {
+                state.sent.to_string()
+            } else {
+                format!("{}/{}", state.sent, state.count)
+            };
+            let last = state.last.map_or("-".to_string(), |time| format!("{:.3} ms", time));
+            let mut cells = vec![
+                target.clone(),
+                progress,
+                last,
+                format!("{:.1}%", state.loss_percent()),
+                format!("{:.3} ms", state.jitter),
+                state.trend(),
+            ];
+            cells.extend(WINDOWS.iter().map(|(_, window)| state.window(now, *window)));
+            cells.push(state.sparkline());
+            Row::new(cells)
+        });
+        let mut widths = vec![
+            Constraint::Fill(2),
+            Constraint::Length(9),
+            Constraint::Length(12),
+            Constraint::Length(7),
+            Constraint::Length(12),
+            Constraint::Length(14),
+        ];
+        widths.extend(WINDOWS.iter().map(|_| Constraint::Length(14)));
+        widths.push(Constraint::Length(HISTORY_LEN as u16));
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(" multiping (q to quit) "));
+        frame.render_widget(table, frame.area());
+    }
+}
+
+/// Shows a live table of the targets, fed by the events sent by
+/// `PingRunner::run_with_sender`, until every target finishes.
+/// `opts` are the options the targets are pinged with.
+/// Returns `false` if the user quit before that.
+pub async fn run_dashboard(targets: &[Target], opts: &PingOptions, receiver: mpsc::Receiver<PingEvent>) -> io::Result<bool> {
+    let states = targets
+        .iter()
+        .map(|target| {
+            let state = TargetState { count: target.options(opts).count, ..Default::default() };
+            (target.label(), state)
+        })
+        .collect();
+    let targets = targets.iter().map(|target| target.label()).collect();
+    let mut terminal = ratatui::try_init()?;
+    let result = dashboard_loop(&mut terminal, Dashboard { targets, states }, receiver).await;
+    ratatui::try_restore()?;
+    result
+}
+
+async fn dashboard_loop(terminal: &mut DefaultTerminal, mut dashboard: Dashboard, mut receiver: mpsc::Receiver<PingEvent>) -> io::Result<bool> {
+    let mut ticker = tokio::time::interval(TICK);
+    loop {
+        tokio::select! {
+            message = receiver.recv() => match message {
+                Some(message) => dashboard.update(&message),
+                None => return Ok(true),
+            },
+            _ = ticker.tick() => {
+                terminal.draw(|frame| dashboard.draw(frame))?;
+                while event::poll(Duration::ZERO)? {
+                    if let Event::Key(key) = event::read()? {
+                        if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                            return Ok(false);
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
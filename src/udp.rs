@@ -0,0 +1,114 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use crate::PingOptions;
+use crate::event::{EventKind, PingEvent};
+use crate::report::{PingError, PingErrorKind, PingInfo, PingReport, RunClock};
+use crate::stats::{keep, packet_statistics, round_ms, round_trip_statistics};
+use crate::tcp::mark;
+
+// The first port traceroute sends to, which nothing usually listens on.
+pub(crate) const DEFAULT_PORT: u16 = 33434;
+
+// A socket for one probe, sent from where the options ask. Each probe gets
+// its own, so a late answer to one is never taken for the next's.
+pub(crate) fn open(address: SocketAddr, opts: &PingOptions) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(address), Type::DGRAM, Some(Protocol::UDP))?;
+    let any = if address.is_ipv6() { IpAddr::V6(Ipv6Addr::UNSPECIFIED) } else { IpAddr::V4(Ipv4Addr::UNSPECIFIED) };
+    socket.bind(&SocketAddr::new(opts.source_ip.unwrap_or(any), 0).into())?;
+    mark(SockRef::from(&socket), address.is_ipv6(), opts)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+// What came back for a probe: the system reports ICMP errors to connected
+// UDP sockets as errors on the next receive.
+enum Answer {
+    Reply,
+    Unreachable(&'static str),
+    Lost,
+}
+
+async fn probe(address: SocketAddr, payload: &[u8], opts: &PingOptions) -> io::Result<Answer> {
+    let socket = open(address, opts)?;
+    let mut buf = [0u8; 1500];
+    let exchange = async {
+        socket.connect(address).await?;
+        socket.send(payload).await?;
+        socket.recv(&mut buf).await
+    };
+    match tokio::time::timeout(opts.reply_timeout(), exchange).await {
+        // Port unreachable: the host is there, with nothing listening on the port.
+        Ok(Err(err)) if err.kind() == io::ErrorKind::ConnectionRefused => Ok(Answer::Reply),
+        Ok(Err(err)) if err.kind() == io::ErrorKind::HostUnreachable => Ok(Answer::Unreachable("Destination Host Unreachable")),
+        Ok(Err(err)) if err.kind() == io::ErrorKind::NetworkUnreachable => Ok(Answer::Unreachable("Destination Net Unreachable")),
+        Ok(Err(_)) => Ok(Answer::Lost),
+        // Something listens, and answered.
+        Ok(Ok(_)) => Ok(Answer::Reply),
+        Err(_) => Ok(Answer::Lost),
+    }
+}
+
+/// Sends a UDP datagram of `size` bytes per probe to the port, and measures
+/// how long it takes until the host answers, usually with an ICMP port
+/// unreachable error, as traceroute relies on. Reaches hosts whose firewall
+/// drops ICMP echo requests but not UDP.
+pub async fn execute_ping(target: String, address: IpAddr, opts: &PingOptions, clock: RunClock, sender: mpsc::Sender<PingEvent>) -> Result<PingReport, io::Error> {
+    let port = opts.port.unwrap_or(opts.probe.default_port());
+    let address = SocketAddr::new(address, port);
+    let payload = vec![0u8; opts.size];
+    let _ = sender.send(PingEvent::new(&target, EventKind::Started { address: address.ip() })).await;
+    let mut pings = Vec::new();
+    let mut errors = Vec::new();
+    let (mut seq, mut received) = (0, 0);
+    let mut next_probe = Instant::now();
+    while opts.more_probes(seq) {
+        seq += 1;
+        tokio::time::sleep_until(next_probe).await;
+        next_probe += opts.next_interval();
+        let started = Instant::now();
+        match probe(address, &payload, opts).await? {
+            Answer::Reply => {
+                let time = round_ms(started.elapsed().as_secs_f32() * 1000.0);
+                let mut ping = PingInfo {
+                    bytes_sent: opts.size as u32,
+                    icmp_seq: seq,
+                    time,
+                    ..Default::default()
+                };
+                clock.stamp(&mut ping);
+                let _ = sender.send(PingEvent::new(&target, EventKind::Reply(ping.clone()))).await;
+                keep(&mut pings, ping, opts.unlimited());
+                received += 1;
+            },
+            // Routers do not tell which of them gave up; the error is put down to the target.
+            Answer::Unreachable(message) => {
+                let error = PingError {
+                    icmp_seq: Some(seq),
+                    from: address.ip().to_string(),
+                    kind: PingErrorKind::Unreachable,
+                    message: message.to_string(),
+                };
+                let _ = sender.send(PingEvent::new(&target, EventKind::IcmpError(error.clone()))).await;
+                keep(&mut errors, error, opts.unlimited());
+            },
+            Answer::Lost => {
+                let _ = sender.send(PingEvent::new(&target, EventKind::Lost { icmp_seq: seq })).await;
+            },
+        }
+    }
+    let packets = packet_statistics(seq, received);
+    let trips = round_trip_statistics(&pings);
+    Ok(PingReport {
+        destination: target,
+        pings,
+        errors,
+        packets: Some(packets),
+        trips,
+        timed_out: false,
+        ..Default::default()
+    })
+}
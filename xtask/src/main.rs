@@ -0,0 +1,33 @@
+//! Build tasks that are not part of multiping itself, run with `cargo xtask`.
+
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::CommandFactory;
+use multiping::cli::Cli;
+
+fn main() -> ExitCode {
+    let result = match std::env::args().nth(1).as_deref() {
+        Some("man") => man(),
+        _ => {
+            eprintln!("usage: cargo xtask man");
+            return ExitCode::FAILURE;
+        },
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("xtask: {}", err);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+// Writes multiping.1, and a page for every subcommand, into target/man.
+fn man() -> io::Result<()> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../target/man");
+    std::fs::create_dir_all(&dir)?;
+    clap_mangen::generate_to(Cli::command(), &dir)?;
+    println!("{}", dir.canonicalize()?.display());
+    Ok(())
+}